@@ -0,0 +1,24 @@
+use crate::eval::{self, Context, Expr};
+
+/// A breakpoint condition such as `i == 5` or `node->next`: any C expression, which is true when
+/// it evaluates to something non-zero.
+#[derive(Clone)]
+pub struct Condition {
+    pub text: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Condition, String> {
+        let text = text.trim();
+        Ok(Condition {
+            text: text.to_string(),
+            expr: eval::parse(text)?,
+        })
+    }
+
+    /// Evaluates the condition against the stopped inferior.
+    pub fn evaluate(&self, context: &mut dyn Context) -> Result<bool, String> {
+        eval::evaluate(context, &self.expr)?.is_true()
+    }
+}
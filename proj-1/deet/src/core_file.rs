@@ -0,0 +1,660 @@
+use crate::inferior::{get_fp_registers, Inferior};
+use crate::target::{auxv_entry, MemoryMap, Target};
+use libc::{user_fpregs_struct, user_regs_struct};
+use nix::errno::Errno;
+use nix::sys::ptrace;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem::size_of;
+use std::os::unix::fs::FileExt;
+
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const PAGE_SIZE: usize = 4096;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+const NT_FPREGSET: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x4649_4c45;
+
+/// Sizes of and offsets into the x86-64 `struct elf_prstatus` and `struct elf_prpsinfo` notes.
+const PRSTATUS_SIZE: usize = 336;
+const PRSTATUS_CURSIG: usize = 12;
+const PRSTATUS_PID: usize = 32;
+const PRSTATUS_REG: usize = 112;
+const PRPSINFO_SIZE: usize = 136;
+const PRPSINFO_SNAME: usize = 1;
+const PRPSINFO_PID: usize = 24;
+const PRPSINFO_FNAME: usize = 40;
+const FNAME_LEN: usize = 16;
+const PRPSINFO_PSARGS: usize = 56;
+const PSARGS_LEN: usize = 80;
+
+/// How much memory `write_core` copies at a time.
+const COPY_CHUNK: usize = 1 << 20;
+
+/// A PT_LOAD segment: memory at `vaddr`, of which the first `filesz` bytes were dumped at
+/// `offset` in the core. The kernel leaves out the rest, e.g. unmodified code.
+struct Segment {
+    vaddr: usize,
+    memsz: usize,
+    offset: usize,
+    filesz: usize,
+}
+
+/// A thread as it was when the process died, from its NT_PRSTATUS and NT_FPREGSET notes.
+struct CoreThread {
+    tid: Pid,
+    registers: user_regs_struct,
+    fp_registers: Option<user_fpregs_struct>,
+}
+
+/// An ELF core dump, standing in for the process it was taken from. The first thread is the
+/// one that got the fatal signal.
+pub struct CoreFile {
+    data: Vec<u8>,
+    segments: Vec<Segment>,
+    threads: Vec<CoreThread>,
+    /// Index into `threads` of the one being looked at, as chosen with `thread`.
+    current: usize,
+    /// Mapped files from the NT_FILE note, which also supply the memory the kernel didn't dump.
+    files: Vec<MemoryMap>,
+    auxv: Vec<u8>,
+    /// The command line the process was started with.
+    pub command: String,
+    /// The signal that killed it.
+    pub signal: Option<Signal>,
+}
+
+/// The `len` bytes at `offset`, unless they run past the end of `data`, or of the address
+/// space, as offsets from a corrupt file may.
+fn field(data: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    data.get(offset..offset.checked_add(len)?)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(field(data, offset, 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(field(data, offset, 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(field(data, offset, 8)?.try_into().ok()?) as usize)
+}
+
+/// Reinterprets the start of `bytes` as a register struct, the layout the kernel dumps.
+fn read_struct<T: Copy>(bytes: &[u8]) -> Option<T> {
+    if bytes.len() < size_of::<T>() {
+        return None;
+    }
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+impl CoreFile {
+    pub fn open(path: &str) -> Result<CoreFile, std::io::Error> {
+        let data = std::fs::read(path)?;
+        if data.get(..4) != Some(b"\x7fELF") || data.get(4) != Some(&2) {
+            return Err(invalid("not a 64-bit ELF file"));
+        }
+        if read_u16(&data, 0x10) != Some(ET_CORE) {
+            return Err(invalid("not a core dump"));
+        }
+        let truncated = || invalid("truncated ELF header");
+        let phoff = read_u64(&data, 0x20).ok_or_else(truncated)?;
+        let phentsize = read_u16(&data, 0x36).ok_or_else(truncated)? as usize;
+        let phnum = read_u16(&data, 0x38).ok_or_else(truncated)? as usize;
+        let mut core = CoreFile {
+            data: Vec::new(),
+            segments: Vec::new(),
+            threads: Vec::new(),
+            current: 0,
+            files: Vec::new(),
+            auxv: Vec::new(),
+            command: String::new(),
+            signal: None,
+        };
+        for index in 0..phnum {
+            let header = index
+                .checked_mul(phentsize)
+                .and_then(|start| start.checked_add(phoff))
+                .ok_or_else(truncated)?;
+            let field_at = |offset| {
+                header
+                    .checked_add(offset)
+                    .and_then(|offset| read_u64(&data, offset))
+                    .ok_or_else(truncated)
+            };
+            let offset = field_at(8)?;
+            let filesz = field_at(32)?;
+            match read_u32(&data, header) {
+                Some(PT_LOAD) => {
+                    let segment = Segment {
+                        vaddr: field_at(16)?,
+                        memsz: field_at(40)?,
+                        offset,
+                        filesz,
+                    };
+                    // the rest of the module can then add these up without overflowing
+                    if segment.vaddr.checked_add(segment.memsz).is_none()
+                        || segment.offset.checked_add(segment.filesz).is_none()
+                    {
+                        return Err(invalid("segment past the end of the address space"));
+                    }
+                    core.segments.push(segment);
+                }
+                Some(PT_NOTE) => {
+                    let notes = field(&data, offset, filesz)
+                        .ok_or_else(|| invalid("note segment past the end of the file"))?;
+                    core.read_notes(notes);
+                }
+                _ => {}
+            }
+        }
+        if core.threads.is_empty() {
+            return Err(invalid("no NT_PRSTATUS note"));
+        }
+        core.data = data;
+        Ok(core)
+    }
+
+    /// Walks a PT_NOTE segment: each note is a header of name size, descriptor size and type,
+    /// followed by the name and the descriptor, both padded to 4 bytes.
+    fn read_notes(&mut self, notes: &[u8]) {
+        let align = |size: usize| (size + 3) & !3;
+        let mut offset = 0;
+        while let (Some(namesz), Some(descsz), Some(note_type)) = (
+            read_u32(notes, offset),
+            read_u32(notes, offset + 4),
+            read_u32(notes, offset + 8),
+        ) {
+            let start = offset + 12 + align(namesz as usize);
+            let desc = match field(notes, start, descsz as usize) {
+                Some(desc) => desc,
+                None => return,
+            };
+            match note_type {
+                NT_PRSTATUS => self.read_prstatus(desc),
+                NT_FPREGSET => {
+                    // it belongs to the thread of the NT_PRSTATUS before it
+                    if let Some(thread) = self.threads.last_mut() {
+                        thread.fp_registers = read_struct(desc);
+                    }
+                }
+                NT_PRPSINFO => {
+                    let psargs = desc
+                        .get(PRPSINFO_PSARGS..PRPSINFO_PSARGS + PSARGS_LEN)
+                        .unwrap_or_default();
+                    let end = psargs.iter().position(|byte| *byte == 0);
+                    let psargs = &psargs[..end.unwrap_or(psargs.len())];
+                    self.command = String::from_utf8_lossy(psargs).trim_end().to_string();
+                }
+                NT_AUXV => self.auxv = desc.to_vec(),
+                NT_FILE => self.read_file_note(desc),
+                _ => {}
+            }
+            offset = start + align(descsz as usize);
+        }
+    }
+
+    fn read_prstatus(&mut self, desc: &[u8]) {
+        let registers = match desc.get(PRSTATUS_REG..).and_then(read_struct) {
+            Some(registers) => registers,
+            None => return,
+        };
+        if self.threads.is_empty() {
+            let signal = read_u16(desc, PRSTATUS_CURSIG).unwrap_or(0);
+            self.signal = Signal::try_from(signal as i32).ok();
+        }
+        let tid = read_u32(desc, PRSTATUS_PID).unwrap_or(0);
+        self.threads.push(CoreThread {
+            tid: Pid::from_raw(tid as i32),
+            registers,
+            fp_registers: None,
+        });
+    }
+
+    /// NT_FILE is a count and page size, then (start, end, page offset) for each mapping, then
+    /// their paths, NUL-separated.
+    fn read_file_note(&mut self, desc: &[u8]) {
+        let count = read_u64(desc, 0).unwrap_or(0);
+        let page_size = read_u64(desc, 8).unwrap_or(0);
+        let names_start = match count.checked_mul(24).and_then(|size| size.checked_add(16)) {
+            Some(names_start) => names_start,
+            None => return,
+        };
+        let names = desc
+            .get(names_start..)
+            .unwrap_or_default()
+            .split(|byte| *byte == 0);
+        for (index, name) in (0..count).zip(names) {
+            let entry = 16 + index * 24;
+            if let (Some(start), Some(end), Some(offset)) = (
+                read_u64(desc, entry),
+                read_u64(desc, entry + 8),
+                read_u64(desc, entry + 16).and_then(|page| page.checked_mul(page_size)),
+            ) {
+                self.files.push(MemoryMap {
+                    start,
+                    end,
+                    perms: String::new(),
+                    offset,
+                    path: String::from_utf8_lossy(name).into_owned(),
+                });
+            }
+        }
+    }
+
+    /// The number, tid and registers of each thread, numbered from 1 like a live process's.
+    pub fn threads(&self) -> Vec<(usize, Pid, user_regs_struct)> {
+        self.threads
+            .iter()
+            .enumerate()
+            .map(|(index, thread)| (index + 1, thread.tid, thread.registers))
+            .collect()
+    }
+
+    /// The number and tid of the thread being looked at.
+    pub fn current_thread(&self) -> (usize, Pid) {
+        (self.current + 1, self.threads[self.current].tid)
+    }
+
+    /// Looks at thread `num` from now on. Returns its tid, or None if there is no such thread.
+    pub fn select_thread(&mut self, num: usize) -> Option<Pid> {
+        let thread = self.threads.get(num.checked_sub(1)?)?;
+        self.current = num - 1;
+        Some(thread.tid)
+    }
+
+    /// Reads the part of `addr..addr + len` that one segment or mapped file has, which is
+    /// at least a byte unless the address isn't in the core at all.
+    fn read_chunk(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| segment.vaddr <= addr && addr < segment.vaddr + segment.memsz)?;
+        let offset = addr - segment.vaddr;
+        if offset < segment.filesz {
+            let len = len.min(segment.filesz - offset);
+            let start = segment.offset + offset;
+            return field(&self.data, start, len).map(|bytes| bytes.to_vec());
+        }
+        // not dumped, so it should still be what the mapped file has
+        let map = self
+            .files
+            .iter()
+            .find(|map| map.start <= addr && addr < map.end)?;
+        let len = len
+            .min(map.end - addr)
+            .min(segment.vaddr + segment.memsz - addr);
+        let mut bytes = vec![0; len];
+        let file = File::open(&map.path).ok()?;
+        let file_offset = map.offset.checked_add(addr - map.start)?;
+        let read = file.read_at(&mut bytes, file_offset as u64).ok()?;
+        if read == 0 {
+            return None;
+        }
+        bytes.truncate(read);
+        Some(bytes)
+    }
+}
+
+impl Target for CoreFile {
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let next = addr
+                .checked_add(bytes.len())
+                .ok_or(nix::Error::Sys(Errno::EIO))?;
+            match self.read_chunk(next, len - bytes.len()) {
+                Some(chunk) => bytes.extend(chunk),
+                None => return Err(nix::Error::Sys(Errno::EIO)),
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn get_registers(&self) -> Result<user_regs_struct, nix::Error> {
+        Ok(self.threads[self.current].registers)
+    }
+
+    fn get_fp_registers(&self) -> Result<user_fpregs_struct, nix::Error> {
+        self.threads[self.current]
+            .fp_registers
+            .ok_or(nix::Error::Sys(Errno::ENODATA))
+    }
+
+    fn entry_point(&self) -> Result<usize, std::io::Error> {
+        auxv_entry(&self.auxv)
+    }
+
+    fn memory_maps(&self) -> Result<Vec<MemoryMap>, std::io::Error> {
+        Ok(self.files.clone())
+    }
+}
+
+/// The raw bytes of a register struct, laid out the way notes hold them.
+fn struct_bytes<T>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+fn put(buffer: &mut [u8], offset: usize, field: &[u8]) {
+    buffer[offset..offset + field.len()].copy_from_slice(field);
+}
+
+fn elf_header(phnum: usize) -> Vec<u8> {
+    let mut header = vec![0; ELF_HEADER_SIZE];
+    // 64-bit, little endian, version 1, System V ABI
+    put(&mut header, 0, b"\x7fELF\x02\x01\x01\x00");
+    put(&mut header, 0x10, &ET_CORE.to_le_bytes());
+    put(&mut header, 0x12, &EM_X86_64.to_le_bytes());
+    put(&mut header, 0x14, &1u32.to_le_bytes());
+    put(&mut header, 0x20, &(ELF_HEADER_SIZE as u64).to_le_bytes());
+    put(&mut header, 0x34, &(ELF_HEADER_SIZE as u16).to_le_bytes());
+    put(
+        &mut header,
+        0x36,
+        &(PROGRAM_HEADER_SIZE as u16).to_le_bytes(),
+    );
+    put(&mut header, 0x38, &(phnum as u16).to_le_bytes());
+    header
+}
+
+fn program_header(p_type: u32, flags: u32, segment: &Segment) -> Vec<u8> {
+    let align = if p_type == PT_LOAD { PAGE_SIZE } else { 1 };
+    let mut header = vec![0; PROGRAM_HEADER_SIZE];
+    put(&mut header, 0, &p_type.to_le_bytes());
+    put(&mut header, 4, &flags.to_le_bytes());
+    put(&mut header, 8, &(segment.offset as u64).to_le_bytes());
+    put(&mut header, 16, &(segment.vaddr as u64).to_le_bytes());
+    put(&mut header, 24, &(segment.vaddr as u64).to_le_bytes());
+    put(&mut header, 32, &(segment.filesz as u64).to_le_bytes());
+    put(&mut header, 40, &(segment.memsz as u64).to_le_bytes());
+    put(&mut header, 48, &(align as u64).to_le_bytes());
+    header
+}
+
+/// Appends a note owned by "CORE", the way the kernel writes them.
+fn push_note(notes: &mut Vec<u8>, note_type: u32, desc: &[u8]) {
+    let name = b"CORE\0";
+    notes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&note_type.to_le_bytes());
+    for part in [&name[..], desc].iter() {
+        notes.extend_from_slice(part);
+        notes.resize((notes.len() + 3) & !3, 0);
+    }
+}
+
+fn prpsinfo(pid: i32) -> Vec<u8> {
+    let mut info = vec![0; PRPSINFO_SIZE];
+    info[PRPSINFO_SNAME] = b't';
+    put(&mut info, PRPSINFO_PID, &pid.to_le_bytes());
+    let comm = std::fs::read(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    let comm = String::from_utf8_lossy(&comm);
+    let fname = comm.trim_end().as_bytes();
+    put(
+        &mut info,
+        PRPSINFO_FNAME,
+        &fname[..fname.len().min(FNAME_LEN - 1)],
+    );
+    // the arguments are NUL-separated in cmdline, but space-separated here
+    let mut psargs = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    for byte in psargs.iter_mut().filter(|byte| **byte == 0) {
+        *byte = b' ';
+    }
+    let psargs = &psargs[..psargs.len().min(PSARGS_LEN - 1)];
+    put(&mut info, PRPSINFO_PSARGS, psargs);
+    info
+}
+
+fn file_note(maps: &[MemoryMap]) -> Vec<u8> {
+    let files: Vec<&MemoryMap> = maps
+        .iter()
+        .filter(|map| map.path.starts_with('/'))
+        .collect();
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&(files.len() as u64).to_le_bytes());
+    desc.extend_from_slice(&(PAGE_SIZE as u64).to_le_bytes());
+    for map in &files {
+        for value in [map.start, map.end, map.offset / PAGE_SIZE].iter() {
+            desc.extend_from_slice(&(*value as u64).to_le_bytes());
+        }
+    }
+    for map in &files {
+        desc.extend_from_slice(map.path.as_bytes());
+        desc.push(0);
+    }
+    desc
+}
+
+fn segment_flags(perms: &str) -> u32 {
+    let mut flags = 0;
+    for (perm, flag) in perms.chars().zip([PF_R, PF_W, PF_X].iter()) {
+        if perm != '-' {
+            flags |= flag;
+        }
+    }
+    flags
+}
+
+/// Writes an ELF core of the stopped `inferior` to `path`: a PT_LOAD segment for each readable
+/// mapping, read through /proc/<pid>/mem, and register notes for every thread. The current
+/// thread goes first, since that is the one a debugger shows when loading the core.
+///
+/// Mappings /proc/<pid>/mem can't read at all, like `[vvar]`, are left out. Pages that fail
+/// part way through a mapping, e.g. past the end of a mapped file, are saved as zeros; the
+/// number of those is returned.
+pub fn write_core(inferior: &Inferior, path: &str) -> Result<usize, std::io::Error> {
+    let pid = inferior.pid();
+    let all_maps = inferior.memory_maps()?;
+    let mem = File::open(format!("/proc/{}/mem", pid))?;
+    let readable = |map: &&MemoryMap| {
+        let mut byte = [0];
+        map.perms.starts_with('r') && mem.read_exact_at(&mut byte, map.start as u64).is_ok()
+    };
+    let maps: Vec<&MemoryMap> = all_maps.iter().filter(readable).collect();
+
+    let to_io = |err: nix::Error| {
+        std::io::Error::from_raw_os_error(err.as_errno().map_or(libc::EIO, |errno| errno as i32))
+    };
+    let mut notes = Vec::new();
+    push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid.as_raw()));
+    // like gdb, every thread gets the signal the current one stopped with
+    let signal = ptrace::getsiginfo(inferior.tid()).map_or(0, |info| info.si_signo as u16);
+    let mut tids: Vec<_> = inferior.threads().iter().map(|thread| thread.tid).collect();
+    tids.sort_by_key(|tid| *tid != inferior.tid());
+    for tid in tids {
+        let registers = ptrace::getregs(tid).map_err(to_io)?;
+        let mut prstatus = vec![0; PRSTATUS_SIZE];
+        put(&mut prstatus, PRSTATUS_CURSIG, &signal.to_le_bytes());
+        put(&mut prstatus, PRSTATUS_PID, &tid.as_raw().to_le_bytes());
+        put(&mut prstatus, PRSTATUS_REG, struct_bytes(&registers));
+        push_note(&mut notes, NT_PRSTATUS, &prstatus);
+        let fp_registers = get_fp_registers(tid).map_err(to_io)?;
+        push_note(&mut notes, NT_FPREGSET, struct_bytes(&fp_registers));
+    }
+    if let Ok(auxv) = std::fs::read(format!("/proc/{}/auxv", pid)) {
+        push_note(&mut notes, NT_AUXV, &auxv);
+    }
+    push_note(&mut notes, NT_FILE, &file_note(&all_maps));
+
+    // headers and notes first, then the memory, starting on a page boundary
+    let notes_offset = ELF_HEADER_SIZE + (1 + maps.len()) * PROGRAM_HEADER_SIZE;
+    let data_offset = (notes_offset + notes.len() + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+    let mut headers = program_header(
+        PT_NOTE,
+        0,
+        &Segment {
+            vaddr: 0,
+            memsz: 0,
+            offset: notes_offset,
+            filesz: notes.len(),
+        },
+    );
+    let mut offset = data_offset;
+    for map in &maps {
+        let size = map.end - map.start;
+        let segment = Segment {
+            vaddr: map.start,
+            memsz: size,
+            offset,
+            filesz: size,
+        };
+        headers.extend(program_header(PT_LOAD, segment_flags(&map.perms), &segment));
+        offset += size;
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(&elf_header(1 + maps.len()))?;
+    out.write_all(&headers)?;
+    out.write_all(&notes)?;
+    out.write_all(&vec![0; data_offset - notes_offset - notes.len()])?;
+    let mut zeroed = 0;
+    for map in &maps {
+        let mut addr = map.start;
+        while addr < map.end {
+            let mut chunk = vec![0; COPY_CHUNK.min(map.end - addr)];
+            if mem.read_exact_at(&mut chunk, addr as u64).is_err() {
+                // go page by page, so only the pages that really fail become zeros
+                for (index, page) in chunk.chunks_mut(PAGE_SIZE).enumerate() {
+                    let page_addr = addr + index * PAGE_SIZE;
+                    if mem.read_exact_at(page, page_addr as u64).is_err() {
+                        page.iter_mut().for_each(|byte| *byte = 0);
+                        zeroed += 1;
+                    }
+                }
+            }
+            out.write_all(&chunk)?;
+            addr += chunk.len();
+        }
+    }
+    out.flush()?;
+    Ok(zeroed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `bytes` to a scratch file and opens it as a core.
+    fn open_bytes(name: &str, bytes: &[u8]) -> Result<CoreFile, std::io::Error> {
+        let path = std::env::temp_dir().join(format!("deet-{}.{}", name, std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, bytes).unwrap();
+        let core = CoreFile::open(path);
+        std::fs::remove_file(path).unwrap();
+        core
+    }
+
+    fn with_headers(headers: &[Vec<u8>]) -> Vec<u8> {
+        let mut core = elf_header(headers.len());
+        for header in headers {
+            core.extend_from_slice(header);
+        }
+        core
+    }
+
+    #[test]
+    fn rejects_corrupt_cores() {
+        let mut core = elf_header(1);
+        put(&mut core, 0x20, &(u64::MAX - 8).to_le_bytes());
+        assert!(open_bytes("phoff", &core).is_err());
+
+        let wrapping = Segment {
+            vaddr: usize::MAX - 0xfff,
+            memsz: 0x2000,
+            offset: 0,
+            filesz: 0,
+        };
+        let core = with_headers(&[program_header(PT_LOAD, PF_R, &wrapping)]);
+        assert!(open_bytes("vaddr", &core).is_err());
+
+        let huge_notes = Segment {
+            vaddr: 0,
+            memsz: 0,
+            offset: 8,
+            filesz: usize::MAX,
+        };
+        let core = with_headers(&[program_header(PT_NOTE, 0, &huge_notes)]);
+        assert!(open_bytes("notes", &core).is_err());
+
+        assert!(open_bytes("short", &core[..0x30]).is_err());
+    }
+
+    #[test]
+    fn survives_a_corrupt_file_note() {
+        let mut notes = Vec::new();
+        push_note(&mut notes, NT_PRSTATUS, &[0; PRSTATUS_SIZE]);
+        let mut files = u64::MAX.to_le_bytes().to_vec();
+        files.extend_from_slice(&(PAGE_SIZE as u64).to_le_bytes());
+        push_note(&mut notes, NT_FILE, &files);
+        let segment = Segment {
+            vaddr: 0,
+            memsz: 0,
+            offset: ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE,
+            filesz: notes.len(),
+        };
+        let mut core = with_headers(&[program_header(PT_NOTE, 0, &segment)]);
+        core.extend(notes);
+        let core = open_bytes("file-note", &core).unwrap();
+        assert!(core.memory_maps().unwrap().is_empty());
+        assert_eq!(core.threads().len(), 1);
+        assert!(core.read_memory(usize::MAX - 1, 4).is_err());
+    }
+
+    /// Dumps this very test program, stopped at its exec, and reads the dump back.
+    #[test]
+    fn reads_back_what_write_core_wrote() {
+        let exe = std::env::current_exe().unwrap();
+        let args = vec!["--marker".to_string()];
+        let mut inferior = Inferior::new(exe.to_str().unwrap(), &args).unwrap();
+        let path = std::env::temp_dir().join(format!("deet-core.{}", inferior.pid()));
+        let path = path.to_str().unwrap();
+        let written = write_core(&inferior, path);
+        let registers = inferior.get_registers().unwrap();
+        let rip = registers.rip as usize;
+        let code = inferior.read_memory(rip, 64).unwrap();
+        let stack = inferior.read_memory(registers.rsp as usize, 64).unwrap();
+        let entry = inferior.entry_point().unwrap();
+        let pid = inferior.pid();
+        inferior.kill();
+        written.unwrap();
+        let core = CoreFile::open(path);
+        std::fs::remove_file(path).unwrap();
+        let mut core = core.unwrap();
+
+        assert!(core.command.ends_with("--marker"), "{}", core.command);
+        // stopped at its exec
+        assert_eq!(core.signal, Some(Signal::SIGTRAP));
+        assert_eq!(core.entry_point().unwrap(), entry);
+        let core_registers = core.get_registers().unwrap();
+        assert_eq!(core_registers.rip, registers.rip);
+        assert_eq!(core_registers.rsp, registers.rsp);
+        assert!(core.get_fp_registers().is_ok());
+        assert_eq!(core.read_memory(rip, 64).unwrap(), code);
+        assert_eq!(core.read_memory(registers.rsp as usize, 64).unwrap(), stack);
+        assert_eq!(core.threads().len(), 1);
+        assert_eq!(core.current_thread(), (1, pid));
+        assert_eq!(core.select_thread(1), Some(pid));
+        assert_eq!(core.select_thread(2), None);
+        assert_eq!(core.select_thread(0), None);
+        let maps = core.memory_maps().unwrap();
+        assert!(maps.iter().any(|map| map.start <= rip && rip < map.end));
+    }
+}
@@ -1,9 +1,27 @@
 use std::collections::HashMap;
 
+use crate::condition::Condition;
+use crate::core_file::{self, CoreFile};
 use crate::debugger_command::DebuggerCommand;
+use crate::disasm;
 use crate::dwarf_data::DwarfData;
-use crate::inferior::{Inferior, Status};
+use crate::dwarf_data::{Encoding, Line, Location, Type, TypeKind};
+use crate::eval;
+use crate::examine::ExamineFormat;
+use crate::inferior::{exe_path, Inferior, Status};
+use crate::registers::{self, REGISTER_NAMES};
+use crate::signals::{self, SignalTable};
+use crate::source::{self, SourceCache};
+use crate::syscall::{self, Catchpoint};
+use crate::target::Target;
+use crate::unwind::{unwind_frame_pointer, CallFrameInfo, Frame, LibraryCfi, Unwound};
+use crate::value::{format_string, format_value, read_c_string, read_unsigned};
+use crate::watchpoint::{
+    check_watchable, dr7_bits, WatchScope, Watchpoint, DR_CONTROL, DR_STATUS, MAX_HW_WATCH_POINTS,
+};
 use nix::sys::ptrace;
+use nix::sys::signal;
+use nix::unistd::Pid;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
@@ -12,16 +30,74 @@ pub struct Debugger {
     history_path: String,
     readline: Editor<()>,
     inferior: Option<Inferior>,
+    /// A core file given with --core, examined in place of a live process.
+    core: Option<CoreFile>,
     dwarf_data: DwarfData,
-    break_points: HashMap<usize, Breakpoint>,
+    break_points: Vec<Breakpoint>,
+    watch_points: Vec<Watchpoint>,
+    catch_points: Vec<Catchpoint>,
+    next_break_point_num: usize,
+    /// Stack of the stopped inferior, innermost first. Filled in on demand and cleared whenever
+    /// the inferior runs.
+    frames: Vec<Frame>,
+    /// Index into `frames` of the frame `print` and `info locals` look at.
+    selected_frame: usize,
+    /// Format of the last `x`, which a bare `x/N` or `x` reuses.
+    last_examine: ExamineFormat,
+    /// Where a bare `x` continues from.
+    next_examine: Option<usize>,
+    sources: SourceCache,
+    /// File and first and last line of the last `list`, which a bare `list` continues from.
+    /// Cleared when the inferior runs or another frame gets selected.
+    last_listed: Option<(String, usize, usize)>,
+    /// `set follow-fork-mode child`: after a fork, debug the child rather than the parent.
+    follow_fork_child: bool,
+    /// `set detach-on-fork`: let the side of a fork we don't follow run freely, rather than
+    /// keeping it stopped.
+    detach_on_fork: bool,
+    /// Processes from forks we didn't follow, kept stopped with detach-on-fork off. Debugging
+    /// switches to one when the followed process ends.
+    held: Vec<Inferior>,
+    /// The parent of a vfork whose child we followed. It is detached once the child stops
+    /// sharing its memory, which still holds our breakpoints until then.
+    vfork_parent: Option<Inferior>,
+    /// Address and original byte of the breakpoint `run_until` plants, while it is in place.
+    temp_break_point: Option<(usize, u8)>,
+    /// What to do with each signal the inferior receives, as set with `handle`.
+    signals: SignalTable,
+    /// Scripts being run by `source`, innermost last, so a script can't source itself forever.
+    sourcing: Vec<Script>,
+    /// Numbers of the breakpoints the inferior last stopped at, whose commands are yet to run.
+    stop_break_points: Vec<usize>,
+    /// Set while breakpoint commands run, so stops they cause queue up rather than nest.
+    running_commands: bool,
+    /// How the last inferior ended, as a shell reports it: its exit code, or 128 plus the
+    /// number of the signal that killed it. Batch mode exits with it.
+    exit_status: i32,
 }
 
 #[derive(Clone)]
 pub struct Breakpoint {
+    pub num: usize,
+    /// Where the user asked for it, to find it again in the new program after an exec.
+    pub location: String,
     pub addr: usize,
     pub orig_byte: u8,
+    pub enabled: bool,
+    pub condition: Option<Condition>,
+    pub ignore_count: usize,
+    pub hit_count: usize,
+    /// Debugger commands to run when the inferior stops here, as set with `commands`.
+    pub commands: Vec<String>,
 }
 
+/// A file being run by `source`.
+struct Script {
+    path: String,
+    lines: Vec<String>,
+    /// Index into `lines` of the next one to run.
+    next: usize,
+}
 
 fn parse_address(addr: &str) -> Option<usize> {
     let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
@@ -32,197 +108,2921 @@ fn parse_address(addr: &str) -> Option<usize> {
     usize::from_str_radix(addr_without_0x, 16).ok()
 }
 
-impl Debugger {
-    /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
-        let dwarf = DwarfData::from_file(target).unwrap();
-        dwarf.print();
+/// Writes the original bytes under the enabled breakpoints and `temp` back into `inferior`, or
+/// with `planted`, the 0xcc again.
+fn patch_break_points(
+    inferior: &mut Inferior,
+    break_points: &[Breakpoint],
+    temp: Option<(usize, u8)>,
+    planted: bool,
+) -> Result<(), nix::Error> {
+    let enabled = break_points.iter().filter(|bp| bp.enabled);
+    for (addr, orig_byte) in enabled.map(|bp| (bp.addr, bp.orig_byte)).chain(temp) {
+        // the original bytes are known, so there is no need to read them back
+        inferior.write_memory(addr, &[if planted { 0xcc } else { orig_byte }])?;
+    }
+    Ok(())
+}
+
+fn format_hit_count(hit_count: usize) -> String {
+    match hit_count {
+        0 => String::new(),
+        1 => "\n\tbreakpoint already hit 1 time".to_string(),
+        n => format!("\n\tbreakpoint already hit {} times", n),
+    }
+}
+
+/// Backtraces give up past this depth, e.g. on runaway recursion.
+const MAX_FRAMES: usize = 4096;
+
+/// How much of a string `x/s` shows, like gdb's `print elements` limit.
+const MAX_STRING: usize = 200;
+
+/// The longest an x86-64 instruction can be.
+const MAX_INSTRUCTION_LEN: usize = 15;
+
+impl Debugger {
+    /// Initializes the debugger. Scripts run with `-x` pass `batch`, which leaves out the dump
+    /// of the debug info.
+    pub fn new(target: &str, batch: bool) -> Debugger {
+        let dwarf = DwarfData::from_file(target).unwrap();
+        if !batch {
+            dwarf.print();
+        }
+
+        // without a home directory, the history stays in the working one
+        let history_path = match std::env::var("HOME") {
+            Ok(home) => format!("{}/.deet_history", home),
+            Err(_) => ".deet_history".to_string(),
+        };
+        let mut readline = Editor::<()>::new();
+        // Attempt to load history from ~/.deet_history if it exists
+        let _ = readline.load_history(&history_path);
+
+        Debugger {
+            target: target.to_string(),
+            history_path,
+            readline,
+            inferior: None,
+            core: None,
+            dwarf_data: dwarf,
+            break_points: Vec::new(),
+            watch_points: Vec::new(),
+            catch_points: Vec::new(),
+            next_break_point_num: 0,
+            frames: Vec::new(),
+            selected_frame: 0,
+            last_examine: ExamineFormat::default(),
+            next_examine: None,
+            sources: SourceCache::default(),
+            last_listed: None,
+            follow_fork_child: false,
+            detach_on_fork: true,
+            held: Vec::new(),
+            vfork_parent: None,
+            temp_break_point: None,
+            signals: SignalTable::default(),
+            sourcing: Vec::new(),
+            stop_break_points: Vec::new(),
+            running_commands: false,
+            exit_status: 0,
+        }
+    }
+
+    /// Reads commands at the prompt and runs them until the user quits.
+    pub fn run(&mut self) {
+        loop {
+            let cmd = self.get_next_command();
+            if !self.execute(cmd) {
+                return;
+            }
+        }
+    }
+
+    /// Carries out one command, typed at the prompt or read from a script. Returns false once
+    /// it has quit.
+    pub fn execute(&mut self, cmd: DebuggerCommand) -> bool {
+        match cmd {
+            DebuggerCommand::Run(args) => self.run_command(args),
+            DebuggerCommand::Quit => {
+                self.quit();
+                return false;
+            }
+            DebuggerCommand::Source(path) => match path {
+                Some(path) => return self.source_command(&path),
+                None => println!("source command requires file name as argument."),
+            },
+            DebuggerCommand::Attach(pid) => self.attach_command(pid),
+            DebuggerCommand::Detach => self.detach_command(),
+            DebuggerCommand::Gcore(path) => self.gcore_command(path),
+            DebuggerCommand::Cont => {
+                if let Err(err) = self.cont_command() {
+                    println!("continue command fail {}", err);
+                }
+            }
+            DebuggerCommand::BackTrace => self.back_trace(),
+            DebuggerCommand::BreakPoint(pos, condition) => self.break_point_command(pos, condition),
+            DebuggerCommand::Condition(num, condition) => self.condition_command(num, condition),
+            DebuggerCommand::Ignore(num, count) => self.ignore_command(num, count),
+            DebuggerCommand::Commands(num) => self.commands_command(num),
+            DebuggerCommand::InfoBreakPoints => self.info_break_points(),
+            DebuggerCommand::InfoLocals => self.info_variables(false),
+            DebuggerCommand::InfoArgs => self.info_variables(true),
+            DebuggerCommand::InfoThreads => self.info_threads(),
+            DebuggerCommand::InfoSignals(name) => self.info_signals(name),
+            DebuggerCommand::Handle(args) => self.handle_command(args),
+            DebuggerCommand::Signal(name) => self.signal_command(name),
+            DebuggerCommand::Thread(num) => self.thread_command(num),
+            DebuggerCommand::Up(count) => self.move_frame_command(count, 1),
+            DebuggerCommand::Down(count) => self.move_frame_command(count, -1),
+            DebuggerCommand::Frame(num) => self.frame_command(num),
+            DebuggerCommand::Delete(num) => self.delete_command(num),
+            DebuggerCommand::Disable(num) => self.enable_command(num, false),
+            DebuggerCommand::Enable(num) => self.enable_command(num, true),
+            DebuggerCommand::Print(name) => self.print_command(name),
+            DebuggerCommand::SetVar(expr) => self.set_var_command(expr),
+            DebuggerCommand::Set(setting, value) => self.set_command(&setting, value),
+            DebuggerCommand::Show(setting) => self.show_command(setting),
+            DebuggerCommand::Examine(format, expr) => self.examine_command(format, expr),
+            DebuggerCommand::InfoRegisters(names) => self.info_registers(names),
+            DebuggerCommand::Disassemble(target) => self.disassemble_command(target),
+            DebuggerCommand::List(location) => self.list_command(location),
+            DebuggerCommand::Directory(dirs) => self.directory_command(dirs),
+            DebuggerCommand::Watch(expr, access) => self.watch_command(expr, access),
+            DebuggerCommand::CatchSyscall(syscalls) => self.catch_syscall_command(syscalls),
+            DebuggerCommand::Next => {
+                if let Err(err) = self.step_line_command(false) {
+                    println!("next command fail {}", err);
+                }
+            }
+            DebuggerCommand::Step => {
+                if let Err(err) = self.step_line_command(true) {
+                    println!("step command fail {}", err);
+                }
+            }
+            DebuggerCommand::StepI => {
+                if let Err(err) = self.step_instruction_command() {
+                    println!("stepi command fail {}", err);
+                }
+            }
+            DebuggerCommand::Finish => {
+                if let Err(err) = self.finish_command() {
+                    println!("finish command fail {}", err);
+                }
+            }
+        }
+        self.run_break_point_commands()
+    }
+
+    /// Runs the commands of the breakpoints the inferior stopped at. As in gdb, a command that
+    /// resumes the inferior ends its list. Should that stop at a breakpoint again, its commands
+    /// run from the loop here rather than from a nested call, so a list ending in `cont` can
+    /// trace any number of hits. Returns false if one of them quit.
+    fn run_break_point_commands(&mut self) -> bool {
+        if self.running_commands {
+            return true;
+        }
+        self.running_commands = true;
+        let mut running = true;
+        while running && !self.stop_break_points.is_empty() {
+            let nums = std::mem::take(&mut self.stop_break_points);
+            let commands: Vec<String> = self
+                .break_points
+                .iter()
+                .filter(|bp| nums.contains(&bp.num))
+                .flat_map(|bp| bp.commands.clone())
+                .collect();
+            for line in commands {
+                let tokens: Vec<&str> = line.split_whitespace().collect();
+                // checked when the list was entered
+                let cmd = DebuggerCommand::from_tokens(&tokens).unwrap();
+                let resumes = cmd.resumes();
+                if !self.execute(cmd) {
+                    running = false;
+                    break;
+                }
+                if resumes {
+                    break;
+                }
+            }
+        }
+        self.running_commands = false;
+        running
+    }
+
+    /// Runs the commands in the file at `path` as if they were typed at the prompt, skipping
+    /// blank lines and `#` comments. Gives up on the file at a line it can't parse. Returns
+    /// false if a command in it quit.
+    pub fn source_command(&mut self, path: &str) -> bool {
+        if self.sourcing.iter().any(|script| script.path == path) {
+            println!("{}: already being sourced.", path);
+            return true;
+        }
+        let script = match std::fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(err) => {
+                println!("{}: {}.", path, err);
+                return true;
+            }
+        };
+        self.sourcing.push(Script {
+            path: path.to_string(),
+            lines: script.lines().map(|line| line.trim().to_string()).collect(),
+            next: 0,
+        });
+        let mut running = true;
+        while let Some((number, line)) = self.next_script_line() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match DebuggerCommand::from_tokens(&tokens) {
+                Some(cmd) => {
+                    if !self.execute(cmd) {
+                        running = false;
+                        break;
+                    }
+                }
+                None => {
+                    println!("{}:{}: Unrecognized command \"{}\".", path, number, line);
+                    break;
+                }
+            }
+        }
+        self.sourcing.pop();
+        running
+    }
+
+    /// Takes the next line to run, with its line number, from the innermost script being
+    /// sourced.
+    fn next_script_line(&mut self) -> Option<(usize, String)> {
+        let script = self.sourcing.last_mut()?;
+        while script.next < script.lines.len() {
+            let line = &script.lines[script.next];
+            script.next += 1;
+            if !line.is_empty() && !line.starts_with('#') {
+                return Some((script.next, line.clone()));
+            }
+        }
+        None
+    }
+
+    /// Sources `~/.deetinit` and then `./.deetinit`, those that exist, like gdb's `.gdbinit`.
+    /// Returns false if one of them quit.
+    pub fn source_init_files(&mut self) -> bool {
+        let mut paths = Vec::new();
+        // started from the home directory, the two are the same file
+        let same = |a: &str, b: &str| match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        };
+        if let Ok(home) = std::env::var("HOME") {
+            paths.push(format!("{}/.deetinit", home));
+        }
+        if !paths.iter().any(|home| same(home, ".deetinit")) {
+            paths.push(".deetinit".to_string());
+        }
+        for path in paths {
+            if std::path::Path::new(&path).exists() && !self.source_command(&path) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The status batch mode exits with: that of the last inferior to end.
+    pub fn exit_status(&self) -> i32 {
+        self.exit_status
+    }
+
+    /// Kills the inferior, or detaches if we attached to it, along with any held processes.
+    pub fn quit(&mut self) {
+        // leave a process we attached to running, as we found it
+        match self
+            .inferior
+            .as_ref()
+            .map(|inferior| inferior.is_attached())
+        {
+            Some(true) => self.detach_command(),
+            Some(false) => self.inferior.as_mut().unwrap().kill(),
+            None => {}
+        }
+        self.inferior = None;
+        self.kill_held();
+    }
+
+    /// The process read-only commands look at: the live one, or else the core file's.
+    fn process(&self) -> Option<&dyn Target> {
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => Some(inferior),
+            (None, Some(core)) => Some(core),
+            (None, None) => None,
+        }
+    }
+
+    /// Examines the core file at `path` in place of a live process, saying how the program
+    /// ended the way gdb does.
+    pub fn core_command(&mut self, path: &str) {
+        let core = match CoreFile::open(path) {
+            Ok(core) => core,
+            Err(err) => {
+                println!("\"{}\" is not a core dump: {}", path, err);
+                return;
+            }
+        };
+        if !core.command.is_empty() {
+            println!("Core was generated by `{}'.", core.command);
+        }
+        if let Some(signal) = core.signal {
+            println!(
+                "Program terminated with signal {}, {}.",
+                signal.as_str(),
+                signals::description(signal)
+            );
+        }
+        self.core = Some(core);
+        self.invalidate_frames();
+        self.update_load_base();
+        if let Err(err) = self.load_frames() {
+            println!("read registers fail {}", err);
+            return;
+        }
+        self.print_frame(0);
+    }
+
+    /// Saves a core of the stopped inferior, `core.<pid>` unless told otherwise. It gets the
+    /// program's own code, without our breakpoints.
+    fn gcore_command(&mut self, path: Option<String>) {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => {
+                println!("The program is not being run.");
+                return;
+            }
+        };
+        let path = path.unwrap_or_else(|| format!("core.{}", inferior.pid()));
+        if let Err(err) = patch_break_points(inferior, &self.break_points, None, false) {
+            println!("Cannot remove breakpoints: {}", err);
+            return;
+        }
+        let ret = core_file::write_core(inferior, &path);
+        if let Err(err) = patch_break_points(inferior, &self.break_points, None, true) {
+            println!("Cannot reinsert breakpoints: {}", err);
+        }
+        match ret {
+            Ok(0) => println!("Saved corefile {}", path),
+            Ok(zeroed) => println!(
+                "Saved corefile {}, with {} unreadable pages filled with zeros",
+                path, zeroed
+            ),
+            Err(err) => println!("Can't create a corefile: {}", err),
+        }
+    }
+
+    fn run_command(&mut self, args: Vec<String>) {
+        // a process we attached to is detached from rather than killed, as on quit
+        self.quit();
+
+        if let Some(inferior) = Inferior::new(&self.target, &args) {
+            self.inferior = Some(inferior);
+            self.core = None;
+            self.update_load_base();
+            self.insert_break_points();
+            self.insert_watch_points();
+            self.cont_run();
+        } else {
+            println!("Error starting subprocess");
+        }
+    }
+
+    /// Starts debugging a running process, switching to its executable's debug info if it isn't
+    /// the current target.
+    pub fn attach_command(&mut self, pid: Option<String>) {
+        let pid = match pid.as_ref().map(|pid| pid.parse::<i32>()) {
+            Some(Ok(pid)) => Pid::from_raw(pid),
+            Some(Err(_)) => {
+                println!("Invalid process id \"{}\".", pid.unwrap());
+                return;
+            }
+            None => {
+                println!("Argument required (process-id to attach).");
+                return;
+            }
+        };
+        if self.inferior.is_some() {
+            println!("A program is being debugged already. Kill or detach it first.");
+            return;
+        }
+        let target = match exe_path(pid) {
+            Ok(target) => target,
+            Err(err) => {
+                println!("Cannot read executable of process {}: {}", pid, err);
+                return;
+            }
+        };
+        if target != self.target {
+            match DwarfData::from_file(&target) {
+                Ok(dwarf_data) => self.dwarf_data = dwarf_data,
+                Err(err) => {
+                    println!("Cannot load debug info from {}: {:?}", target, err);
+                    return;
+                }
+            }
+            if !self.break_points.is_empty() || !self.watch_points.is_empty() {
+                println!(
+                    "Deleting breakpoints and watchpoints set in {}",
+                    self.target
+                );
+                self.break_points.clear();
+                self.watch_points.clear();
+            }
+            self.target = target;
+        }
+        match Inferior::attach(pid) {
+            Ok(inferior) => {
+                println!("Attaching to program: {}, process {}", self.target, pid);
+                self.inferior = Some(inferior);
+                self.core = None;
+                self.invalidate_frames();
+                self.update_load_base();
+                self.insert_break_points();
+                self.insert_watch_points();
+                match ptrace::getregs(pid) {
+                    Ok(registers) => self.print_location(registers.rip as usize),
+                    Err(err) => println!("read registers fail {}", err),
+                }
+            }
+            // EPERM usually means kernel.yama.ptrace_scope forbids attaching to non-children
+            Err(err) => println!("Cannot attach to process {}: {}", pid, err),
+        }
+    }
+
+    /// Works out where the inferior's executable got loaded (non-zero only for PIE) and moves
+    /// breakpoints and global watchpoints along with it. Addresses set before the first run are
+    /// link-time ones, since the base starts out as zero.
+    fn update_load_base(&mut self) {
+        let entry = match self.process().unwrap().entry_point() {
+            Ok(entry) => entry,
+            Err(err) => {
+                println!("Cannot read load address, assuming non-PIE: {}", err);
+                return;
+            }
+        };
+        let old_base = self.dwarf_data.load_base();
+        let new_base = entry.wrapping_sub(self.dwarf_data.entry());
+        if new_base == old_base {
+            return;
+        }
+        for bp in self.break_points.iter_mut() {
+            bp.addr = bp.addr.wrapping_sub(old_base).wrapping_add(new_base);
+        }
+        for wp in self.watch_points.iter_mut().filter(|wp| wp.scope.is_none()) {
+            wp.addr = wp.addr.wrapping_sub(old_base).wrapping_add(new_base);
+        }
+        self.dwarf_data.set_load_base(new_base);
+    }
+
+    /// Takes every 0xcc and watchpoint out of the inferior and lets it run on untraced.
+    fn detach_command(&mut self) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
+            return;
+        }
+        let mut restored: HashMap<usize, u8> = HashMap::new();
+        for bp in self.break_points.iter().filter(|bp| bp.enabled) {
+            restored.insert(bp.addr, bp.orig_byte);
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        for (addr, orig_byte) in restored {
+            if let Err(err) = inferior.write_memory(addr, &[orig_byte]) {
+                println!("Cannot remove breakpoint at {:#x}: {}", addr, err);
+            }
+        }
+        if let Err(err) = inferior.write_debug_register(DR_CONTROL, 0) {
+            println!("Cannot remove hardware watchpoints: {}", err);
+        }
+        let pid = inferior.pid();
+        match inferior.detach() {
+            Ok(()) => println!("Detaching from program: {}, process {}", self.target, pid),
+            Err(err) => println!("detach fail {}", err),
+        }
+        // watched locals belonged to frames we can no longer follow
+        self.watch_points.retain(|wp| wp.scope.is_none());
+        self.inferior = None;
+    }
+
+    fn cont_command(&mut self) -> Result<(), nix::Error> {
+        if self.inferior.is_none() {
+            println!("no process in debugger");
+            return Ok(());
+        }
+        if let Some(status) = self.step_over_break_point()? {
+            println!("Child {}", self.format_status(&status));
+            return Ok(());
+        }
+        self.cont_run();
+        Ok(())
+    }
+
+    fn step_instruction_command(&mut self) -> Result<(), nix::Error> {
+        if self.inferior.is_none() {
+            println!("No process is running");
+            return Ok(());
+        }
+        match self.step_instruction()? {
+            Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                self.watch_point_triggered()?;
+                self.print_location(rip);
+            }
+            status => println!("Child {}", self.format_status(&status)),
+        }
+        Ok(())
+    }
+
+    /// Steps to the start of the next source line. Calls are stepped over unless `step_into` is
+    /// set and the callee has line information, in which case we stop after its prologue.
+    fn step_line_command(&mut self, step_into: bool) -> Result<(), nix::Error> {
+        if self.inferior.is_none() {
+            println!("No process is running");
+            return Ok(());
+        }
+        let pid = self.inferior.as_ref().unwrap().tid();
+        let mut registers = ptrace::getregs(pid)?;
+        let (line_start, line_end) = match self.dwarf_data.get_line_range(registers.rip as usize) {
+            Some(range) => range,
+            None => {
+                println!("No line number information, use stepi instead");
+                return Ok(());
+            }
+        };
+        let start_line = self
+            .dwarf_data
+            .get_line_from_addr(registers.rip as usize)
+            .map(|line| line.number);
+
+        loop {
+            let prev_rip = registers.rip as usize;
+            let prev_rsp = registers.rsp;
+            let mut rip = match self.step_instruction()? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => rip,
+                status => {
+                    println!("Child {}", self.format_status(&status));
+                    return Ok(());
+                }
+            };
+            if self.watch_point_triggered()? == Some(true) {
+                self.print_location(rip);
+                return Ok(());
+            }
+            registers = ptrace::getregs(pid)?;
+            let top_of_stack = ptrace::read(pid, registers.rsp as ptrace::AddressType)? as usize;
+
+            // a call pushes the address of the instruction following it
+            if registers.rsp == prev_rsp - 8
+                && top_of_stack > prev_rip
+                && top_of_stack <= prev_rip + 16
+            {
+                if step_into {
+                    if let Some(func) = self.dwarf_data.get_function_by_addr(rip) {
+                        let body = self.dwarf_data.get_prologue_end(func);
+                        if body != rip {
+                            match self.run_until(body)? {
+                                Status::Stopped(_, rip) => self.print_location(rip),
+                                status => println!("Child {}", self.format_status(&status)),
+                            }
+                        } else {
+                            self.print_location(rip);
+                        }
+                        return Ok(());
+                    }
+                }
+                // step over the call by running to its return address, ignoring hits of the
+                // temporary breakpoint from deeper recursive calls
+                let return_addr = top_of_stack;
+                let call_rsp = registers.rsp;
+                loop {
+                    match self.run_until(return_addr)? {
+                        Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == return_addr => {
+                            registers = ptrace::getregs(pid)?;
+                            if registers.rsp > call_rsp {
+                                break;
+                            }
+                        }
+                        Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                            self.print_location(rip);
+                            return Ok(());
+                        }
+                        status => {
+                            println!("Child {}", self.format_status(&status));
+                            return Ok(());
+                        }
+                    }
+                }
+                rip = return_addr;
+            } else if registers.rsp == prev_rsp + 8
+                && ptrace::read(pid, prev_rsp as ptrace::AddressType)? as usize == rip
+            {
+                // returned to the caller
+                self.print_location(rip);
+                return Ok(());
+            }
+
+            if (rip < line_start || rip >= line_end)
+                && self.dwarf_data.is_line_start(rip)
+                && self
+                    .dwarf_data
+                    .get_line_from_addr(rip)
+                    .map(|line| line.number)
+                    != start_line
+            {
+                self.print_location(rip);
+                return Ok(());
+            }
+            if self.break_point_at(rip).is_some() && self.should_stop_at(rip) {
+                self.print_location(rip);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs until the selected frame returns to its caller, then prints where we landed and
+    /// the returned value.
+    fn finish_command(&mut self) -> Result<(), nix::Error> {
+        if self.inferior.is_none() {
+            println!("No process is running");
+            return Ok(());
+        }
+        let pid = self.inferior.as_ref().unwrap().tid();
+        // finish out of the selected frame, returning to the frame that called it
+        let (frames, err) = self.unwind_stack()?;
+        let selected = self.selected_frame.min(frames.len() - 1);
+        let pc = frames[selected].lookup_pc(selected == 0);
+        let func = match self.dwarf_data.get_function_by_addr(pc) {
+            Some(func) => func.clone(),
+            None => {
+                println!("No function contains {:#x}", pc);
+                return Ok(());
+            }
+        };
+        // the caller's rsp is the CFA of the frame being finished, where it is once it returns
+        let (return_addr, cfa) = match frames.get(selected + 1) {
+            Some(caller) => (caller.pc, caller.rsp),
+            None => {
+                match err {
+                    Some(err) => println!("Cannot find the caller of {}: {}", func.name, err),
+                    None => println!("\"finish\" not meaningful in the outermost frame."),
+                }
+                return Ok(());
+            }
+        };
+        println!("Run till exit from {}", func.name);
+
+        // a deeper recursive call may return to the same address first
+        loop {
+            match self.run_until(return_addr)? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip) if rip == return_addr => {
+                    if ptrace::getregs(pid)?.rsp as usize >= cfa {
+                        break;
+                    }
+                }
+                Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                    self.print_location(rip);
+                    return Ok(());
+                }
+                status => {
+                    println!("Child {}", self.format_status(&status));
+                    return Ok(());
+                }
+            }
+        }
+        self.print_location(return_addr);
+
+        if let Some(return_type) = &func.return_type {
+            let inferior = self.inferior.as_ref().unwrap();
+            let registers = ptrace::getregs(pid)?;
+            // floating point values come back in xmm0, small aggregates in rax:rdx, and larger
+            // ones in memory the caller provided, whose address is handed back in rax
+            let bytes = match return_type.strip_aliases().kind {
+                TypeKind::Base(Encoding::Float) => {
+                    let xmm0 = &inferior.get_fp_registers()?.xmm_space[..2];
+                    (((xmm0[1] as u64) << 32) | xmm0[0] as u64)
+                        .to_le_bytes()
+                        .to_vec()
+                }
+                _ if return_type.size > 16 => inferior
+                    .read_memory(registers.rax as usize, return_type.size)
+                    .unwrap_or_default(),
+                _ => {
+                    let mut bytes = registers.rax.to_le_bytes().to_vec();
+                    bytes.extend_from_slice(&registers.rdx.to_le_bytes());
+                    bytes
+                }
+            };
+            println!(
+                "Value returned ({}) = {}",
+                return_type.name,
+                format_value(self, return_type, &bytes)
+            );
+        }
+        Ok(())
+    }
+
+    /// If rip sits on one of our breakpoints, restores the original byte, single-steps past it
+    /// and writes the 0xcc back. Returns the status if the step didn't end in a plain trap, or
+    /// if it tripped a watchpoint.
+    fn step_over_break_point(&mut self) -> Result<Option<Status>, nix::Error> {
+        let pid = self.inferior.as_ref().unwrap().tid();
+        let rip = ptrace::getregs(pid)?.rip as usize;
+        if self.break_point_at(rip).is_none() {
+            return Ok(None);
+        }
+        match self.step_instruction()? {
+            Status::Stopped(signal::Signal::SIGTRAP, rip) => {
+                if self.watch_point_triggered()? == Some(true) {
+                    Ok(Some(Status::Stopped(signal::Signal::SIGTRAP, rip)))
+                } else {
+                    Ok(None)
+                }
+            }
+            status => Ok(Some(status)),
+        }
+    }
+
+    /// Executes one machine instruction, temporarily restoring the original byte if rip sits on
+    /// one of our breakpoints.
+    fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        self.invalidate_frames();
+        let rip = ptrace::getregs(self.inferior.as_ref().unwrap().tid())?.rip as usize;
+        let break_point = self.break_point_at(rip).cloned();
+        let inferior = self.inferior.as_mut().unwrap();
+        if let Some(bp) = &break_point {
+            inferior.write_memory(bp.addr, &[bp.orig_byte])?;
+        }
+
+        inferior.step()?;
+        let status = self.wait_inferior(true)?;
+        if let (Status::Stopped(..), Some(bp)) = (&status, &break_point) {
+            self.inferior
+                .as_mut()
+                .unwrap()
+                .write_memory(bp.addr, &[0xcc])?;
+        }
+        Ok(status)
+    }
+
+    /// Resumes the inferior until it reaches `addr`, planting a temporary breakpoint there unless
+    /// one of ours already covers it.
+    fn run_until(&mut self, addr: usize) -> Result<Status, nix::Error> {
+        let rip = ptrace::getregs(self.inferior.as_ref().unwrap().tid())?.rip as usize;
+        if rip == addr || self.break_point_at(rip).is_some() {
+            // step off the current instruction so we don't trap on it right away
+            match self.step_instruction()? {
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if rip != addr && self.watch_point_triggered()? != Some(true) => {}
+                status => return Ok(status),
+            }
+        }
+        if self.break_point_at(addr).is_none() {
+            let orig_byte = self.inferior.as_mut().unwrap().write_byte(addr, 0xcc)?;
+            self.temp_break_point = Some((addr, orig_byte));
+        }
+        let status = self.resume(Some(addr));
+        if let (Some((addr, orig_byte)), Some(inferior)) =
+            (self.temp_break_point.take(), self.inferior.as_mut())
+        {
+            inferior.write_memory(addr, &[orig_byte])?;
+        }
+        status
+    }
+
+    fn break_point_command(&mut self, position: Option<String>, condition: Option<String>) {
+        if position.is_none() {
+            println!("please input position");
+            return;
+        }
+        let condition = match condition.map(|text| Condition::parse(&text)) {
+            Some(Ok(condition)) => Some(condition),
+            Some(Err(err)) => {
+                println!("{}", err);
+                return;
+            }
+            None => None,
+        };
+        let position = position.unwrap();
+        let addr = match self.parse_location(&position) {
+            Ok(addr) => addr,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        // lines relative to where we are stopped mean something else later, so pin them down
+        let relative = position.starts_with('+')
+            || position.starts_with('-')
+            || position.parse::<usize>().is_ok();
+        let location = match self.dwarf_data.get_line_from_addr(addr) {
+            Some(line) if relative => format!("{}:{}", line.file, line.number),
+            _ => position,
+        };
+        let num = self.next_break_point_num;
+        self.next_break_point_num += 1;
+        self.break_points.push(Breakpoint {
+            num,
+            location,
+            addr,
+            orig_byte: 0,
+            enabled: true,
+            condition,
+            ignore_count: 0,
+            hit_count: 0,
+            commands: Vec::new(),
+        });
+        println!("Set breakpoint {} at {}", num, addr);
+        if self.inferior.is_some() {
+            if let Err(err) = self.insert_break_point(self.break_points.len() - 1) {
+                println!("Cannot insert breakpoint {}: {}", num, err);
+            }
+        }
+    }
+
+    fn info_break_points(&self) {
+        if self.break_points.is_empty()
+            && self.watch_points.is_empty()
+            && self.catch_points.is_empty()
+        {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        // breakpoints, watchpoints and catchpoints share one numbering, so list them together
+        // in order
+        let mut rows: Vec<(usize, String)> = Vec::new();
+        for bp in &self.break_points {
+            let func = self.dwarf_data.get_function_from_addr(bp.addr);
+            let line = self.dwarf_data.get_line_from_addr(bp.addr);
+            let what = match (func, line) {
+                (Some(func), Some(line)) => format!("in {} at {}", func, line),
+                (Some(func), None) => format!("in {}", func),
+                (None, Some(line)) => format!("at {}", line),
+                (None, None) => String::new(),
+            };
+            let mut row = format!(
+                "{:<5}{:<16}{:<5}{:<#20x}{}",
+                bp.num,
+                "breakpoint",
+                if bp.enabled { "y" } else { "n" },
+                bp.addr,
+                what
+            );
+            if let Some(condition) = &bp.condition {
+                row.push_str(&format!("\n\tstop only if {}", condition.text));
+            }
+            row.push_str(&format_hit_count(bp.hit_count));
+            if bp.ignore_count > 0 {
+                row.push_str(&format!(
+                    "\n\tWill ignore next {} crossings of breakpoint.",
+                    bp.ignore_count
+                ));
+            }
+            for command in &bp.commands {
+                row.push_str(&format!("\n        {}", command));
+            }
+            rows.push((bp.num, row));
+        }
+        for wp in &self.watch_points {
+            let row = format!(
+                "{:<5}{:<16}{:<5}{:<20}{}{}",
+                wp.num,
+                wp.kind(),
+                if wp.enabled { "y" } else { "n" },
+                "",
+                wp.expr,
+                format_hit_count(wp.hit_count)
+            );
+            rows.push((wp.num, row));
+        }
+        for cp in &self.catch_points {
+            let row = format!(
+                "{:<5}{:<16}{:<5}{:<20}{}{}",
+                cp.num,
+                "catchpoint",
+                if cp.enabled { "y" } else { "n" },
+                "",
+                cp.what(),
+                format_hit_count(cp.hit_count).replace("breakpoint", "catchpoint")
+            );
+            rows.push((cp.num, row));
+        }
+        rows.sort_by_key(|(num, _)| *num);
+        println!(
+            "{:<5}{:<16}{:<5}{:<20}What",
+            "Num", "Type", "Enb", "Address"
+        );
+        for (_, row) in rows {
+            println!("{}", row);
+        }
+    }
+
+    fn condition_command(&mut self, num: Option<String>, condition: Option<String>) {
+        if num.is_none() {
+            println!("please input breakpoint number");
+            return;
+        }
+        let index = match self.find_break_point(&num.unwrap()) {
+            Some(index) => index,
+            None => return,
+        };
+        let num = self.break_points[index].num;
+        match condition.map(|text| Condition::parse(&text)) {
+            Some(Ok(condition)) => {
+                println!("Breakpoint {} will stop only if {}.", num, condition.text);
+                self.break_points[index].condition = Some(condition);
+            }
+            Some(Err(err)) => println!("{}", err),
+            None => {
+                self.break_points[index].condition = None;
+                println!("Breakpoint {} now unconditional.", num);
+            }
+        }
+    }
+
+    /// `commands [N]`: reads a list of commands, up to `end`, to run whenever breakpoint N (by
+    /// default the last one set) stops the inferior. An empty list removes them.
+    fn commands_command(&mut self, num: Option<String>) {
+        let index = match num {
+            Some(num) => match self.find_break_point(&num) {
+                Some(index) => index,
+                None => return,
+            },
+            None if self.break_points.is_empty() => {
+                println!("No breakpoints specified.");
+                return;
+            }
+            None => self.break_points.len() - 1,
+        };
+        if self.sourcing.is_empty() {
+            println!(
+                "Type commands for breakpoint(s) {}, one per line.",
+                self.break_points[index].num
+            );
+            println!("End with a line saying just \"end\".");
+        }
+        if let Some(commands) = self.read_command_list() {
+            self.break_points[index].commands = commands;
+        }
+    }
+
+    /// Reads commands up to `end`, from the script being sourced or else the terminal. Lines
+    /// that aren't commands are left out, as is a nested `commands` along with its own list.
+    /// None if the user gave up with ctrl+c.
+    fn read_command_list(&mut self) -> Option<Vec<String>> {
+        let mut commands = Vec::new();
+        // how many nested lists we are inside, whose `end` isn't ours
+        let mut nested = 0;
+        loop {
+            let line = if self.sourcing.is_empty() {
+                match self.readline.readline(">") {
+                    Ok(line) => line.trim().to_string(),
+                    Err(ReadlineError::Interrupted) => return None,
+                    Err(_) => break,
+                }
+            } else {
+                match self.next_script_line() {
+                    Some((_, line)) => line,
+                    None => break,
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if nested > 0 {
+                match tokens[0] {
+                    "end" => nested -= 1,
+                    "commands" => nested += 1,
+                    _ => {}
+                }
+                continue;
+            }
+            if line == "end" {
+                break;
+            }
+            match DebuggerCommand::from_tokens(&tokens) {
+                Some(DebuggerCommand::Commands(_)) => {
+                    println!("Can't use \"commands\" inside a command list.");
+                    nested += 1;
+                    continue;
+                }
+                Some(_) => {}
+                None => {
+                    println!("Unrecognized command \"{}\".", line);
+                    continue;
+                }
+            }
+            commands.push(line);
+        }
+        Some(commands)
+    }
+
+    fn ignore_command(&mut self, num: Option<String>, count: Option<String>) {
+        if num.is_none() || count.is_none() {
+            println!("Argument required (a breakpoint number and a count).");
+            return;
+        }
+        let index = match self.find_break_point(&num.unwrap()) {
+            Some(index) => index,
+            None => return,
+        };
+        let count = count.unwrap();
+        match count.parse::<usize>() {
+            Ok(count) => {
+                self.break_points[index].ignore_count = count;
+                println!(
+                    "Will ignore next {} crossings of breakpoint {}.",
+                    count, self.break_points[index].num
+                );
+            }
+            Err(_) => println!("Invalid count \"{}\".", count),
+        }
+    }
+
+    /// Records a hit on every enabled breakpoint at `rip` whose condition holds, consuming ignore
+    /// counts, and decides whether to stop. A condition that can't be evaluated stops, so the
+    /// user gets to see why.
+    fn should_stop_at(&mut self, rip: usize) -> bool {
+        let mut stop = false;
+        for index in 0..self.break_points.len() {
+            let bp = &self.break_points[index];
+            if !bp.enabled || bp.addr != rip {
+                continue;
+            }
+            let num = bp.num;
+            if let Some(condition) = bp.condition.clone() {
+                match condition.evaluate(self) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        println!("Error in testing breakpoint condition {}: {}", num, err);
+                        stop = true;
+                        continue;
+                    }
+                }
+            }
+            let bp = &mut self.break_points[index];
+            bp.hit_count += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+            } else {
+                stop = true;
+                self.stop_break_points.push(num);
+            }
+        }
+        stop
+    }
+
+    fn delete_command(&mut self, num: Option<String>) {
+        if num.is_none() {
+            // like gdb, a bare delete removes every breakpoint, watchpoint and catchpoint
+            while !self.break_points.is_empty() {
+                self.delete_break_point(0);
+            }
+            self.watch_points.clear();
+            self.catch_points.clear();
+            self.arm_watch_points();
+            return;
+        }
+        let num = num.unwrap();
+        if let Some(index) = self.find_watch_point(&num) {
+            self.watch_points.remove(index);
+            self.arm_watch_points();
+        } else if let Some(index) = self.find_catch_point(&num) {
+            self.catch_points.remove(index);
+        } else if let Some(index) = self.find_break_point(&num) {
+            self.delete_break_point(index);
+        }
+    }
+
+    fn delete_break_point(&mut self, index: usize) {
+        if self.break_points[index].enabled && self.inferior.is_some() {
+            if let Err(err) = self.remove_break_point(index) {
+                println!(
+                    "Cannot remove breakpoint {}: {}",
+                    self.break_points[index].num, err
+                );
+            }
+        }
+        self.break_points.remove(index);
+    }
+
+    fn enable_command(&mut self, num: Option<String>, enabled: bool) {
+        if num.is_none() {
+            println!("please input breakpoint number");
+            return;
+        }
+        let num = num.unwrap();
+        if let Some(index) = self.find_watch_point(&num) {
+            if enabled
+                && !self.watch_points[index].enabled
+                && self.watch_points.iter().filter(|wp| wp.enabled).count() >= MAX_HW_WATCH_POINTS
+            {
+                println!(
+                    "Only {} hardware watchpoints can be enabled at once.",
+                    MAX_HW_WATCH_POINTS
+                );
+                return;
+            }
+            self.watch_points[index].enabled = enabled;
+            self.arm_watch_points();
+            return;
+        }
+        if let Some(index) = self.find_catch_point(&num) {
+            self.catch_points[index].enabled = enabled;
+            return;
+        }
+        let index = match self.find_break_point(&num) {
+            Some(index) => index,
+            None => return,
+        };
+        if self.break_points[index].enabled == enabled {
+            return;
+        }
+        if self.inferior.is_some() {
+            let ret = if enabled {
+                self.insert_break_point(index)
+            } else {
+                self.remove_break_point(index)
+            };
+            if let Err(err) = ret {
+                println!(
+                    "Cannot update breakpoint {}: {}",
+                    self.break_points[index].num, err
+                );
+                return;
+            }
+        }
+        self.break_points[index].enabled = enabled;
+    }
+
+    /// Maps a user-supplied breakpoint number to its index in `break_points`.
+    fn find_break_point(&self, num: &str) -> Option<usize> {
+        let index = num
+            .parse::<usize>()
+            .ok()
+            .and_then(|num| self.break_points.iter().position(|bp| bp.num == num));
+        if index.is_none() {
+            println!("No breakpoint number {}.", num);
+        }
+        index
+    }
+
+    /// Maps a user-supplied number to an index in `watch_points`. Silent, since the number may
+    /// well belong to a breakpoint instead.
+    fn find_watch_point(&self, num: &str) -> Option<usize> {
+        num.parse::<usize>()
+            .ok()
+            .and_then(|num| self.watch_points.iter().position(|wp| wp.num == num))
+    }
+
+    /// Maps a user-supplied number to an index in `catch_points`, silently like
+    /// `find_watch_point`.
+    fn find_catch_point(&self, num: &str) -> Option<usize> {
+        num.parse::<usize>()
+            .ok()
+            .and_then(|num| self.catch_points.iter().position(|cp| cp.num == num))
+    }
+
+    /// Returns the enabled breakpoint planted at `addr`, if any.
+    fn break_point_at(&self, addr: usize) -> Option<&Breakpoint> {
+        self.break_points
+            .iter()
+            .find(|bp| bp.enabled && bp.addr == addr)
+    }
+
+    /// Writes 0xcc for every enabled breakpoint into a freshly started inferior.
+    fn insert_break_points(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let mut patched: HashMap<usize, u8> = HashMap::new();
+        for bp in self.break_points.iter_mut() {
+            bp.hit_count = 0;
+        }
+        for bp in self.break_points.iter_mut().filter(|bp| bp.enabled) {
+            if let Some(orig_byte) = patched.get(&bp.addr) {
+                bp.orig_byte = *orig_byte;
+                continue;
+            }
+            match inferior.write_byte(bp.addr, 0xcc) {
+                Ok(orig_byte) => {
+                    bp.orig_byte = orig_byte;
+                    patched.insert(bp.addr, orig_byte);
+                }
+                Err(err) => println!("Cannot insert breakpoint {}: {}", bp.num, err),
+            }
+        }
+    }
+
+    /// Plants breakpoint `index` in the running inferior. If another enabled breakpoint already
+    /// patched the same address, its saved byte is shared instead of reading back our own 0xcc.
+    fn insert_break_point(&mut self, index: usize) -> Result<(), nix::Error> {
+        let addr = self.break_points[index].addr;
+        let orig_byte = match self.other_break_point_at(index, addr) {
+            Some(bp) => bp.orig_byte,
+            None => self.inferior.as_mut().unwrap().write_byte(addr, 0xcc)?,
+        };
+        self.break_points[index].orig_byte = orig_byte;
+        Ok(())
+    }
+
+    /// Restores the original byte under breakpoint `index`, unless another enabled breakpoint
+    /// still needs the 0xcc there.
+    fn remove_break_point(&mut self, index: usize) -> Result<(), nix::Error> {
+        let bp = self.break_points[index].clone();
+        if self.other_break_point_at(index, bp.addr).is_none() {
+            self.inferior
+                .as_mut()
+                .unwrap()
+                .write_memory(bp.addr, &[bp.orig_byte])?;
+        }
+        Ok(())
+    }
+
+    fn other_break_point_at(&self, index: usize, addr: usize) -> Option<&Breakpoint> {
+        self.break_points
+            .iter()
+            .enumerate()
+            .find(|(i, bp)| *i != index && bp.enabled && bp.addr == addr)
+            .map(|(_, bp)| bp)
+    }
+
+    /// Resolves a breakpoint location: `*addr`, `line`, `file:line`, `function`, `file:function`
+    /// or `+N`/`-N` lines from the selected frame's line. Bare lines are in the current file, or
+    /// else the one defining main.
+    fn parse_location(&self, location: &str) -> Result<usize, String> {
+        if location.starts_with('*') {
+            let addr = location.trim_start_matches('*');
+            return parse_address(addr).ok_or_else(|| format!("Invalid address \"{}\".", addr));
+        }
+        if location.starts_with('+') || location.starts_with('-') {
+            let offset = location[1..]
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid line offset \"{}\".", location))?;
+            let current = self.current_source_line().ok_or_else(|| {
+                "No line to offset from; the program is not stopped in known source.".to_string()
+            })?;
+            let number = if location.starts_with('+') {
+                current.number + offset
+            } else {
+                current.number.saturating_sub(offset).max(1)
+            };
+            return self
+                .dwarf_data
+                .get_addr_for_line(Some(&current.file), number)
+                .ok_or_else(|| format!("No line {} in file \"{}\".", number, current.file));
+        }
+        let (file, name) = match location.rfind(':') {
+            Some(colon) => (Some(&location[..colon]), &location[colon + 1..]),
+            None => (None, location),
+        };
+        if let Some(file) = file {
+            if self.dwarf_data.get_source_path(file).is_none() {
+                return Err(format!("No source file named {}.", file));
+            }
+        }
+        if let Ok(number) = name.parse::<usize>() {
+            let file = match file {
+                Some(file) => file.to_string(),
+                None => self
+                    .current_source_line()
+                    .map(|line| line.file)
+                    .or_else(|| {
+                        self.dwarf_data
+                            .get_function_source("main")
+                            .map(|(path, _)| path)
+                    })
+                    .ok_or_else(|| "No symbol table is loaded.".to_string())?,
+            };
+            return self
+                .dwarf_data
+                .get_addr_for_line(Some(&file), number)
+                .ok_or_else(|| format!("No line {} in file \"{}\".", number, file));
+        }
+        if file.is_none() {
+            let files = self.dwarf_data.get_files_defining(name);
+            if files.len() > 1 {
+                return Err(format!(
+                    "Function \"{}\" is ambiguous; it is defined in {}. Use file:function to pick one.",
+                    name,
+                    files.join(", ")
+                ));
+            }
+        }
+        self.dwarf_data
+            .get_addr_for_function(file, name)
+            .ok_or_else(|| format!("Function \"{}\" not defined.", name))
+    }
+
+    fn print_command(&mut self, expr: Option<String>) {
+        let expr = match expr {
+            Some(expr) => expr,
+            None => {
+                println!("please input variable name");
+                return;
+            }
+        };
+        if self.process().is_none() {
+            println!("No process is running");
+            return;
+        }
+        match self.evaluate(&expr) {
+            Ok(value) => println!(
+                "{} ({}) = {}",
+                expr,
+                value.entity_type.name,
+                format_value(self, &value.entity_type, &value.bytes)
+            ),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    fn set_var_command(&mut self, expr: Option<String>) {
+        let expr = match expr {
+            Some(expr) => expr,
+            None => {
+                println!("Argument required (expression to compute).");
+                return;
+            }
+        };
+        if self.inferior.is_none() {
+            println!("No process is running");
+            return;
+        }
+        if let Err(err) = self.evaluate(&expr) {
+            println!("{}", err);
+        }
+    }
+
+    fn examine_command(&mut self, spec: Option<String>, expr: Option<String>) {
+        if self.process().is_none() {
+            println!("No process is running");
+            return;
+        }
+        let format = match spec {
+            Some(spec) => match ExamineFormat::parse(&spec, self.last_examine) {
+                Ok(format) => format,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => ExamineFormat {
+                count: 1,
+                ..self.last_examine
+            },
+        };
+        let addr = match expr {
+            Some(expr) => match self.evaluate(&expr).and_then(|value| value.to_address()) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => match self.next_examine {
+                Some(addr) => addr,
+                None => {
+                    println!("Argument required (starting display address).");
+                    return;
+                }
+            },
+        };
+        self.last_examine = format;
+        let result = match format.format {
+            's' => self.examine_strings(addr, format.count),
+            'i' => self.examine_instructions(addr, format.count),
+            _ => self.examine_units(addr, format),
+        };
+        match result {
+            Ok(next) => self.next_examine = Some(next),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// Labels an address the way `x` does: `0x401136 <main+4>`.
+    fn format_address(&self, addr: usize) -> String {
+        match self.dwarf_data.get_symbol(addr) {
+            Some(symbol) => format!("{:#x} <{}>", addr, symbol),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// Dumps `format.count` units starting at `addr`, returning the address after the last one.
+    fn examine_units(&self, addr: usize, format: ExamineFormat) -> Result<usize, String> {
+        let bytes = self
+            .process()
+            .unwrap()
+            .read_memory(addr, format.count * format.size)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+        for (line, chunk) in bytes.chunks(format.per_line() * format.size).enumerate() {
+            let line_addr = addr + line * format.per_line() * format.size;
+            let units: Vec<String> = chunk
+                .chunks(format.size)
+                .map(|unit| match format.format {
+                    'a' => self.format_address(read_unsigned(unit, format.size) as usize),
+                    _ => format.format_unit(unit),
+                })
+                .collect();
+            println!("{}:\t{}", self.format_address(line_addr), units.join("\t"));
+        }
+        Ok(addr + bytes.len())
+    }
+
+    /// Prints `count` NUL-terminated strings starting at `addr`, returning the address after the
+    /// last terminator.
+    fn examine_strings(&self, mut addr: usize, count: usize) -> Result<usize, String> {
+        for _ in 0..count {
+            let bytes = read_c_string(self, addr, MAX_STRING)
+                .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+            let truncated = bytes.len() == MAX_STRING;
+            println!(
+                "{}:\t{}",
+                self.format_address(addr),
+                format_string(&bytes, truncated)
+            );
+            addr += bytes.len() + if truncated { 0 } else { 1 };
+        }
+        Ok(addr)
+    }
+
+    /// Prints `count` instructions starting at `addr`, returning the address after the last one.
+    fn examine_instructions(&self, mut addr: usize, count: usize) -> Result<usize, String> {
+        let pc = self.current_pc();
+        for _ in 0..count {
+            let bytes = self.read_code(addr, MAX_INSTRUCTION_LEN)?;
+            let instruction =
+                disasm::decode(&bytes, addr, &|target| self.dwarf_data.get_symbol(target));
+            println!(
+                "{}{}:\t{}",
+                if Some(addr) == pc { "=> " } else { "   " },
+                self.format_address(addr),
+                instruction.text
+            );
+            addr += instruction.len;
+        }
+        Ok(addr)
+    }
+
+    fn disassemble_command(&mut self, target: Option<String>) {
+        let pc = self.current_pc();
+        let func = match &target {
+            Some(name) => match self.dwarf_data.get_function_by_name(name) {
+                Some(func) => Some(func),
+                None if self.process().is_some() => {
+                    match self.evaluate(name).and_then(|value| value.to_address()) {
+                        Ok(addr) => self.dwarf_data.get_function_by_addr(addr),
+                        Err(err) => {
+                            println!("{}", err);
+                            return;
+                        }
+                    }
+                }
+                None => {
+                    println!("No symbol \"{}\" in current context.", name);
+                    return;
+                }
+            },
+            None => match pc {
+                Some(pc) => self.dwarf_data.get_function_by_addr(pc),
+                None => {
+                    println!("No frame selected.");
+                    return;
+                }
+            },
+        };
+        let func = match func {
+            Some(func) => func,
+            None => {
+                println!("No function contains specified address.");
+                return;
+            }
+        };
+        let start = self.dwarf_data.to_runtime(func.address);
+        let end = start + func.text_length;
+        let bytes = match self.read_code(start, func.text_length) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let lines = self.dwarf_data.get_lines_in_range(start, end);
+        println!("Dump of assembler code for function {}:", func.name);
+        let mut addr = start;
+        let mut last_line = None;
+        while addr < end {
+            // show where each source line's code starts, as long as the line changes
+            if let Some(line) = lines.iter().find(|line| line.address == addr) {
+                if last_line != Some(line.number) {
+                    println!("{}", line);
+                    last_line = Some(line.number);
+                }
+            }
+            let instruction = disasm::decode(&bytes[addr - start..], addr, &|target| {
+                self.dwarf_data.get_symbol(target)
+            });
+            println!(
+                "{}{:#018x} <+{}>:\t{}",
+                if Some(addr) == pc { "=> " } else { "   " },
+                addr,
+                addr - start,
+                instruction.text
+            );
+            addr += instruction.len;
+        }
+        println!("End of assembler dump.");
+    }
+
+    /// The pc of the selected frame, if the program is running.
+    fn current_pc(&self) -> Option<usize> {
+        self.process()?;
+        eval::Context::read_register(self, "rip")
+            .ok()
+            .map(|pc| pc as usize)
+    }
+
+    /// Reads code for disassembly: from the inferior while it runs, with the original bytes in
+    /// place of our breakpoints, from the core file if there is one, and from the executable
+    /// otherwise.
+    fn read_code(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        let target = match self.process() {
+            Some(target) => target,
+            None => {
+                return self
+                    .dwarf_data
+                    .read_text(addr, len)
+                    .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+            }
+        };
+        let mut bytes = target
+            .read_memory(addr, len)
+            .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+        if self.inferior.is_none() {
+            return Ok(bytes);
+        }
+        for bp in &self.break_points {
+            if bp.enabled && addr <= bp.addr && bp.addr < addr + len {
+                let byte = &mut bytes[bp.addr - addr];
+                if *byte == 0xcc {
+                    *byte = bp.orig_byte;
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn info_registers(&self, names: Vec<String>) {
+        if self.process().is_none() {
+            println!("The program has no registers now.");
+            return;
+        }
+        let names: Vec<String> = if names.is_empty() {
+            REGISTER_NAMES.iter().map(|name| name.to_string()).collect()
+        } else {
+            names
+        };
+        for name in names {
+            let value = match eval::Context::read_register(self, &name) {
+                Ok(value) => value,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            };
+            // the natural column: code addresses get a symbol, data addresses stay hex
+            let natural = match name.as_str() {
+                "rip" | "pc" => self.format_address(value as usize),
+                "rsp" | "rbp" | "sp" | "fp" | "fs_base" | "gs_base" => format!("{:#x}", value),
+                "eflags" => registers::format_eflags(value),
+                _ => (value as i64).to_string(),
+            };
+            println!("{:<15}{:<19}{}", name, format!("{:#x}", value), natural);
+        }
+    }
+
+    fn watch_command(&mut self, expr: Option<String>, access: bool) {
+        let expr = match expr {
+            Some(expr) => expr,
+            None => {
+                println!("Argument required (expression to compute).");
+                return;
+            }
+        };
+        if self.watch_points.iter().filter(|wp| wp.enabled).count() >= MAX_HW_WATCH_POINTS {
+            println!(
+                "Only {} hardware watchpoints can be enabled at once; delete or disable one first.",
+                MAX_HW_WATCH_POINTS
+            );
+            return;
+        }
+        let (addr, entity_type, scope) = match self.resolve_watch_expr(&expr) {
+            Ok(location) => location,
+            Err(err) => {
+                println!("Cannot watch {}: {}", expr, err);
+                return;
+            }
+        };
+        if let Err(err) = check_watchable(addr, entity_type.size) {
+            println!("Cannot watch {}: {}", expr, err);
+            return;
+        }
+        let old_value = match &self.inferior {
+            Some(inferior) => match inferior.read_memory(addr, entity_type.size) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    println!("Cannot watch {}: read {:#x} fail {}", expr, addr, err);
+                    return;
+                }
+            },
+            None => Vec::new(),
+        };
+        let num = self.next_break_point_num;
+        self.next_break_point_num += 1;
+        self.watch_points.push(Watchpoint {
+            num,
+            expr: expr.clone(),
+            addr,
+            entity_type,
+            access,
+            old_value,
+            scope,
+            enabled: true,
+            hit_count: 0,
+        });
+        if access {
+            println!("Hardware access (read/write) watchpoint {}: {}", num, expr);
+        } else {
+            println!("Hardware watchpoint {}: {}", num, expr);
+        }
+        self.arm_watch_points();
+    }
+
+    /// Works out the address, type and (for locals) owning frame of a watch expression: either a
+    /// variable name or `*addr`.
+    fn resolve_watch_expr(&self, expr: &str) -> Result<(usize, Type, Option<WatchScope>), String> {
+        if expr.starts_with('*') {
+            let addr = parse_address(&expr[1..])
+                .ok_or_else(|| format!("invalid address {}", &expr[1..]))?;
+            // a bare address has no type, so watch the widest naturally aligned integer there
+            let entity_type = match addr % 8 {
+                0 => Type::base("long", 8, Encoding::Signed),
+                4 => Type::base("int", 4, Encoding::Signed),
+                2 | 6 => Type::base("short", 2, Encoding::Signed),
+                _ => Type::base("char", 1, Encoding::SignedChar),
+            };
+            return Ok((addr, entity_type, None));
+        }
+        let frame = match &self.inferior {
+            Some(_) => Some(
+                self.current_frame()
+                    .map_err(|err| format!("read registers fail {}", err))?,
+            ),
+            None => None,
+        };
+        // without a process there is no frame, so only globals can be found
+        let pc = frame.map_or(0, |(_, pc)| pc);
+        let var = self
+            .dwarf_data
+            .get_variable(pc, expr)
+            .ok_or_else(|| format!("No symbol \"{}\" in current context.", expr))?;
+        match (&var.location, frame) {
+            (Location::Address(_), _) => Ok((
+                self.dwarf_data.variable_address(var, 0),
+                var.entity_type.clone(),
+                None,
+            )),
+            (Location::FramePointerOffset(_), Some((frame, _))) => {
+                let cfa = self.dwarf_data.frame_cfa(&frame, self.selected_frame == 0);
+                let scope = WatchScope {
+                    cfa,
+                    function: self
+                        .dwarf_data
+                        .get_function_from_addr(pc)
+                        .unwrap_or_default(),
+                };
+                Ok((
+                    self.dwarf_data.variable_address(var, cfa),
+                    var.entity_type.clone(),
+                    Some(scope),
+                ))
+            }
+            (Location::FramePointerOffset(_), None) => Err("No process is running".to_string()),
+        }
+    }
+
+    /// Arms watchpoints in a newly started or attached inferior. Watched locals belonged to
+    /// frames of the old process, so they are dropped.
+    fn insert_watch_points(&mut self) {
+        for wp in self.watch_points.iter().filter(|wp| wp.scope.is_some()) {
+            println!(
+                "Watchpoint {} deleted because the process it was set in is gone.",
+                wp.num
+            );
+        }
+        self.watch_points.retain(|wp| wp.scope.is_none());
+        let inferior = self.inferior.as_ref().unwrap();
+        for wp in self.watch_points.iter_mut() {
+            wp.hit_count = 0;
+            wp.old_value = inferior.read_memory(wp.addr, wp.size()).unwrap_or_default();
+        }
+        self.arm_watch_points();
+    }
+
+    fn arm_watch_points(&mut self) {
+        if let Err(err) = self.update_debug_registers() {
+            println!("Cannot update hardware watchpoints: {}", err);
+        }
+    }
+
+    /// Loads the enabled watchpoints into DR0-DR3, in order, and sets DR7 to match.
+    fn update_debug_registers(&mut self) -> Result<(), nix::Error> {
+        let inferior = match self.inferior.as_mut() {
+            Some(inferior) => inferior,
+            None => return Ok(()),
+        };
+        let mut dr7 = 0;
+        let enabled = self.watch_points.iter().filter(|wp| wp.enabled);
+        for (slot, wp) in enabled.take(MAX_HW_WATCH_POINTS).enumerate() {
+            inferior.write_debug_register(slot, wp.addr as u64)?;
+            dr7 |= dr7_bits(slot, wp.size(), wp.access);
+        }
+        inferior.write_debug_register(DR_CONTROL, dr7)
+    }
+
+    /// Checks DR6 after a SIGTRAP to see whether a watchpoint fired, and reports it. Returns None
+    /// if the trap wasn't a watchpoint, otherwise whether to stop: a write watchpoint whose value
+    /// didn't change doesn't stop, while a watched local whose frame is gone does (after deleting
+    /// the watchpoint).
+    fn watch_point_triggered(&mut self) -> Result<Option<bool>, nix::Error> {
+        if !self.watch_points.iter().any(|wp| wp.enabled) {
+            return Ok(None);
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        let dr6 = inferior.read_debug_register(DR_STATUS)?;
+        if dr6 & 0xf == 0 {
+            return Ok(None);
+        }
+        // the CPU never clears DR6 itself
+        inferior.write_debug_register(DR_STATUS, 0)?;
+
+        let enabled: Vec<usize> = (0..self.watch_points.len())
+            .filter(|index| self.watch_points[*index].enabled)
+            .collect();
+        let mut stop = false;
+        let mut out_of_scope = Vec::new();
+        for (slot, index) in enabled.into_iter().enumerate() {
+            if dr6 & (1 << slot) == 0 {
+                continue;
+            }
+            let wp = self.watch_points[index].clone();
+            if let Some(scope) = &wp.scope {
+                if !self.frame_is_live(scope)? {
+                    println!(
+                        "\nWatchpoint {} deleted because the program has left the block in\n\
+                         which its expression is valid.",
+                        wp.num
+                    );
+                    out_of_scope.push(index);
+                    stop = true;
+                    continue;
+                }
+            }
+            let new_value = self
+                .inferior
+                .as_ref()
+                .unwrap()
+                .read_memory(wp.addr, wp.size())?;
+            if !wp.access && new_value == wp.old_value {
+                continue;
+            }
+            let old = format_value(self, &wp.entity_type, &wp.old_value);
+            let new = format_value(self, &wp.entity_type, &new_value);
+            if wp.access {
+                println!(
+                    "\nHardware access (read/write) watchpoint {}: {}\n",
+                    wp.num, wp.expr
+                );
+                if new_value == wp.old_value {
+                    println!("Value = {}", new);
+                } else {
+                    println!("Old value = {}\nNew value = {}", old, new);
+                }
+            } else {
+                println!("\nHardware watchpoint {}: {}\n", wp.num, wp.expr);
+                println!("Old value = {}\nNew value = {}", old, new);
+            }
+            let wp = &mut self.watch_points[index];
+            wp.old_value = new_value;
+            wp.hit_count += 1;
+            stop = true;
+        }
+        if !out_of_scope.is_empty() {
+            for index in out_of_scope.into_iter().rev() {
+                self.watch_points.remove(index);
+            }
+            self.update_debug_registers()?;
+        }
+        Ok(Some(stop))
+    }
+
+    /// Walks the stack looking for the frame a watched local belongs to. The stack slot may
+    /// have been reused by a later call, so the function has to match as well as the CFA.
+    fn frame_is_live(&self, scope: &WatchScope) -> Result<bool, nix::Error> {
+        let (frames, _) = self.unwind_stack()?;
+        for (num, frame) in frames.iter().enumerate() {
+            let cfa = self.dwarf_data.frame_cfa(frame, num == 0);
+            if cfa == scope.cfa {
+                let pc = frame.lookup_pc(num == 0);
+                return Ok(
+                    self.dwarf_data.get_function_from_addr(pc).as_ref() == Some(&scope.function)
+                );
+            }
+            if cfa > scope.cfa {
+                break;
+            }
+        }
+        Ok(false)
+    }
+
+    fn catch_syscall_command(&mut self, names: Vec<String>) {
+        let mut syscalls = Vec::new();
+        for name in &names {
+            match syscall::number(name) {
+                Some(number) => syscalls.push(number),
+                None if name.parse::<u64>().is_ok() => {
+                    println!("Unknown syscall number '{}'.", name);
+                    return;
+                }
+                None => {
+                    println!("Unknown syscall name '{}'.", name);
+                    return;
+                }
+            }
+        }
+        let num = self.next_break_point_num;
+        self.next_break_point_num += 1;
+        let catch_point = Catchpoint {
+            num,
+            syscalls,
+            enabled: true,
+            hit_count: 0,
+        };
+        println!("Catchpoint {} ({})", num, catch_point.describe());
+        self.catch_points.push(catch_point);
+    }
+
+    /// Reports a syscall stop of the current thread if an enabled catchpoint wants it, and
+    /// returns whether it did.
+    fn syscall_caught(&mut self, entering: bool) -> Result<bool, nix::Error> {
+        let regs = ptrace::getregs(self.inferior.as_ref().unwrap().tid())?;
+        // rax holds the return value by now, but the kernel keeps the number in orig_rax
+        let number = regs.orig_rax;
+        let catch_point = match self.catch_points.iter_mut().find(|cp| cp.catches(number)) {
+            Some(catch_point) => catch_point,
+            None => return Ok(false),
+        };
+        catch_point.hit_count += 1;
+        let num = catch_point.num;
+        let name = syscall::display_name(number);
+        if entering {
+            let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+            println!(
+                "\nCatchpoint {} (call to syscall {}), {}",
+                num,
+                name,
+                syscall::format_call(self, number, args)
+            );
+        } else {
+            println!(
+                "\nCatchpoint {} (returned from syscall {}) = {}",
+                num,
+                name,
+                syscall::format_return(number, regs.rax)
+            );
+        }
+        Ok(true)
+    }
+
+    /// Evaluates an expression in the selected frame. Assignments write to the inferior.
+    fn evaluate(&mut self, expr: &str) -> Result<eval::Value, String> {
+        eval::evaluate(self, &eval::parse(expr)?)
+    }
+
+    /// Returns the registers of the selected frame, along with the pc to resolve its scope with.
+    /// For outer frames that is the call instruction rather than the return address.
+    fn current_frame(&self) -> Result<(Frame, usize), nix::Error> {
+        if self.selected_frame > 0 {
+            let frame = self.frames[self.selected_frame];
+            return Ok((frame, frame.lookup_pc(false)));
+        }
+        let registers = self.process().unwrap().get_registers()?;
+        let frame = Frame {
+            pc: registers.rip as usize,
+            rsp: registers.rsp as usize,
+            rbp: registers.rbp as usize,
+        };
+        Ok((frame, frame.pc))
+    }
+
+    fn invalidate_frames(&mut self) {
+        self.frames.clear();
+        self.selected_frame = 0;
+        self.last_listed = None;
+    }
+
+    /// Unwinds the stack into `frames` unless that was already done since the last stop.
+    fn load_frames(&mut self) -> Result<(), nix::Error> {
+        if self.frames.is_empty() {
+            // a partial stack is still worth navigating, so unwinding errors are left to bt
+            self.frames = self.unwind_stack()?.0;
+        }
+        Ok(())
+    }
+
+    fn frame_command(&mut self, num: Option<String>) {
+        if self.process().is_none() {
+            println!("No stack.");
+            return;
+        }
+        if let Err(err) = self.load_frames() {
+            println!("frame command fail {}", err);
+            return;
+        }
+        if let Some(num) = num {
+            match num.parse::<usize>() {
+                Ok(num) if num < self.frames.len() => self.selected_frame = num,
+                Ok(_) => {
+                    println!("No frame at level {}.", num);
+                    return;
+                }
+                Err(_) => {
+                    println!("Invalid frame number \"{}\".", num);
+                    return;
+                }
+            }
+        }
+        self.print_frame(self.selected_frame);
+    }
+
+    /// Moves the selected frame `count` frames outwards (`direction` 1, for up) or inwards (-1,
+    /// for down).
+    fn move_frame_command(&mut self, count: Option<String>, direction: isize) {
+        if self.process().is_none() {
+            println!("No stack.");
+            return;
+        }
+        let count = match count.map(|count| count.parse::<isize>()) {
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                println!("Invalid frame count.");
+                return;
+            }
+            None => 1,
+        };
+        if let Err(err) = self.load_frames() {
+            println!("frame command fail {}", err);
+            return;
+        }
+        let target = self.selected_frame as isize + direction * count;
+        if target < 0 {
+            println!("Bottom (innermost) frame selected; you cannot go down.");
+            return;
+        }
+        if target as usize >= self.frames.len() {
+            println!("Initial frame selected; you cannot go up.");
+            return;
+        }
+        self.selected_frame = target as usize;
+        self.print_frame(self.selected_frame);
+    }
+
+    /// Shows a newly selected frame along with its source line, which `list` then centers on.
+    fn print_frame(&mut self, num: usize) {
+        println!("{}", self.format_frame(num));
+        self.last_listed = None;
+        if let Some(line) = self.current_source_line() {
+            println!("{}", self.format_source_line(&line.file, line.number));
+        }
+    }
+
+    /// Prints the locals (or, with `args`, the parameters) of the selected frame's function.
+    fn info_variables(&self, args: bool) {
+        if self.process().is_none() {
+            println!("No frame selected.");
+            return;
+        }
+        let (frame, pc) = match self.current_frame() {
+            Ok(current) => current,
+            Err(err) => {
+                println!("read registers fail {}", err);
+                return;
+            }
+        };
+        let variables = match self.dwarf_data.get_function_variables(pc) {
+            Some(variables) => variables,
+            None => {
+                println!("No symbol table info available.");
+                return;
+            }
+        };
+        let cfa = self.dwarf_data.frame_cfa(&frame, self.selected_frame == 0);
+        let mut found = false;
+        for var in variables.iter().filter(|var| var.is_parameter == args) {
+            found = true;
+            let addr = self.dwarf_data.variable_address(var, cfa);
+            match self
+                .process()
+                .unwrap()
+                .read_memory(addr, var.entity_type.size)
+            {
+                Ok(bytes) => println!(
+                    "{} = {}",
+                    var.name,
+                    format_value(self, &var.entity_type, &bytes)
+                ),
+                Err(err) => println!("{} = <error reading {:#x}: {}>", var.name, addr, err),
+            }
+        }
+        if !found {
+            println!("{}", if args { "No arguments." } else { "No locals." });
+        }
+    }
+
+    fn back_trace(&mut self) {
+        if self.process().is_none() {
+            println!("No process is running");
+            return;
+        }
+        let (frames, err) = match self.unwind_stack() {
+            Ok(frames) => frames,
+            Err(err) => {
+                println!("backtrace fail {}", err);
+                return;
+            }
+        };
+        self.frames = frames;
+        if self.selected_frame >= self.frames.len() {
+            self.selected_frame = 0;
+        }
+        for num in 0..self.frames.len() {
+            println!("{}", self.format_frame(num));
+        }
+        if let Some(err) = err {
+            println!("Backtrace stopped: {}", err);
+        }
+    }
+
+    fn format_frame(&self, num: usize) -> String {
+        let frame = &self.frames[num];
+        // outer frames are shown at their call rather than at the return address
+        let pc = frame.lookup_pc(num == 0);
+        let func = self.dwarf_data.get_function_from_addr(pc);
+        let line = self.dwarf_data.get_line_from_addr(pc);
+        match (func, line) {
+            (Some(func), Some(line)) => {
+                format!("#{:<3}{} ({}:{})", num, func, line.file, line.number)
+            }
+            (Some(func), None) => format!("#{:<3}{} ({:#x})", num, func, frame.pc),
+            (None, _) => format!("#{:<3}?? ({:#x})", num, frame.pc),
+        }
+    }
 
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<()>::new();
-        // Attempt to load history from ~/.deet_history if it exists
-        let _ = readline.load_history(&history_path);
+    /// Lists the inferior's threads with where each of them is, marking the selected one.
+    fn info_threads(&self) {
+        // the number, tid and registers of each thread, and the number of the selected one
+        let (threads, selected) = match (&self.inferior, &self.core) {
+            (Some(inferior), _) => (
+                inferior
+                    .threads()
+                    .iter()
+                    .map(|thread| (thread.num, thread.tid, ptrace::getregs(thread.tid)))
+                    .collect::<Vec<_>>(),
+                inferior.current_thread().map(|thread| thread.num),
+            ),
+            (None, Some(core)) => (
+                core.threads()
+                    .into_iter()
+                    .map(|(num, tid, registers)| (num, tid, Ok(registers)))
+                    .collect(),
+                Some(core.current_thread().0),
+            ),
+            (None, None) => {
+                println!("No threads.");
+                return;
+            }
+        };
+        println!("  Id   Target Id      Frame");
+        for (num, tid, registers) in threads {
+            let marker = if Some(num) == selected { '*' } else { ' ' };
+            let frame = match registers {
+                Ok(registers) => {
+                    let pc = registers.rip as usize;
+                    let func = self.dwarf_data.get_function_from_addr(pc);
+                    match (func, self.dwarf_data.get_line_from_addr(pc)) {
+                        (Some(func), Some(line)) => {
+                            format!("{} ({}:{})", func, line.file, line.number)
+                        }
+                        (Some(func), None) => format!("{} ({:#x})", func, pc),
+                        (None, _) => format!("?? ({:#x})", pc),
+                    }
+                }
+                // a thread that has only just been created may not have stopped yet
+                Err(err) => format!("(running: {})", err),
+            };
+            let target = format!("LWP {}", tid);
+            println!("{} {:<4} {:<14} {}", marker, num, target, frame);
+        }
+    }
 
-        Debugger {
-            target: target.to_string(),
-            history_path,
-            readline,
-            inferior: None,
-            dwarf_data: dwarf,
-            break_points: HashMap::new(),
+    /// `thread N` selects thread N and shows its innermost frame; without an argument it shows
+    /// which thread is selected.
+    fn thread_command(&mut self, num: Option<String>) {
+        let current = match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior
+                .current_thread()
+                .map(|thread| (thread.num, thread.tid)),
+            (None, Some(core)) => Some(core.current_thread()),
+            (None, None) => {
+                println!("No thread selected.");
+                return;
+            }
+        };
+        let num = match num {
+            Some(num) => num,
+            None => {
+                if let Some((num, tid)) = current {
+                    println!("[Current thread is {} (LWP {})]", num, tid);
+                }
+                return;
+            }
+        };
+        let selected = num.parse::<usize>().ok().and_then(|num| {
+            match (self.inferior.as_mut(), self.core.as_mut()) {
+                (Some(inferior), _) => inferior.select_thread(num),
+                (None, Some(core)) => core.select_thread(num),
+                (None, None) => None,
+            }
+        });
+        let tid = match selected {
+            Some(tid) => tid,
+            None => {
+                println!("Invalid thread ID: {}", num);
+                return;
+            }
+        };
+        println!("[Switching to thread {} (LWP {})]", num, tid);
+        self.invalidate_frames();
+        if let Err(err) = self.load_frames() {
+            println!("thread command fail {}", err);
+            return;
         }
+        self.print_frame(0);
     }
 
-    pub fn run(&mut self) {
-        loop {
-            match self.get_next_command() {
-                DebuggerCommand::Run(args) => self.run_command(args),
-                DebuggerCommand::Quit => {
-                    if let Some(inferior) = self.inferior.as_mut() {
-                        inferior.kill();
-                    }
-                    return;
+    /// Unwinds the inferior's stack with the call frame information, innermost frame first,
+    /// stopping at main like gdb does. If unwinding fails part way, the frames found so far are
+    /// returned along with the reason.
+    fn unwind_stack(&self) -> Result<(Vec<Frame>, Option<String>), nix::Error> {
+        let target = self.process().unwrap();
+        let registers = target.get_registers()?;
+        let mut frames = vec![Frame {
+            pc: registers.rip as usize,
+            rsp: registers.rsp as usize,
+            rbp: registers.rbp as usize,
+        }];
+        let read_word = |addr: usize| {
+            target
+                .read_memory(addr, 8)
+                .map(|word| read_unsigned(&word, 8) as usize)
+                .map_err(|_| format!("Cannot access memory at address {:#x}", addr))
+        };
+        // call frame information of shared libraries, loaded as the walk reaches them
+        let mut libraries: Vec<LibraryCfi> = Vec::new();
+        while frames.len() < MAX_FRAMES {
+            let frame = frames[frames.len() - 1];
+            let innermost = frames.len() == 1;
+            let pc = frame.lookup_pc(innermost);
+            if self.dwarf_data.get_function_from_addr(pc).as_deref() == Some("main") {
+                break;
+            }
+            let mut unwound = self.dwarf_data.unwind_frame(&frame, innermost, read_word);
+            if let Ok(Unwound::NoInfo) = unwound {
+                if !libraries.iter().any(|lib| lib.contains(pc)) {
+                    libraries.push(self.load_library_cfi(pc));
+                }
+                let library = libraries.iter().find(|lib| lib.contains(pc)).unwrap();
+                if let Some(cfi) = &library.cfi {
+                    unwound = cfi.unwind(&frame, pc - library.base, read_word);
                 }
-                DebuggerCommand::Cont => {
-                    if let Err(err) = self.cont_command() {
-                        println!("continue command fail {}", err);
+            }
+            if let Ok(Unwound::NoInfo) = unwound {
+                unwound = unwind_frame_pointer(&frame, read_word);
+            }
+            match unwound {
+                Ok(Unwound::Caller(caller)) if caller.pc != 0 => {
+                    if caller.rsp <= frame.rsp {
+                        let err = "previous frame inner to this frame (corrupt stack?)";
+                        return Ok((frames, Some(err.to_string())));
                     }
-                },
-                DebuggerCommand::BackTrace => self.back_trace(),
-                DebuggerCommand::BreakPoint(pos) => self.break_point_command(pos),
+                    frames.push(caller);
+                }
+                Ok(_) => break,
+                Err(err) => return Ok((frames, Some(err))),
             }
         }
+        Ok((frames, None))
     }
 
-    fn run_command(&mut self, args: Vec<String>) {
-        if let Some(infer) = self.inferior.as_mut() {
-            infer.kill();
+    /// Finds the file mapped at `pc` and loads its call frame information.
+    fn load_library_cfi(&self, pc: usize) -> LibraryCfi {
+        let maps = self.process().unwrap().memory_maps().unwrap_or_default();
+        let path = match maps.iter().find(|map| map.start <= pc && pc < map.end) {
+            Some(map) if map.path.starts_with('/') => map.path.clone(),
+            _ => {
+                return LibraryCfi {
+                    start: pc,
+                    end: pc + 1,
+                    base: 0,
+                    cfi: None,
+                }
+            }
+        };
+        let mappings: Vec<_> = maps.iter().filter(|map| map.path == path).collect();
+        let start = mappings.iter().map(|map| map.start).min().unwrap_or(pc);
+        let end = mappings.iter().map(|map| map.end).max().unwrap_or(pc + 1);
+        // shared libraries are linked at zero, so the mapping of the file's start is the base
+        let base = mappings
+            .iter()
+            .find(|map| map.offset == 0)
+            .map_or(start, |map| map.start);
+        LibraryCfi {
+            start,
+            end,
+            base,
+            cfi: CallFrameInfo::from_file(&path),
         }
+    }
 
-        if let Some(inferior) = Inferior::new(&self.target, &args, &mut self.break_points) {
-            self.inferior = Some(inferior);
-            self.cont_run();
-        } else {
-            println!("Error starting subprocess");
+    fn cont_run(&mut self) {
+        if self.inferior.is_none() {
+            println!("No process is running");
+            return;
         }
+        match self.resume(None) {
+            Ok(status) => println!("Child {}", self.format_status(&status)),
+            Err(err) => println!("wait fail {}", err),
+        };
     }
 
-    fn cont_command(&mut self) -> Result<(), nix::Error> {
-        if self.inferior.is_none() {
-            println!("no process in debugger");
-            return Ok(());
+    /// Continues the inferior until it stops somewhere the user cares about. A stop in another
+    /// thread than the selected one switches to it, as gdb does.
+    fn resume(&mut self, temp_addr: Option<usize>) -> Result<Status, nix::Error> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let selected = (inferior.pid(), inferior.tid());
+        let status = self.resume_until_stop(temp_addr)?;
+        if let (Status::Stopped(..), Some(inferior)) = (&status, self.inferior.as_ref()) {
+            let thread = inferior.current_thread().unwrap();
+            // a fork we followed or a held process switches processes instead
+            if inferior.pid() == selected.0 && thread.tid != selected.1 {
+                println!("[Switching to thread {} (LWP {})]", thread.num, thread.tid);
+            }
         }
-        let inferior = self.inferior.as_mut().unwrap();
-        let pid = inferior.pid();
-        let mut registers = ptrace::getregs(pid)?;
-        let rip = registers.rip - 1;
-        let break_pointer = self.break_points.get(&(rip as usize));
+        Ok(status)
+    }
 
-        if break_pointer.is_none() {
-            self.cont_run();
-            return Ok(());
+    /// Breakpoints whose condition is false or whose ignore count hasn't run out are stepped over
+    /// transparently, as are syscalls no catchpoint is after.
+    fn resume_until_stop(&mut self, temp_addr: Option<usize>) -> Result<Status, nix::Error> {
+        self.invalidate_frames();
+        self.stop_break_points.clear();
+        loop {
+            self.cont_inferior()?;
+            let status = self.wait(temp_addr)?;
+            match status {
+                Status::SyscallEntry(_) if !self.syscall_caught(true)? => continue,
+                Status::SyscallExit(_) if !self.syscall_caught(false)? => continue,
+                _ => {}
+            }
+            if let Status::Stopped(signal::Signal::SIGTRAP, rip) = status {
+                if Some(rip) != temp_addr && self.break_point_at(rip).is_some() {
+                    if !self.should_stop_at(rip) {
+                        if let Some(status) = self.step_over_break_point()? {
+                            return Ok(status);
+                        }
+                        continue;
+                    }
+                } else if self.watch_point_triggered()? == Some(false) {
+                    // a write that left the value unchanged
+                    continue;
+                }
+            }
+            return Ok(status);
         }
+    }
 
-        let address = break_pointer.unwrap().addr;
-        let orig_byte = break_pointer.unwrap().orig_byte;
+    /// Waits for the inferior to stop. When it traps on one of our breakpoints (or on
+    /// `temp_addr`), rip is moved back onto the 0xcc so it points at the original instruction.
+    fn wait(&mut self, temp_addr: Option<usize>) -> Result<Status, nix::Error> {
+        let status = self.wait_inferior(false)?;
+        // where the int3 would be; rip may be 0 after a jump through a null pointer
+        let trap_addr = match status {
+            Status::Stopped(signal::Signal::SIGTRAP, rip) => rip.checked_sub(1),
+            _ => None,
+        };
+        match trap_addr {
+            Some(addr) if self.break_point_at(addr).is_some() || temp_addr == Some(addr) => {
+                let pid = self.inferior.as_ref().unwrap().tid();
+                let mut registers = ptrace::getregs(pid)?;
+                registers.rip = addr as u64;
+                ptrace::setregs(pid, registers)?;
+                Ok(Status::Stopped(signal::Signal::SIGTRAP, addr))
+            }
+            _ => Ok(status),
+        }
+    }
 
-        inferior.write_byte(address, orig_byte)?;
+    /// Waits for the inferior to stop or end, dealing with forks and execs along the way.
+    /// `stepping` says how to carry on after one of those: single-step the current thread, or
+    /// continue them all.
+    fn wait_inferior(&mut self, stepping: bool) -> Result<Status, nix::Error> {
+        loop {
+            let status = self.inferior.as_mut().unwrap().wait()?;
+            match status {
+                Status::Stopped(signal::Signal::SIGTRAP, _)
+                | Status::SyscallEntry(_)
+                | Status::SyscallExit(_) => return Ok(status),
+                Status::Stopped(signal, _) => {
+                    let policy = self.signals.get(signal);
+                    // passed on when the thread resumes, unless `signal` says otherwise
+                    let pass = if policy.pass { Some(signal) } else { None };
+                    self.inferior.as_mut().unwrap().set_signal(pass);
+                    if policy.stop {
+                        return Ok(status);
+                    }
+                    if policy.print {
+                        println!(
+                            "\nProgram received signal {}, {}.",
+                            signal.as_str(),
+                            signals::description(signal)
+                        );
+                    }
+                }
+                Status::Exited(_) | Status::Signaled(_) => {
+                    self.exit_status = match status {
+                        Status::Signaled(signal) => 128 + signal as i32,
+                        Status::Exited(code) => code,
+                        _ => unreachable!(),
+                    };
+                    self.process_ended();
+                    return Ok(status);
+                }
+                Status::Forked(child, vfork) => self.follow_fork(child, vfork)?,
+                Status::VforkDone => {
+                    // the child no longer shares our memory, so the breakpoints can go back in
+                    let inferior = self.inferior.as_mut().unwrap();
+                    patch_break_points(inferior, &self.break_points, self.temp_break_point, true)?;
+                }
+                Status::Execed => {
+                    self.follow_exec();
+                    if stepping {
+                        let inferior = self.inferior.as_ref().unwrap();
+                        let rip = ptrace::getregs(inferior.tid())?.rip as usize;
+                        return Ok(Status::Stopped(signal::Signal::SIGTRAP, rip));
+                    }
+                }
+            }
+            if stepping {
+                self.inferior.as_mut().unwrap().step()?;
+            } else {
+                self.cont_inferior()?;
+            }
+        }
+    }
 
-        registers.rip = rip;
-        ptrace::setregs(pid, registers)?;
+    /// Continues every thread, having them stop at the syscalls enabled catchpoints are after.
+    /// The inferior may have changed since the last continue, after a fork.
+    fn cont_inferior(&mut self) -> Result<(), nix::Error> {
+        let syscalls = syscall::caught_syscalls(&self.catch_points);
+        let inferior = self.inferior.as_mut().unwrap();
+        inferior.trace_syscalls(syscalls);
+        inferior.cont()
+    }
 
-        // step forward
-        ptrace::step(pid, None)?;
-        let status = inferior.wait(None)?;
-        if let Status::Exited(_) = status {
-            self.inferior = None;
-            return Ok(());
+    /// Prints the signal table, or the row of one signal.
+    fn info_signals(&self, name: Option<String>) {
+        let selected = match name {
+            Some(name) => match signals::parse_signal(&name) {
+                Some(signal) => vec![signal],
+                None => {
+                    println!("Bad signal name: {}.", name);
+                    return;
+                }
+            },
+            None => signal::Signal::iterator().collect(),
+        };
+        println!("{}\n", signals::TABLE_HEADER);
+        for signal in selected {
+            println!("{}", self.signals.format_row(signal));
         }
+    }
 
-        // wite back  debug command
-        inferior.write_byte(address, 0xcc)?;
-        self.cont_run();
-
-        Ok(())
+    /// `handle SIGNAL... [stop|nostop|print|noprint|pass|nopass]...`, where `all` stands for
+    /// every signal but the two the debugger uses itself.
+    fn handle_command(&mut self, args: Vec<String>) {
+        if args.is_empty() {
+            println!("Argument required (signal to handle).");
+            return;
+        }
+        let mut selected = Vec::new();
+        let mut actions = Vec::new();
+        for arg in &args {
+            if arg == "all" {
+                selected.extend(signal::Signal::iterator().filter(|signal| {
+                    *signal != signal::Signal::SIGTRAP && *signal != signal::Signal::SIGINT
+                }));
+            } else if let Some(signal) = signals::parse_signal(arg) {
+                selected.push(signal);
+            } else if signals::ACTIONS.contains(&arg.as_str()) {
+                actions.push(arg.as_str());
+            } else {
+                println!("Unrecognized or ambiguous flag word: \"{}\".", arg);
+                return;
+            }
+        }
+        for signal in &selected {
+            for action in &actions {
+                self.signals.apply(*signal, action);
+            }
+        }
+        println!("{}", signals::TABLE_HEADER);
+        for signal in selected {
+            println!("{}", self.signals.format_row(signal));
+        }
     }
 
-    fn break_point_command(&mut self, position: Option<String>) {
-        if position.is_none() {
-            println!("please input position");
+    /// Continues the inferior with `SIG` delivered instead of the signal it stopped with, or
+    /// with no signal at all for `signal 0`.
+    fn signal_command(&mut self, name: Option<String>) {
+        if self.inferior.is_none() {
+            println!("The program is not being run.");
             return;
         }
-        let str = position.unwrap();
-        if let Some(addr) = self.parse_address(&str) {
-            self.break_points.insert(addr, Breakpoint { addr, orig_byte: 0 });
+        let name = match name {
+            Some(name) => name,
+            None => {
+                println!("Argument required (signal number).");
+                return;
+            }
+        };
+        let signal = if name == "0" {
+            None
+        } else {
+            match signals::parse_signal(&name) {
+                Some(signal) => Some(signal),
+                None => {
+                    println!("Bad signal name: {}.", name);
+                    return;
+                }
+            }
+        };
+        match signal {
+            Some(signal) => println!("Continuing with signal {}.", signal.as_str()),
+            None => println!("Continuing with no signal."),
+        }
+        self.inferior.as_mut().unwrap().set_signal(signal);
+        if let Err(err) = self.cont_command() {
+            println!("cont command fail {}", err);
+        }
+    }
+
+    /// `set follow-fork-mode parent|child` and `set detach-on-fork on|off`.
+    fn set_command(&mut self, setting: &str, value: Option<String>) {
+        let value = value.unwrap_or_default();
+        match setting {
+            "follow-fork-mode" => match value.as_str() {
+                "parent" => self.follow_fork_child = false,
+                "child" => self.follow_fork_child = true,
+                "" => println!("Requires an argument. Valid arguments are child, parent."),
+                _ => println!("Undefined item: \"{}\".", value),
+            },
+            "detach-on-fork" => match value.as_str() {
+                "on" | "" => self.detach_on_fork = true,
+                "off" => self.detach_on_fork = false,
+                _ => println!("\"on\" or \"off\" expected."),
+            },
+            _ => println!("Undefined set command: \"{}\".", setting),
+        }
+    }
+
+    fn show_command(&self, setting: Option<String>) {
+        let show_follow_fork_mode = || {
+            println!(
+                "Debugger response to a program call of fork or vfork is \"{}\".",
+                if self.follow_fork_child {
+                    "child"
+                } else {
+                    "parent"
+                }
+            )
+        };
+        let show_detach_on_fork = || {
+            println!(
+                "Whether deet will detach the child of a fork is {}.",
+                if self.detach_on_fork { "on" } else { "off" }
+            )
+        };
+        match setting.as_deref() {
+            Some("follow-fork-mode") => show_follow_fork_mode(),
+            Some("detach-on-fork") => show_detach_on_fork(),
+            None => {
+                show_follow_fork_mode();
+                show_detach_on_fork();
+            }
+            Some(setting) => println!("Undefined show command: \"{}\".", setting),
+        }
+    }
+
+    /// Picks the side of a fork to keep debugging, per follow-fork-mode, and detaches the other
+    /// or holds on to it, per detach-on-fork.
+    fn follow_fork(&mut self, child: Pid, vfork: bool) -> Result<(), nix::Error> {
+        let kind = if vfork { "vfork" } else { "fork" };
+        let mut child = Inferior::from_fork(child);
+        if !self.follow_fork_child {
+            if !self.detach_on_fork {
+                println!(
+                    "[Keeping child process {} stopped after {}]",
+                    child.pid(),
+                    kind
+                );
+                self.hold(child)?;
+                return Ok(());
+            }
+            // the child inherited our breakpoints, and would die of SIGTRAP on hitting one. A
+            // vfork child shares our memory, so this takes them out of us too until VforkDone.
+            patch_break_points(&mut child, &self.break_points, self.temp_break_point, false)?;
+            child.detach()?;
+            println!(
+                "[Detaching after {} from child process {}]",
+                kind,
+                child.pid()
+            );
+            return Ok(());
+        }
+        let child_pid = child.pid();
+        let parent = self.inferior.replace(child).unwrap();
+        println!(
+            "[Attaching after process {} {} to child process {}]",
+            parent.pid(),
+            kind,
+            child_pid
+        );
+        self.invalidate_frames();
+        // debug registers aren't inherited
+        self.arm_watch_points();
+        if !self.detach_on_fork {
             println!(
-                "Set breakpoint {} at {}",
-                self.break_points.len() - 1,
-                addr
+                "[Keeping parent process {} stopped after {}]",
+                parent.pid(),
+                kind
             );
+            self.hold(parent)?;
+        } else if vfork {
+            self.vfork_parent = Some(parent);
         } else {
-            println!("parse address: {} fail", str);
+            self.detach_parent(parent, kind);
         }
+        Ok(())
     }
 
-    fn parse_address(&self, address: &str) -> Option<usize> {
-        parse_address(address);
-        if address.starts_with("*") {
-            return parse_address(&address[1..]);
+    /// Keeps the other side of a fork stopped for later. The temporary breakpoint of a `finish`
+    /// or `until` in progress is taken out of it, since only the followed process waits for it.
+    fn hold(&mut self, mut inferior: Inferior) -> Result<(), nix::Error> {
+        if let Some((addr, orig_byte)) = self.temp_break_point {
+            inferior.write_memory(addr, &[orig_byte])?;
         }
+        self.held.push(inferior);
+        Ok(())
+    }
 
-        let mut file = self.target.to_string();
-        file.push_str(".c");
-        if let Some(addr) = self.dwarf_data.get_addr_for_function(Some(&file), address) {
-            return Some(addr);
+    /// Lets the parent of a fork we followed the child of run on, without our breakpoints and
+    /// watchpoints.
+    fn detach_parent(&self, mut parent: Inferior, kind: &str) {
+        let temp = self.temp_break_point;
+        let result = patch_break_points(&mut parent, &self.break_points, temp, false)
+            .and_then(|_| parent.write_debug_register(DR_CONTROL, 0))
+            .and_then(|_| parent.detach());
+        match result {
+            Ok(()) => println!(
+                "[Detaching after {} from parent process {}]",
+                kind,
+                parent.pid()
+            ),
+            Err(err) => println!("Cannot detach parent process {}: {}", parent.pid(), err),
         }
+    }
 
-        let line = address.parse::<usize>();
-        if let Ok(num) = line {
-            return self.dwarf_data.get_addr_for_line(Some(&file), num);
+    /// Loads the program the inferior just exec'ed. Breakpoints are found again from their
+    /// locations, since the old addresses mean nothing in the new image.
+    fn follow_exec(&mut self) {
+        // the vfork child is done with the parent's memory
+        if let Some(parent) = self.vfork_parent.take() {
+            self.detach_parent(parent, "vfork");
         }
-        None
+        let pid = self.inferior.as_ref().unwrap().pid();
+        match exe_path(pid) {
+            Ok(target) => {
+                println!("process {} is executing new program: {}", pid, target);
+                match DwarfData::from_file(&target) {
+                    Ok(dwarf_data) => self.dwarf_data = dwarf_data,
+                    Err(err) => println!("Cannot load debug info from {}: {:?}", target, err),
+                }
+                self.target = target;
+            }
+            Err(err) => println!("Cannot read executable of process {}: {}", pid, err),
+        }
+        // the new image has no temporary breakpoint, and the old address means nothing in it
+        self.temp_break_point = None;
+        self.invalidate_frames();
+        self.update_load_base();
+        let mut index = 0;
+        while index < self.break_points.len() {
+            match self.parse_location(&self.break_points[index].location) {
+                Ok(addr) => {
+                    self.break_points[index].addr = addr;
+                    index += 1;
+                }
+                Err(err) => {
+                    println!(
+                        "Error in re-setting breakpoint {}: {}",
+                        self.break_points[index].num, err
+                    );
+                    self.break_points.remove(index);
+                }
+            }
+        }
+        let inferior = self.inferior.as_mut().unwrap();
+        for bp in self.break_points.iter_mut().filter(|bp| bp.enabled) {
+            match inferior.write_byte(bp.addr, 0xcc) {
+                Ok(orig_byte) => bp.orig_byte = orig_byte,
+                Err(err) => println!("Cannot insert breakpoint {}: {}", bp.num, err),
+            }
+        }
+        let mut index = 0;
+        while index < self.watch_points.len() {
+            let wp = &self.watch_points[index];
+            if wp.scope.is_some() {
+                // dropped, with a message, by insert_watch_points
+                index += 1;
+                continue;
+            }
+            match self.resolve_watch_expr(&wp.expr) {
+                Ok((addr, entity_type, _)) => {
+                    self.watch_points[index].addr = addr;
+                    self.watch_points[index].entity_type = entity_type;
+                    index += 1;
+                }
+                Err(err) => {
+                    println!("Watchpoint {} deleted: {}", wp.num, err);
+                    self.watch_points.remove(index);
+                }
+            }
+        }
+        self.insert_watch_points();
     }
 
-    fn back_trace(&mut self) {
-        if self.inferior.is_none() {
-            println!("No process is running");
-            return;
+    /// Forgets the inferior once it is gone. A process held stopped after a fork takes its
+    /// place, if there is one.
+    fn process_ended(&mut self) {
+        let pid = self.inferior.take().unwrap().pid();
+        if let Some(parent) = self.vfork_parent.take() {
+            self.detach_parent(parent, "vfork");
         }
-        let pid = self.inferior.as_ref().unwrap().pid();
-        let registers = ptrace::getregs(pid).unwrap();
-        let mut instruction_ptr = registers.rip as usize;
-        let mut base_ptr = registers.rbp as usize;
-        loop {
-            let path = self.dwarf_data.get_line_from_addr(instruction_ptr).unwrap();
-            let func = self
-                .dwarf_data
-                .get_function_from_addr(instruction_ptr)
-                .unwrap();
-            println!("{} ({}:{})", func, path.file, path.number);
-            if func == "main" {
-                break;
+        if let Some(inferior) = self.held.pop() {
+            println!(
+                "[Process {} is gone; switching to process {}, stopped after fork]",
+                pid,
+                inferior.pid()
+            );
+            self.inferior = Some(inferior);
+            self.arm_watch_points();
+        }
+    }
+
+    /// Kills the processes held stopped after forks.
+    fn kill_held(&mut self) {
+        for mut inferior in self.held.drain(..) {
+            inferior.kill();
+        }
+        if let Some(parent) = self.vfork_parent.take() {
+            self.detach_parent(parent, "vfork");
+        }
+    }
+
+    fn format_status(&self, status: &Status) -> String {
+        match status {
+            Status::Stopped(sig, rip) => format!(
+                "Stopped (status {})\n{}",
+                sig.as_str(),
+                self.format_stop_location(*rip)
+            ),
+            Status::SyscallEntry(rip) | Status::SyscallExit(rip) => {
+                format!("Stopped (syscall)\n{}", self.format_stop_location(*rip))
             }
-            instruction_ptr =
-                ptrace::read(pid, (base_ptr + 8) as ptrace::AddressType).unwrap() as usize;
-            base_ptr = ptrace::read(pid, base_ptr as ptrace::AddressType).unwrap() as usize;
+            Status::Exited(code) => format!("Exited (Status {})", code),
+            Status::Signaled(n) => format!("Signal (Status {})", n.as_str()),
+            // wait_inferior acts on these itself
+            Status::Forked(..) | Status::VforkDone | Status::Execed => String::new(),
         }
     }
 
-    fn cont_run(&mut self) {
-        if self.inferior.is_none() {
-            println!("No process is running");
-            return;
+    fn format_stop_location(&self, rip: usize) -> String {
+        let (file, num) = match self.dwarf_data.get_line_from_addr(rip) {
+            None => ("unknown".to_string(), 0),
+            Some(path) => (path.file, path.number),
+        };
+        let mut text = format!("Stopped at {}:{}", file, num);
+        if num > 0 {
+            text.push('\n');
+            text.push_str(&self.format_source_line(&file, num));
         }
-        ptrace::cont(self.inferior.as_mut().unwrap().pid(), None).ok();
-        match self.wait() {
-            Ok(ret) => println!("Child {}", ret),
-            Err(err) => println!("wait fail {}", err),
+        text
+    }
+
+    fn print_location(&self, rip: usize) {
+        let line = match self.dwarf_data.get_line_from_addr(rip) {
+            Some(line) => line,
+            None => {
+                println!("Stopped at {:#x}", rip);
+                return;
+            }
         };
+        if self.dwarf_data.is_line_start(rip) {
+            println!("Stopped at {}", line);
+        } else {
+            println!("Stopped at {:#x} in {}", rip, line);
+        }
+        println!("{}", self.format_source_line(&line.file, line.number));
     }
 
-    fn wait(&mut self) -> Result<String, nix::Error> {
-        let status = self.inferior.as_mut().unwrap().wait(None)?;
+    /// Formats one line of source the way gdb shows it on a stop: `26\t    x = 6;`.
+    fn format_source_line(&self, path: &str, number: usize) -> String {
+        match self.sources.get_lines(path) {
+            Some(lines) => match lines.get(number.wrapping_sub(1)) {
+                Some(text) => format!("{}\t{}", number, text),
+                None => format!(
+                    "Line number {} out of range; \"{}\" has {} lines.",
+                    number,
+                    path,
+                    lines.len()
+                ),
+            },
+            None => format!("{}\t{}: No such file or directory.", number, path),
+        }
+    }
 
-        let ret = match status {
-            Status::Stopped(sig, rip) => {
-                let (file, num) = match self.dwarf_data.get_line_from_addr(rip) {
-                    None => ("unknown".to_string(), 0 as usize),
-                    Some(path) => (path.file, path.number)
-                };
-                format!(
-                    "Stopped (status {})\nStopped at {}:{}",
-                    sig.as_str(),
-                    file,
-                    num
-                )
-            }
-            Status::Exited(code) => {
-                self.inferior = None;
-                format!("Exited (Status {})", code)
+    /// Source file and line of the selected frame.
+    fn current_source_line(&self) -> Option<Line> {
+        let pc = self.current_pc()?;
+        // outer frames are shown at their call rather than at the return address
+        let pc = if self.selected_frame > 0 {
+            pc.checked_sub(1)?
+        } else {
+            pc
+        };
+        self.dwarf_data.get_line_from_addr(pc)
+    }
+
+    fn list_command(&mut self, location: Option<String>) {
+        let current = self.current_source_line();
+        let centered = |(path, center): (String, usize)| {
+            let (first, last) = source::window_around(center);
+            (path, first, last)
+        };
+        let (path, first, last) = match location.as_deref() {
+            None => match self.last_listed.clone() {
+                Some((path, _, last)) => {
+                    let (first, last) = source::window_after(last);
+                    (path, first, last)
+                }
+                None => match current
+                    .clone()
+                    .map(|line| (line.file, line.number))
+                    .or_else(|| self.dwarf_data.get_function_source("main"))
+                {
+                    Some(location) => centered(location),
+                    None => {
+                        println!("No symbol table is loaded.");
+                        return;
+                    }
+                },
+            },
+            Some("-") => match self.last_listed.clone() {
+                Some((path, first, _)) => match source::window_before(first) {
+                    Some((first, last)) => (path, first, last),
+                    None => {
+                        println!("Already at the start of {}.", path);
+                        return;
+                    }
+                },
+                None => {
+                    println!("No source listed yet.");
+                    return;
+                }
+            },
+            Some(spec) => match self.resolve_source_location(spec, current.as_ref()) {
+                Ok(location) => centered(location),
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+        };
+        let lines = match self.sources.get_lines(&path) {
+            Some(lines) => lines,
+            None => {
+                println!("{}\t{}: No such file or directory.", first, path);
+                return;
             }
-            Status::Signaled(n) => format!("Signal (Status {})", n.as_str()),
         };
-        Ok(ret)
+        if first > lines.len() {
+            println!(
+                "Line number {} out of range; \"{}\" has {} lines.",
+                first,
+                path,
+                lines.len()
+            );
+            return;
+        }
+        let last = last.min(lines.len());
+        for number in first..=last {
+            let is_current = match &current {
+                Some(line) => line.file == path && line.number == number,
+                None => false,
+            };
+            println!(
+                "{}{}\t{}",
+                if is_current { "=> " } else { "   " },
+                number,
+                lines[number - 1]
+            );
+        }
+        self.last_listed = Some((path, first, last));
+    }
+
+    /// Resolves `line`, `file:line` or `function` to a source file and line.
+    fn resolve_source_location(
+        &self,
+        spec: &str,
+        current: Option<&Line>,
+    ) -> Result<(String, usize), String> {
+        let (file, rest) = match spec.rfind(':') {
+            Some(colon) => (Some(&spec[..colon]), &spec[colon + 1..]),
+            None => (None, spec),
+        };
+        if let Ok(number) = rest.parse::<usize>() {
+            let path = match file {
+                Some(file) => self
+                    .dwarf_data
+                    .get_source_path(file)
+                    .ok_or_else(|| format!("No source file named {}.", file))?,
+                // a bare line number is in the file last listed, or else where we are
+                None => self
+                    .last_listed
+                    .as_ref()
+                    .map(|(path, _, _)| path.clone())
+                    .or_else(|| current.map(|line| line.file.clone()))
+                    .or_else(|| {
+                        self.dwarf_data
+                            .get_function_source("main")
+                            .map(|(path, _)| path)
+                    })
+                    .ok_or_else(|| "No symbol table is loaded.".to_string())?,
+            };
+            return Ok((path, number));
+        }
+        self.dwarf_data
+            .get_function_source(rest)
+            .ok_or_else(|| format!("Function \"{}\" not defined.", rest))
+    }
+
+    fn directory_command(&mut self, dirs: Vec<String>) {
+        if dirs.is_empty() {
+            self.sources.reset_directories();
+        }
+        // the first one given ends up searched first
+        for dir in dirs.iter().rev() {
+            if !std::path::Path::new(dir).is_dir() {
+                println!("Warning: {}: No such file or directory.", dir);
+            }
+            self.sources.add_directory(dir);
+        }
+        let mut searched = self.sources.directories().to_vec();
+        searched.push("$cdir".to_string());
+        searched.push("$cwd".to_string());
+        println!("Source directories searched: {}", searched.join(":"));
     }
 
     /// This function prompts the user to enter a command, and continues re-prompting until the user
@@ -266,3 +3066,96 @@ impl Debugger {
         }
     }
 }
+
+impl eval::Context for Debugger {
+    fn lookup_variable(&self, name: &str) -> Result<(Type, usize), String> {
+        let (frame, pc) = self
+            .current_frame()
+            .map_err(|err| format!("read registers fail {}", err))?;
+        let var = self
+            .dwarf_data
+            .get_variable(pc, name)
+            .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))?;
+        let cfa = self.dwarf_data.frame_cfa(&frame, self.selected_frame == 0);
+        Ok((
+            var.entity_type.clone(),
+            self.dwarf_data.variable_address(var, cfa),
+        ))
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+        self.process()
+            .ok_or_else(|| "No process is running".to_string())?
+            .read_memory(addr, len)
+            .map_err(|err| err.to_string())
+    }
+
+    /// Writes around our breakpoints: their 0xcc stays put, and what was written under it is what
+    /// goes back when the breakpoint comes out.
+    fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), String> {
+        let inferior = self
+            .inferior
+            .as_mut()
+            .ok_or_else(|| "No process is running".to_string())?;
+        let covers = |bp_addr: usize| bp_addr >= addr && bp_addr - addr < bytes.len();
+        let mut patched = bytes.to_vec();
+        for bp in self.break_points.iter_mut().filter(|bp| bp.enabled) {
+            if covers(bp.addr) {
+                bp.orig_byte = bytes[bp.addr - addr];
+                patched[bp.addr - addr] = 0xcc;
+            }
+        }
+        if let Some((temp_addr, orig_byte)) = self.temp_break_point.as_mut() {
+            if covers(*temp_addr) {
+                *orig_byte = bytes[*temp_addr - addr];
+                patched[*temp_addr - addr] = 0xcc;
+            }
+        }
+        inferior
+            .write_memory(addr, &patched)
+            .map_err(|err| err.to_string())
+    }
+
+    fn get_type(&self, offset: usize) -> Option<Type> {
+        self.dwarf_data.get_type(offset).cloned()
+    }
+
+    fn symbol_at(&self, addr: usize) -> Option<String> {
+        self.dwarf_data.get_symbol(addr)
+    }
+
+    fn read_register(&self, name: &str) -> Result<u64, String> {
+        let target = self.process().ok_or_else(|| "No registers.".to_string())?;
+        // outer frames have their own pc, stack and frame pointers
+        if self.selected_frame > 0 {
+            let frame = &self.frames[self.selected_frame];
+            match name {
+                "rip" | "pc" => return Ok(frame.pc as u64),
+                "rsp" | "sp" => return Ok(frame.rsp as u64),
+                "rbp" | "fp" => return Ok(frame.rbp as u64),
+                _ => {}
+            }
+        }
+        let regs = target.get_registers().map_err(|err| err.to_string())?;
+        registers::register(&regs, name).ok_or_else(|| format!("Invalid register `{}'", name))
+    }
+
+    fn write_register(&mut self, name: &str, value: u64) -> Result<(), String> {
+        let pid = match &self.inferior {
+            Some(inferior) => inferior.tid(),
+            None => return Err("No registers.".to_string()),
+        };
+        if self.selected_frame > 0 {
+            return Err("Registers can only be changed in the innermost frame.".to_string());
+        }
+        let mut regs = ptrace::getregs(pid).map_err(|err| err.to_string())?;
+        match registers::register_mut(&mut regs, name) {
+            Some(register) => *register = value,
+            None => return Err(format!("Invalid register `{}'", name)),
+        }
+        ptrace::setregs(pid, regs).map_err(|err| err.to_string())?;
+        // a new rip or rsp makes the cached stack stale
+        self.frames.clear();
+        Ok(())
+    }
+}
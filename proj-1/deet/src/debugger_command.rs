@@ -3,7 +3,50 @@ pub enum DebuggerCommand {
     Run(Vec<String>),
     Cont,
     BackTrace,
-    BreakPoint(Option<String>),
+    BreakPoint(Option<String>, Option<String>),
+    Condition(Option<String>, Option<String>),
+    Ignore(Option<String>, Option<String>),
+    /// `commands [N]`: the list of commands itself follows on the next lines.
+    Commands(Option<String>),
+    Print(Option<String>),
+    SetVar(Option<String>),
+    /// `set <setting> <value>` for debugger settings such as `follow-fork-mode`.
+    Set(String, Option<String>),
+    Show(Option<String>),
+    /// `x/<count><format><size> <address>`: the part after the slash, and the address.
+    Examine(Option<String>, Option<String>),
+    InfoRegisters(Vec<String>),
+    Disassemble(Option<String>),
+    List(Option<String>),
+    Directory(Vec<String>),
+    Next,
+    Step,
+    StepI,
+    Finish,
+    InfoBreakPoints,
+    InfoLocals,
+    InfoArgs,
+    InfoThreads,
+    InfoSignals(Option<String>),
+    /// `handle <signals...> <actions...>`.
+    Handle(Vec<String>),
+    Signal(Option<String>),
+    Thread(Option<String>),
+    Up(Option<String>),
+    Down(Option<String>),
+    Frame(Option<String>),
+    Delete(Option<String>),
+    Disable(Option<String>),
+    Enable(Option<String>),
+    Watch(Option<String>, bool),
+    /// `catch syscall [name|number...]`; no syscalls catches all of them.
+    CatchSyscall(Vec<String>),
+    Attach(Option<String>),
+    Detach,
+    /// `gcore [file]`: dump a core of the stopped inferior.
+    Gcore(Option<String>),
+    /// `source <file>`: run the commands in a file.
+    Source(Option<String>),
 }
 
 impl DebuggerCommand {
@@ -18,14 +61,232 @@ impl DebuggerCommand {
             }
             "c" | "cont" =>  Some(DebuggerCommand::Cont),
             "bt" | "back" | "backtrace" =>  Some(DebuggerCommand::BackTrace),
-            "b" | "breakpoint" => {
+            "b" | "break" | "breakpoint" => {
                 let pos = match tokens.get(1) {
                     Some(s) => Some(s.to_string()),
                     None => None,
                 };
-                Some(DebuggerCommand::BreakPoint(pos))
+                // break <location> if <condition>
+                let condition = match tokens.get(2) {
+                    Some(&"if") => Some(tokens[3..].join(" ")),
+                    Some(_) => return None,
+                    None => None,
+                };
+                Some(DebuggerCommand::BreakPoint(pos, condition))
             }
+            "condition" => {
+                let condition = if tokens.len() > 2 {
+                    Some(tokens[2..].join(" "))
+                } else {
+                    None
+                };
+                Some(DebuggerCommand::Condition(
+                    tokens.get(1).map(|s| s.to_string()),
+                    condition,
+                ))
+            }
+            "ignore" => Some(DebuggerCommand::Ignore(
+                tokens.get(1).map(|s| s.to_string()),
+                tokens.get(2).map(|s| s.to_string()),
+            )),
+            // expressions may contain spaces, e.g. `p arr[i + 1]`
+            "p" | "print" => Some(DebuggerCommand::Print(join_args(&tokens[1..]))),
+            "set" => match tokens.get(1) {
+                Some(&"var") | Some(&"variable") => {
+                    Some(DebuggerCommand::SetVar(join_args(&tokens[2..])))
+                }
+                // set $rax = 0
+                Some(register) if register.starts_with('$') => {
+                    Some(DebuggerCommand::SetVar(Some(tokens[1..].join(" "))))
+                }
+                Some(setting) => Some(DebuggerCommand::Set(
+                    setting.to_string(),
+                    tokens.get(2).map(|s| s.to_string()),
+                )),
+                None => None,
+            },
+            "show" => Some(DebuggerCommand::Show(tokens.get(1).map(|s| s.to_string()))),
+            "x" => Some(DebuggerCommand::Examine(None, join_args(&tokens[1..]))),
+            examine if examine.starts_with("x/") => Some(DebuggerCommand::Examine(
+                Some(examine[2..].to_string()),
+                join_args(&tokens[1..]),
+            )),
+            "disas" | "disassemble" => Some(DebuggerCommand::Disassemble(join_args(&tokens[1..]))),
+            "l" | "list" => Some(DebuggerCommand::List(join_args(&tokens[1..]))),
+            // like gdb, directories may also be given colon-separated
+            "dir" | "directory" => Some(DebuggerCommand::Directory(
+                tokens[1..]
+                    .iter()
+                    .flat_map(|arg| arg.split(':'))
+                    .filter(|dir| !dir.is_empty())
+                    .map(|dir| dir.to_string())
+                    .collect(),
+            )),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "si" | "stepi" => Some(DebuggerCommand::StepI),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "i" | "info" => match tokens.get(1) {
+                Some(&"b") | Some(&"break") | Some(&"breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakPoints)
+                }
+                Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                Some(&"args") => Some(DebuggerCommand::InfoArgs),
+                Some(&"threads") => Some(DebuggerCommand::InfoThreads),
+                Some(&"signals") | Some(&"handle") => Some(DebuggerCommand::InfoSignals(
+                    tokens.get(2).map(|s| s.to_string()),
+                )),
+                Some(&"r") | Some(&"registers") => Some(DebuggerCommand::InfoRegisters(
+                    tokens[2..]
+                        .iter()
+                        .map(|name| name.trim_start_matches('$').to_string())
+                        .collect(),
+                )),
+                _ => None,
+            },
+            "d" | "delete" => Some(DebuggerCommand::Delete(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            "disable" => Some(DebuggerCommand::Disable(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            "enable" => Some(DebuggerCommand::Enable(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            // watch stops on writes, awatch on reads or writes
+            "watch" => Some(DebuggerCommand::Watch(
+                tokens.get(1).map(|s| s.to_string()),
+                false,
+            )),
+            "awatch" => Some(DebuggerCommand::Watch(
+                tokens.get(1).map(|s| s.to_string()),
+                true,
+            )),
+            "catch" => match tokens.get(1) {
+                Some(&"syscall") => Some(DebuggerCommand::CatchSyscall(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                _ => None,
+            },
+            "up" => Some(DebuggerCommand::Up(tokens.get(1).map(|s| s.to_string()))),
+            "down" => Some(DebuggerCommand::Down(tokens.get(1).map(|s| s.to_string()))),
+            "f" | "frame" => Some(DebuggerCommand::Frame(tokens.get(1).map(|s| s.to_string()))),
+            "t" | "thread" => Some(DebuggerCommand::Thread(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "signal" => Some(DebuggerCommand::Signal(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            "attach" => Some(DebuggerCommand::Attach(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            "detach" => Some(DebuggerCommand::Detach),
+            "gcore" | "generate-core-file" => {
+                Some(DebuggerCommand::Gcore(tokens.get(1).map(|s| s.to_string())))
+            }
+            "commands" => Some(DebuggerCommand::Commands(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
+            "source" => Some(DebuggerCommand::Source(
+                tokens.get(1).map(|s| s.to_string()),
+            )),
             _ => None,
         }
     }
+
+    /// Whether the command sets the inferior running, which ends a breakpoint's command list.
+    pub fn resumes(&self) -> bool {
+        matches!(
+            self,
+            DebuggerCommand::Run(_)
+                | DebuggerCommand::Cont
+                | DebuggerCommand::Next
+                | DebuggerCommand::Step
+                | DebuggerCommand::StepI
+                | DebuggerCommand::Finish
+                | DebuggerCommand::Signal(_)
+        )
+    }
+}
+
+/// Glues the rest of the command line back together, for arguments that are expressions.
+fn join_args(tokens: &[&str]) -> Option<String> {
+    if tokens.is_empty() {
+        None
+    } else {
+        Some(tokens.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<DebuggerCommand> {
+        DebuggerCommand::from_tokens(&line.split_whitespace().collect())
+    }
+
+    #[test]
+    fn set_tells_settings_from_assignments() {
+        assert!(matches!(
+            parse("set follow-fork-mode child"),
+            Some(DebuggerCommand::Set(setting, Some(value)))
+                if setting == "follow-fork-mode" && value == "child"
+        ));
+        assert!(matches!(
+            parse("set detach-on-fork"),
+            Some(DebuggerCommand::Set(setting, None)) if setting == "detach-on-fork"
+        ));
+        assert!(matches!(
+            parse("set var x = 5"),
+            Some(DebuggerCommand::SetVar(Some(expr))) if expr == "x = 5"
+        ));
+        assert!(matches!(
+            parse("set variable p->next = 0"),
+            Some(DebuggerCommand::SetVar(Some(expr))) if expr == "p->next = 0"
+        ));
+        assert!(matches!(
+            parse("set $rax = 0"),
+            Some(DebuggerCommand::SetVar(Some(expr))) if expr == "$rax = 0"
+        ));
+        assert!(matches!(
+            parse("set var"),
+            Some(DebuggerCommand::SetVar(None))
+        ));
+        assert!(parse("set").is_none());
+    }
+
+    #[test]
+    fn show_takes_an_optional_setting() {
+        assert!(matches!(
+            parse("show follow-fork-mode"),
+            Some(DebuggerCommand::Show(Some(setting))) if setting == "follow-fork-mode"
+        ));
+        assert!(matches!(parse("show"), Some(DebuggerCommand::Show(None))));
+    }
+
+    #[test]
+    fn commands_takes_an_optional_breakpoint() {
+        assert!(matches!(
+            parse("commands 2"),
+            Some(DebuggerCommand::Commands(Some(num))) if num == "2"
+        ));
+        assert!(matches!(
+            parse("commands"),
+            Some(DebuggerCommand::Commands(None))
+        ));
+    }
+
+    #[test]
+    fn only_running_commands_resume() {
+        for line in &["run", "r a b", "c", "next", "step", "stepi", "finish"] {
+            assert!(parse(line).unwrap().resumes(), "{}", line);
+        }
+        for line in &["bt", "print x", "commands 1", "info break", "quit"] {
+            assert!(!parse(line).unwrap().resumes(), "{}", line);
+        }
+    }
 }
@@ -0,0 +1,1360 @@
+//! A decoder for the x86-64 instructions compilers commonly emit, printed in the AT&T syntax
+//! gdb and objdump use. It covers the general purpose instructions, scalar and packed SSE, and
+//! the usual x87 loads and stores; anything else comes out as `(bad)`.
+
+const REG64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const REG32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+const REG16: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w",
+    "r14w", "r15w",
+];
+const REG8: [&str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b",
+    "r13b", "r14b", "r15b",
+];
+/// Byte registers 4-7 without a REX prefix.
+const REG8_LEGACY: [&str; 4] = ["ah", "ch", "dh", "bh"];
+const XMM: [&str; 16] = [
+    "xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7", "xmm8", "xmm9", "xmm10",
+    "xmm11", "xmm12", "xmm13", "xmm14", "xmm15",
+];
+const CONDITIONS: [&str; 16] = [
+    "o", "no", "b", "ae", "e", "ne", "be", "a", "s", "ns", "p", "np", "l", "ge", "le", "g",
+];
+const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFTS: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+
+/// One decoded instruction.
+pub struct Instruction {
+    pub len: usize,
+    /// Mnemonic and operands, e.g. `mov    %rsp,%rbp`, with branch targets and rip-relative
+    /// addresses annotated with their symbols.
+    pub text: String,
+}
+
+/// Decodes the instruction at the start of `bytes`, which lives at `addr` in the inferior.
+/// `symbolize` names code and data addresses, like `main+4`.
+pub fn decode(
+    bytes: &[u8],
+    addr: usize,
+    symbolize: &dyn Fn(usize) -> Option<String>,
+) -> Instruction {
+    let mut decoder = Decoder {
+        bytes,
+        pos: 0,
+        rex: 0,
+        opsize_prefixes: 0,
+        rep: None,
+        segment: None,
+        lock: false,
+    };
+    match decoder.decode() {
+        Ok(decoded) => Instruction {
+            len: decoder.pos,
+            text: decoder.format(decoded, addr, symbolize),
+        },
+        Err(()) => Instruction {
+            len: 1,
+            text: "(bad)".to_string(),
+        },
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Mem {
+    base: Option<u8>,
+    /// Index register and scale.
+    index: Option<(u8, u8)>,
+    disp: i64,
+    /// Whether the encoding has a displacement, so `0x0(%rax)` and `(%rax)` differ.
+    has_disp: bool,
+    rip: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Rm {
+    Reg(u8),
+    Mem(Mem),
+}
+
+enum Operand {
+    /// A general purpose register, which decides the operand size.
+    Reg(&'static str),
+    /// A register that doesn't decide the operand size, like xmm registers or a shift's `%cl`.
+    Other(&'static str),
+    Imm(u64),
+    Mem(Mem),
+    /// A branch target.
+    Target(usize),
+}
+
+struct Decoded {
+    mnemonic: String,
+    operands: Vec<Operand>,
+    /// For instructions that act on a size: the size, spelled out as a suffix (`movl`) when no
+    /// register operand makes it clear.
+    suffix_size: Option<usize>,
+    /// Indirect calls and jumps mark their operand with `*`.
+    indirect: bool,
+}
+
+impl Decoded {
+    fn new(mnemonic: &str, operands: Vec<Operand>) -> Self {
+        Decoded {
+            mnemonic: mnemonic.to_string(),
+            operands,
+            suffix_size: None,
+            indirect: false,
+        }
+    }
+
+    fn sized(mnemonic: &str, operands: Vec<Operand>, size: usize) -> Self {
+        Decoded {
+            suffix_size: Some(size),
+            ..Decoded::new(mnemonic, operands)
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    rex: u8,
+    opsize_prefixes: usize,
+    rep: Option<u8>,
+    segment: Option<&'static str>,
+    lock: bool,
+}
+
+fn suffix(size: usize) -> char {
+    match size {
+        1 => 'b',
+        2 => 'w',
+        8 => 'q',
+        _ => 'l',
+    }
+}
+
+fn mask(value: i64, size: usize) -> u64 {
+    if size >= 8 {
+        value as u64
+    } else {
+        (value as u64) & ((1u64 << (8 * size)) - 1)
+    }
+}
+
+fn format_disp(disp: i64) -> String {
+    if disp < 0 {
+        format!("-{:#x}", -(disp as i128))
+    } else {
+        format!("{:#x}", disp)
+    }
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Result<u8, ()> {
+        let byte = *self.bytes.get(self.pos).ok_or(())?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Reads a little-endian immediate of `size` bytes, sign-extended.
+    fn imm(&mut self, size: usize) -> Result<i64, ()> {
+        let bytes = self.bytes.get(self.pos..self.pos + size).ok_or(())?;
+        self.pos += size;
+        let mut value = 0u64;
+        for (i, byte) in bytes.iter().enumerate() {
+            value |= (*byte as u64) << (8 * i);
+        }
+        let shift = 64 - 8 * size as u32;
+        Ok(((value << shift) as i64) >> shift)
+    }
+
+    fn rex_w(&self) -> bool {
+        self.rex & 8 != 0
+    }
+
+    fn opsize(&self) -> usize {
+        if self.rex_w() {
+            8
+        } else if self.opsize_prefixes > 0 {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// Immediates are at most 32 bits, except for movabs.
+    fn imm_size(&self, size: usize) -> usize {
+        size.min(4)
+    }
+
+    fn gpr(&self, num: u8, size: usize) -> &'static str {
+        let num = num as usize;
+        match size {
+            8 => REG64[num],
+            4 => REG32[num],
+            2 => REG16[num],
+            _ if self.rex == 0 && (4..8).contains(&num) => REG8_LEGACY[num - 4],
+            _ => REG8[num],
+        }
+    }
+
+    /// Decodes a ModRM byte (and any SIB and displacement after it), returning the reg field
+    /// extended by REX.R and the r/m operand.
+    fn modrm(&mut self) -> Result<(u8, Rm), ()> {
+        let byte = self.byte()?;
+        let mode = byte >> 6;
+        let reg = ((byte >> 3) & 7) | ((self.rex & 4) << 1);
+        let rm = byte & 7;
+        let rex_b = (self.rex & 1) << 3;
+        if mode == 3 {
+            return Ok((reg, Rm::Reg(rm | rex_b)));
+        }
+        let mut mem = Mem {
+            base: None,
+            index: None,
+            disp: 0,
+            has_disp: false,
+            rip: false,
+        };
+        let mut disp_size = match mode {
+            1 => 1,
+            2 => 4,
+            _ => 0,
+        };
+        if rm == 4 {
+            let sib = self.byte()?;
+            let index = ((sib >> 3) & 7) | ((self.rex & 2) << 2);
+            if index != 4 {
+                mem.index = Some((index, 1 << (sib >> 6)));
+            }
+            if sib & 7 == 5 && mode == 0 {
+                disp_size = 4;
+            } else {
+                mem.base = Some((sib & 7) | rex_b);
+            }
+        } else if rm == 5 && mode == 0 {
+            mem.rip = true;
+            disp_size = 4;
+        } else {
+            mem.base = Some(rm | rex_b);
+        }
+        if disp_size > 0 {
+            mem.disp = self.imm(disp_size)?;
+            mem.has_disp = true;
+        }
+        Ok((reg, Rm::Mem(mem)))
+    }
+
+    fn rm_gpr(&self, rm: Rm, size: usize) -> Operand {
+        match rm {
+            Rm::Reg(num) => Operand::Reg(self.gpr(num, size)),
+            Rm::Mem(mem) => Operand::Mem(mem),
+        }
+    }
+
+    fn rm_xmm(&self, rm: Rm) -> Operand {
+        match rm {
+            Rm::Reg(num) => Operand::Other(XMM[num as usize]),
+            Rm::Mem(mem) => Operand::Mem(mem),
+        }
+    }
+
+    fn target(&mut self, size: usize) -> Result<Operand, ()> {
+        let rel = self.imm(size)?;
+        // relative to the end of the instruction, which is where we are now
+        Ok(Operand::Target(rel as usize))
+    }
+
+    fn decode(&mut self) -> Result<Decoded, ()> {
+        loop {
+            match *self.bytes.get(self.pos).ok_or(())? {
+                0x66 => self.opsize_prefixes += 1,
+                0xf2 | 0xf3 => self.rep = self.bytes.get(self.pos).cloned(),
+                0xf0 => self.lock = true,
+                0x2e => self.segment = Some("cs"),
+                0x3e => self.segment = Some("ds"),
+                0x26 => self.segment = Some("es"),
+                0x36 => self.segment = Some("ss"),
+                0x64 => self.segment = Some("fs"),
+                0x65 => self.segment = Some("gs"),
+                // address size overrides don't show up in compiled code we care about
+                0x67 => {}
+                byte @ 0x40..=0x4f => {
+                    // REX has to come last, right before the opcode
+                    self.rex = byte;
+                    self.pos += 1;
+                    break;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        let op = self.byte()?;
+        let size = self.opsize();
+        let byte_or_full = |op: u8| if op & 1 == 0 { 1 } else { size };
+        Ok(match op {
+            0x0f => return self.decode_0f(),
+            0x00..=0x3f if op & 7 < 6 => {
+                let name = ALU[(op >> 3) as usize];
+                let size = byte_or_full(op);
+                match op & 7 {
+                    0 | 1 => {
+                        let (reg, rm) = self.modrm()?;
+                        Decoded::new(
+                            name,
+                            vec![Operand::Reg(self.gpr(reg, size)), self.rm_gpr(rm, size)],
+                        )
+                    }
+                    2 | 3 => {
+                        let (reg, rm) = self.modrm()?;
+                        Decoded::new(
+                            name,
+                            vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                        )
+                    }
+                    _ => {
+                        let imm = self.imm(self.imm_size(size))?;
+                        Decoded::new(
+                            name,
+                            vec![
+                                Operand::Imm(mask(imm, size)),
+                                Operand::Reg(self.gpr(0, size)),
+                            ],
+                        )
+                    }
+                }
+            }
+            0x50..=0x57 => Decoded::new(
+                "push",
+                vec![Operand::Reg(
+                    REG64[((op & 7) | ((self.rex & 1) << 3)) as usize],
+                )],
+            ),
+            0x58..=0x5f => Decoded::new(
+                "pop",
+                vec![Operand::Reg(
+                    REG64[((op & 7) | ((self.rex & 1) << 3)) as usize],
+                )],
+            ),
+            0x63 => {
+                let (reg, rm) = self.modrm()?;
+                let name = if self.rex_w() { "movslq" } else { "movsxd" };
+                Decoded::new(
+                    name,
+                    vec![self.rm_gpr(rm, 4), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0x68 | 0x6a => {
+                let imm = self.imm(if op == 0x68 { 4 } else { 1 })?;
+                Decoded::new("push", vec![Operand::Imm(mask(imm, 8))])
+            }
+            0x69 | 0x6b => {
+                let (reg, rm) = self.modrm()?;
+                let imm = self.imm(if op == 0x69 { self.imm_size(size) } else { 1 })?;
+                Decoded::new(
+                    "imul",
+                    vec![
+                        Operand::Imm(mask(imm, size)),
+                        self.rm_gpr(rm, size),
+                        Operand::Reg(self.gpr(reg, size)),
+                    ],
+                )
+            }
+            0x70..=0x7f => {
+                let target = self.target(1)?;
+                Decoded::new(
+                    &format!("j{}", CONDITIONS[(op & 15) as usize]),
+                    vec![target],
+                )
+            }
+            0x80 | 0x81 | 0x83 => {
+                let size = if op == 0x80 { 1 } else { size };
+                let (reg, rm) = self.modrm()?;
+                let imm = self.imm(if op == 0x81 { self.imm_size(size) } else { 1 })?;
+                Decoded::sized(
+                    ALU[(reg & 7) as usize],
+                    vec![Operand::Imm(mask(imm, size)), self.rm_gpr(rm, size)],
+                    size,
+                )
+            }
+            0x84..=0x89 => {
+                let name = match op {
+                    0x84 | 0x85 => "test",
+                    0x86 | 0x87 => "xchg",
+                    _ => "mov",
+                };
+                let size = byte_or_full(op);
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    name,
+                    vec![Operand::Reg(self.gpr(reg, size)), self.rm_gpr(rm, size)],
+                )
+            }
+            0x8a | 0x8b => {
+                let size = byte_or_full(op);
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    "mov",
+                    vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0x8d => {
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    "lea",
+                    vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0x8f => {
+                let (_, rm) = self.modrm()?;
+                Decoded::new("pop", vec![self.rm_gpr(rm, 8)])
+            }
+            0x90 if self.rep == Some(0xf3) => {
+                self.rep = None;
+                Decoded::new("pause", vec![])
+            }
+            0x90 if self.rex & 1 == 0 && self.opsize_prefixes == 0 => Decoded::new("nop", vec![]),
+            0x90..=0x97 => Decoded::new(
+                "xchg",
+                vec![
+                    Operand::Reg(self.gpr((op & 7) | ((self.rex & 1) << 3), size)),
+                    Operand::Reg(self.gpr(0, size)),
+                ],
+            ),
+            0x98 => Decoded::new(
+                match size {
+                    8 => "cltq",
+                    2 => "cbtw",
+                    _ => "cwtl",
+                },
+                vec![],
+            ),
+            0x99 => Decoded::new(
+                match size {
+                    8 => "cqto",
+                    2 => "cwtd",
+                    _ => "cltd",
+                },
+                vec![],
+            ),
+            0x9c => Decoded::new("pushf", vec![]),
+            0x9d => Decoded::new("popf", vec![]),
+            0x9e => Decoded::new("sahf", vec![]),
+            0x9f => Decoded::new("lahf", vec![]),
+            0xa8 | 0xa9 => {
+                let size = byte_or_full(op);
+                let imm = self.imm(self.imm_size(size))?;
+                Decoded::new(
+                    "test",
+                    vec![
+                        Operand::Imm(mask(imm, size)),
+                        Operand::Reg(self.gpr(0, size)),
+                    ],
+                )
+            }
+            0xa4..=0xa7 | 0xaa..=0xaf => self.string_op(op, byte_or_full(op)),
+            0xb0..=0xb7 => {
+                let imm = self.imm(1)?;
+                Decoded::new(
+                    "mov",
+                    vec![
+                        Operand::Imm(mask(imm, 1)),
+                        Operand::Reg(self.gpr((op & 7) | ((self.rex & 1) << 3), 1)),
+                    ],
+                )
+            }
+            0xb8..=0xbf => {
+                let reg = Operand::Reg(self.gpr((op & 7) | ((self.rex & 1) << 3), size));
+                if size == 8 {
+                    Decoded::new("movabs", vec![Operand::Imm(self.imm(8)? as u64), reg])
+                } else {
+                    let imm = self.imm(size)?;
+                    Decoded::new("mov", vec![Operand::Imm(mask(imm, size)), reg])
+                }
+            }
+            0xc0 | 0xc1 | 0xd0..=0xd3 => {
+                let size = byte_or_full(op);
+                let (reg, rm) = self.modrm()?;
+                let mut operands = match op {
+                    0xc0 | 0xc1 => vec![Operand::Imm(mask(self.imm(1)?, 1))],
+                    0xd2 | 0xd3 => vec![Operand::Other("cl")],
+                    _ => vec![],
+                };
+                operands.push(self.rm_gpr(rm, size));
+                Decoded::sized(SHIFTS[(reg & 7) as usize], operands, size)
+            }
+            0xc2 => {
+                let imm = self.imm(2)?;
+                Decoded::new("ret", vec![Operand::Imm(mask(imm, 2))])
+            }
+            0xc3 if self.rep == Some(0xf3) => {
+                self.rep = None;
+                Decoded::new("repz ret", vec![])
+            }
+            0xc3 => Decoded::new("ret", vec![]),
+            0xc6 | 0xc7 => {
+                let size = byte_or_full(op);
+                let (reg, rm) = self.modrm()?;
+                if reg & 7 != 0 {
+                    return Err(());
+                }
+                let imm = self.imm(self.imm_size(size))?;
+                Decoded::sized(
+                    "mov",
+                    vec![Operand::Imm(mask(imm, size)), self.rm_gpr(rm, size)],
+                    size,
+                )
+            }
+            0xc9 => Decoded::new("leave", vec![]),
+            0xcc => Decoded::new("int3", vec![]),
+            0xcd => {
+                let imm = self.imm(1)?;
+                Decoded::new("int", vec![Operand::Imm(mask(imm, 1))])
+            }
+            0xd8..=0xdf => self.decode_x87(op)?,
+            0xe3 => {
+                let target = self.target(1)?;
+                Decoded::new("jrcxz", vec![target])
+            }
+            0xe8 => {
+                let target = self.target(4)?;
+                Decoded::new("call", vec![target])
+            }
+            0xe9 => {
+                let target = self.target(4)?;
+                Decoded::new("jmp", vec![target])
+            }
+            0xeb => {
+                let target = self.target(1)?;
+                Decoded::new("jmp", vec![target])
+            }
+            0xf4 => Decoded::new("hlt", vec![]),
+            0xf5 => Decoded::new("cmc", vec![]),
+            0xf8 => Decoded::new("clc", vec![]),
+            0xf9 => Decoded::new("stc", vec![]),
+            0xfa => Decoded::new("cli", vec![]),
+            0xfb => Decoded::new("sti", vec![]),
+            0xfc => Decoded::new("cld", vec![]),
+            0xfd => Decoded::new("std", vec![]),
+            0xf6 | 0xf7 => {
+                let size = byte_or_full(op);
+                let (reg, rm) = self.modrm()?;
+                let operand = self.rm_gpr(rm, size);
+                match reg & 7 {
+                    0 | 1 => {
+                        let imm = self.imm(self.imm_size(size))?;
+                        Decoded::sized("test", vec![Operand::Imm(mask(imm, size)), operand], size)
+                    }
+                    ext => {
+                        let name =
+                            ["", "", "not", "neg", "mul", "imul", "div", "idiv"][ext as usize];
+                        Decoded::sized(name, vec![operand], size)
+                    }
+                }
+            }
+            0xfe | 0xff => {
+                let size = byte_or_full(op);
+                let (reg, rm) = self.modrm()?;
+                match (op, reg & 7) {
+                    (_, 0) => Decoded::sized("inc", vec![self.rm_gpr(rm, size)], size),
+                    (_, 1) => Decoded::sized("dec", vec![self.rm_gpr(rm, size)], size),
+                    (0xff, 2) | (0xff, 4) => {
+                        let name = if reg & 7 == 2 { "call" } else { "jmp" };
+                        let mut decoded = Decoded::new(name, vec![self.rm_gpr(rm, 8)]);
+                        decoded.indirect = true;
+                        decoded
+                    }
+                    (0xff, 6) => Decoded::new("push", vec![self.rm_gpr(rm, 8)]),
+                    _ => return Err(()),
+                }
+            }
+            _ => return Err(()),
+        })
+    }
+
+    /// movs, cmps, stos, lods and scas, which work on `(%rsi)` and `(%rdi)`.
+    fn string_op(&mut self, op: u8, size: usize) -> Decoded {
+        let source = Operand::Other("%ds:(%rsi)");
+        let destination = Operand::Other("%es:(%rdi)");
+        let acc = Operand::Reg(self.gpr(0, size));
+        let (name, operands, repz) = match op {
+            0xa4 | 0xa5 => ("movs", vec![source, destination], false),
+            0xa6 | 0xa7 => ("cmps", vec![destination, source], true),
+            0xaa | 0xab => ("stos", vec![acc, destination], false),
+            0xac | 0xad => ("lods", vec![source, acc], false),
+            _ => ("scas", vec![destination, acc], true),
+        };
+        let prefix = match self.rep.take() {
+            Some(0xf3) if repz => "repz ",
+            Some(0xf3) => "rep ",
+            Some(_) => "repnz ",
+            None => "",
+        };
+        Decoded::sized(&format!("{}{}", prefix, name), operands, size)
+    }
+
+    fn decode_0f(&mut self) -> Result<Decoded, ()> {
+        let op = self.byte()?;
+        let size = self.opsize();
+        Ok(match op {
+            0x05 => Decoded::new("syscall", vec![]),
+            0x0b => Decoded::new("ud2", vec![]),
+            0x1e if self.rep == Some(0xf3) && self.bytes.get(self.pos) == Some(&0xfa) => {
+                self.pos += 1;
+                self.rep = None;
+                Decoded::new("endbr64", vec![])
+            }
+            0x18 => {
+                let (reg, rm) = self.modrm()?;
+                let name = match reg & 7 {
+                    0 => "prefetchnta",
+                    1 => "prefetcht0",
+                    2 => "prefetcht1",
+                    3 => "prefetcht2",
+                    _ => "nop",
+                };
+                Decoded::new(name, vec![self.rm_gpr(rm, size)])
+            }
+            0x1f => {
+                let (_, rm) = self.modrm()?;
+                Decoded::sized("nop", vec![self.rm_gpr(rm, size)], size)
+            }
+            0x01 if self.bytes.get(self.pos) == Some(&0xd0) => {
+                self.pos += 1;
+                Decoded::new("xgetbv", vec![])
+            }
+            0x31 => Decoded::new("rdtsc", vec![]),
+            0xa2 => Decoded::new("cpuid", vec![]),
+            0x40..=0x4f => {
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    &format!("cmov{}", CONDITIONS[(op & 15) as usize]),
+                    vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0x80..=0x8f => {
+                let target = self.target(4)?;
+                Decoded::new(
+                    &format!("j{}", CONDITIONS[(op & 15) as usize]),
+                    vec![target],
+                )
+            }
+            0x90..=0x9f => {
+                let (_, rm) = self.modrm()?;
+                Decoded::new(
+                    &format!("set{}", CONDITIONS[(op & 15) as usize]),
+                    vec![self.rm_gpr(rm, 1)],
+                )
+            }
+            0xa3 | 0xab | 0xb3 | 0xbb => {
+                let name = match op {
+                    0xa3 => "bt",
+                    0xab => "bts",
+                    0xb3 => "btr",
+                    _ => "btc",
+                };
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    name,
+                    vec![Operand::Reg(self.gpr(reg, size)), self.rm_gpr(rm, size)],
+                )
+            }
+            0xba => {
+                let (reg, rm) = self.modrm()?;
+                let name = match reg & 7 {
+                    4 => "bt",
+                    5 => "bts",
+                    6 => "btr",
+                    7 => "btc",
+                    _ => return Err(()),
+                };
+                let imm = self.imm(1)?;
+                Decoded::sized(
+                    name,
+                    vec![Operand::Imm(mask(imm, 1)), self.rm_gpr(rm, size)],
+                    size,
+                )
+            }
+            0xa4 | 0xa5 | 0xac | 0xad => {
+                let name = if op < 0xac { "shld" } else { "shrd" };
+                let (reg, rm) = self.modrm()?;
+                let count = if op & 1 == 0 {
+                    Operand::Imm(mask(self.imm(1)?, 1))
+                } else {
+                    Operand::Other("cl")
+                };
+                Decoded::new(
+                    name,
+                    vec![
+                        count,
+                        Operand::Reg(self.gpr(reg, size)),
+                        self.rm_gpr(rm, size),
+                    ],
+                )
+            }
+            0xaf => {
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    "imul",
+                    vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0xb0 | 0xb1 | 0xc0 | 0xc1 => {
+                let name = if op < 0xc0 { "cmpxchg" } else { "xadd" };
+                let size = if op & 1 == 0 { 1 } else { size };
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    name,
+                    vec![Operand::Reg(self.gpr(reg, size)), self.rm_gpr(rm, size)],
+                )
+            }
+            0xb6 | 0xb7 | 0xbe | 0xbf => {
+                // movzbl, movswq, ...: the source and destination sizes are both spelled out
+                let source_size = if op & 1 == 0 { 1 } else { 2 };
+                let name = format!(
+                    "mov{}{}{}",
+                    if op < 0xbe { 'z' } else { 's' },
+                    suffix(source_size),
+                    suffix(size)
+                );
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    &name,
+                    vec![
+                        self.rm_gpr(rm, source_size),
+                        Operand::Reg(self.gpr(reg, size)),
+                    ],
+                )
+            }
+            0xb8 | 0xbc | 0xbd if op == 0xb8 || self.rep == Some(0xf3) => {
+                self.rep = None;
+                let name = match op {
+                    0xb8 => "popcnt",
+                    0xbc => "tzcnt",
+                    _ => "lzcnt",
+                };
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    name,
+                    vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0xbc | 0xbd => {
+                let (reg, rm) = self.modrm()?;
+                Decoded::new(
+                    if op == 0xbc { "bsf" } else { "bsr" },
+                    vec![self.rm_gpr(rm, size), Operand::Reg(self.gpr(reg, size))],
+                )
+            }
+            0xc8..=0xcf => Decoded::new(
+                "bswap",
+                vec![Operand::Reg(
+                    self.gpr((op & 7) | ((self.rex & 1) << 3), size),
+                )],
+            ),
+            0xae => {
+                let (reg, rm) = self.modrm()?;
+                match (rm, reg & 7) {
+                    (Rm::Reg(_), 5) => Decoded::new("lfence", vec![]),
+                    (Rm::Reg(_), 6) => Decoded::new("mfence", vec![]),
+                    (Rm::Reg(_), 7) => Decoded::new("sfence", vec![]),
+                    (Rm::Mem(mem), ext) if ext < 4 => Decoded::new(
+                        ["fxsave", "fxrstor", "ldmxcsr", "stmxcsr"][ext as usize],
+                        vec![Operand::Mem(mem)],
+                    ),
+                    _ => return Err(()),
+                }
+            }
+            _ => self.decode_sse(op)?,
+        })
+    }
+
+    /// The SSE instructions, whose mandatory 66, F3 or F2 prefix picks the variant.
+    fn decode_sse(&mut self, op: u8) -> Result<Decoded, ()> {
+        let prefix = match self.rep.take() {
+            Some(rep) => rep,
+            None if self.opsize_prefixes > 0 => {
+                self.opsize_prefixes -= 1;
+                0x66
+            }
+            None => 0,
+        };
+        // the packed single, scalar single, packed double and scalar double variants
+        let variant = match prefix {
+            0xf3 => "ss",
+            0x66 => "pd",
+            0xf2 => "sd",
+            _ => "ps",
+        };
+        let gpr_size = if self.rex_w() { 8 } else { 4 };
+        let (reg, rm) = self.modrm()?;
+        let xmm = Operand::Other(XMM[reg as usize]);
+        let load = |name: &str, decoder: &Self| {
+            Decoded::new(
+                name,
+                vec![decoder.rm_xmm(rm), Operand::Other(XMM[reg as usize])],
+            )
+        };
+        let store = |name: &str, decoder: &Self| {
+            Decoded::new(
+                name,
+                vec![Operand::Other(XMM[reg as usize]), decoder.rm_xmm(rm)],
+            )
+        };
+        Ok(match (op, prefix) {
+            (0x10, 0) => load("movups", self),
+            (0x10, 0x66) => load("movupd", self),
+            (0x10, _) => load(&format!("mov{}", variant), self),
+            (0x11, 0) => store("movups", self),
+            (0x11, 0x66) => store("movupd", self),
+            (0x11, _) => store(&format!("mov{}", variant), self),
+            (0x12, 0) if matches!(rm, Rm::Reg(_)) => load("movhlps", self),
+            (0x12, 0) | (0x12, 0x66) => load(if prefix == 0 { "movlps" } else { "movlpd" }, self),
+            (0x13, 0) | (0x13, 0x66) => store(if prefix == 0 { "movlps" } else { "movlpd" }, self),
+            (0x16, 0) if matches!(rm, Rm::Reg(_)) => load("movlhps", self),
+            (0x16, 0) | (0x16, 0x66) => load(if prefix == 0 { "movhps" } else { "movhpd" }, self),
+            (0x17, 0) | (0x17, 0x66) => store(if prefix == 0 { "movhps" } else { "movhpd" }, self),
+            (0x14, 0) | (0x14, 0x66) => load(&format!("unpckl{}", variant), self),
+            (0x15, 0) | (0x15, 0x66) => load(&format!("unpckh{}", variant), self),
+            (0x28, 0) | (0x28, 0x66) => load(&format!("mova{}", variant), self),
+            (0x29, 0) | (0x29, 0x66) => store(&format!("mova{}", variant), self),
+            (0x2a, 0xf3) | (0x2a, 0xf2) => Decoded::sized(
+                &format!("cvtsi2{}", variant),
+                vec![self.rm_gpr(rm, gpr_size), xmm],
+                gpr_size,
+            ),
+            (0x2c, 0xf3) | (0x2c, 0xf2) | (0x2d, 0xf3) | (0x2d, 0xf2) => Decoded::new(
+                &format!("cvt{}{}2si", if op == 0x2c { "t" } else { "" }, variant),
+                vec![self.rm_xmm(rm), Operand::Reg(self.gpr(reg, gpr_size))],
+            ),
+            (0x2e, 0) | (0x2e, 0x66) => load(if prefix == 0 { "ucomiss" } else { "ucomisd" }, self),
+            (0x2f, 0) | (0x2f, 0x66) => load(if prefix == 0 { "comiss" } else { "comisd" }, self),
+            (0x51, _) => load(&format!("sqrt{}", variant), self),
+            (0x54, 0) | (0x54, 0x66) => load(&format!("and{}", variant), self),
+            (0x55, 0) | (0x55, 0x66) => load(&format!("andn{}", variant), self),
+            (0x56, 0) | (0x56, 0x66) => load(&format!("or{}", variant), self),
+            (0x57, 0) | (0x57, 0x66) => load(&format!("xor{}", variant), self),
+            (0x58, _) => load(&format!("add{}", variant), self),
+            (0x59, _) => load(&format!("mul{}", variant), self),
+            (0x5a, _) => load(
+                match prefix {
+                    0xf3 => "cvtss2sd",
+                    0xf2 => "cvtsd2ss",
+                    0x66 => "cvtpd2ps",
+                    _ => "cvtps2pd",
+                },
+                self,
+            ),
+            (0x5b, 0xf2) => return Err(()),
+            (0x5b, _) => load(
+                match prefix {
+                    0xf3 => "cvttps2dq",
+                    0x66 => "cvtps2dq",
+                    _ => "cvtdq2ps",
+                },
+                self,
+            ),
+            (0x5c, _) => load(&format!("sub{}", variant), self),
+            (0x5d, _) => load(&format!("min{}", variant), self),
+            (0x5e, _) => load(&format!("div{}", variant), self),
+            (0x5f, _) => load(&format!("max{}", variant), self),
+            (0xe6, 0xf3) => load("cvtdq2pd", self),
+            (0xe6, 0xf2) => load("cvtpd2dq", self),
+            (0xe6, 0x66) => load("cvttpd2dq", self),
+            (0x6e, 0x66) => Decoded::new(
+                if gpr_size == 8 { "movq" } else { "movd" },
+                vec![self.rm_gpr(rm, gpr_size), xmm],
+            ),
+            (0x7e, 0x66) => Decoded::new(
+                if gpr_size == 8 { "movq" } else { "movd" },
+                vec![xmm, self.rm_gpr(rm, gpr_size)],
+            ),
+            (0x7e, 0xf3) => load("movq", self),
+            (0xd6, 0x66) => store("movq", self),
+            (0x6f, 0x66) => load("movdqa", self),
+            (0x6f, 0xf3) => load("movdqu", self),
+            (0x7f, 0x66) => store("movdqa", self),
+            (0x7f, 0xf3) => store("movdqu", self),
+            (0x70, _) | (0xc6, 0) | (0xc6, 0x66) => {
+                let name = match (op, prefix) {
+                    (0xc6, _) => format!("shuf{}", variant),
+                    (_, 0x66) => "pshufd".to_string(),
+                    (_, 0xf3) => "pshufhw".to_string(),
+                    (_, 0xf2) => "pshuflw".to_string(),
+                    _ => return Err(()),
+                };
+                let imm = self.imm(1)?;
+                Decoded::new(
+                    &name,
+                    vec![Operand::Imm(mask(imm, 1)), self.rm_xmm(rm), xmm],
+                )
+            }
+            (0x71, 0x66) | (0x72, 0x66) | (0x73, 0x66) => {
+                let lane = match op {
+                    0x71 => "w",
+                    0x72 => "d",
+                    _ => "q",
+                };
+                let name = match (op, reg & 7) {
+                    (_, 2) => format!("psrl{}", lane),
+                    (0x73, 3) => "psrldq".to_string(),
+                    (0x71, 4) | (0x72, 4) => format!("psra{}", lane),
+                    (_, 6) => format!("psll{}", lane),
+                    (0x73, 7) => "pslldq".to_string(),
+                    _ => return Err(()),
+                };
+                let imm = self.imm(1)?;
+                Decoded::new(&name, vec![Operand::Imm(mask(imm, 1)), self.rm_xmm(rm)])
+            }
+            (0xd7, 0x66) => Decoded::new(
+                "pmovmskb",
+                vec![self.rm_xmm(rm), Operand::Reg(self.gpr(reg, 4))],
+            ),
+            (0x50, 0) | (0x50, 0x66) => Decoded::new(
+                &format!("movmsk{}", variant),
+                vec![self.rm_xmm(rm), Operand::Reg(self.gpr(reg, 4))],
+            ),
+            (0xc2, _) => {
+                let predicate = ["eq", "lt", "le", "unord", "neq", "nlt", "nle", "ord"]
+                    .get(self.imm(1)? as usize)
+                    .ok_or(())?;
+                load(&format!("cmp{}{}", predicate, variant), self)
+            }
+            (0xc4, 0x66) => {
+                let imm = self.imm(1)?;
+                Decoded::new(
+                    "pinsrw",
+                    vec![Operand::Imm(mask(imm, 1)), self.rm_gpr(rm, 4), xmm],
+                )
+            }
+            (0xc5, 0x66) => {
+                let imm = self.imm(1)?;
+                Decoded::new(
+                    "pextrw",
+                    vec![
+                        Operand::Imm(mask(imm, 1)),
+                        self.rm_xmm(rm),
+                        Operand::Reg(self.gpr(reg, 4)),
+                    ],
+                )
+            }
+            (_, 0x66) => {
+                let name = match op {
+                    0x60 => "punpcklbw",
+                    0x63 => "packsswb",
+                    0x67 => "packuswb",
+                    0x6b => "packssdw",
+                    0x61 => "punpcklwd",
+                    0x62 => "punpckldq",
+                    0x64 => "pcmpgtb",
+                    0x65 => "pcmpgtw",
+                    0x66 => "pcmpgtd",
+                    0x68 => "punpckhbw",
+                    0x69 => "punpckhwd",
+                    0x6a => "punpckhdq",
+                    0x6c => "punpcklqdq",
+                    0x6d => "punpckhqdq",
+                    0x74 => "pcmpeqb",
+                    0x75 => "pcmpeqw",
+                    0x76 => "pcmpeqd",
+                    0xd1 => "psrlw",
+                    0xd2 => "psrld",
+                    0xd3 => "psrlq",
+                    0xd4 => "paddq",
+                    0xd5 => "pmullw",
+                    0xda => "pminub",
+                    0xdb => "pand",
+                    0xde => "pmaxub",
+                    0xdf => "pandn",
+                    0xe1 => "psraw",
+                    0xe2 => "psrad",
+                    0xeb => "por",
+                    0xef => "pxor",
+                    0xf1 => "psllw",
+                    0xf2 => "pslld",
+                    0xf3 => "psllq",
+                    0xf4 => "pmuludq",
+                    0xf5 => "pmaddwd",
+                    0xf6 => "psadbw",
+                    0xf8 => "psubb",
+                    0xf9 => "psubw",
+                    0xfa => "psubd",
+                    0xfb => "psubq",
+                    0xfc => "paddb",
+                    0xfd => "paddw",
+                    0xfe => "paddd",
+                    _ => return Err(()),
+                };
+                load(name, self)
+            }
+            _ => return Err(()),
+        })
+    }
+
+    /// The x87 instructions gcc uses for `long double`: mostly loads, stores and arithmetic.
+    fn decode_x87(&mut self, op: u8) -> Result<Decoded, ()> {
+        let (reg, rm) = self.modrm()?;
+        let ext = reg & 7;
+        if let Rm::Mem(mem) = rm {
+            let name = match (op, ext) {
+                (0xd8, _) | (0xdc, _) => {
+                    let base = [
+                        "fadd", "fmul", "fcom", "fcomp", "fsub", "fsubr", "fdiv", "fdivr",
+                    ];
+                    let size = if op == 0xd8 { 's' } else { 'l' };
+                    format!("{}{}", base[ext as usize], size)
+                }
+                (0xd9, 0) => "flds".to_string(),
+                (0xd9, 2) => "fsts".to_string(),
+                (0xd9, 3) => "fstps".to_string(),
+                (0xd9, 5) => "fldcw".to_string(),
+                (0xd9, 4) => "fldenv".to_string(),
+                (0xd9, 6) => "fnstenv".to_string(),
+                (0xd9, 7) => "fnstcw".to_string(),
+                (0xdb, 0) => "fildl".to_string(),
+                (0xdb, 1) => "fisttpl".to_string(),
+                (0xdb, 2) => "fistl".to_string(),
+                (0xdb, 3) => "fistpl".to_string(),
+                (0xdb, 5) => "fldt".to_string(),
+                (0xdb, 7) => "fstpt".to_string(),
+                (0xdd, 0) => "fldl".to_string(),
+                (0xdd, 1) => "fisttpll".to_string(),
+                (0xdd, 2) => "fstl".to_string(),
+                (0xdd, 3) => "fstpl".to_string(),
+                (0xdd, 7) => "fnstsw".to_string(),
+                (0xdf, 0) => "filds".to_string(),
+                (0xdf, 1) => "fisttps".to_string(),
+                (0xdf, 3) => "fistps".to_string(),
+                (0xdf, 5) => "fildll".to_string(),
+                (0xdf, 7) => "fistpll".to_string(),
+                _ => return Err(()),
+            };
+            return Ok(Decoded::new(&name, vec![Operand::Mem(mem)]));
+        }
+        let index = match rm {
+            Rm::Reg(num) => (num & 7) as usize,
+            Rm::Mem(_) => unreachable!(),
+        };
+        const ST: [&str; 8] = [
+            "%st(0)", "%st(1)", "%st(2)", "%st(3)", "%st(4)", "%st(5)", "%st(6)", "%st(7)",
+        ];
+        let st = Operand::Other(ST[index]);
+        let top = || Operand::Other("%st");
+        Ok(match (op, ext) {
+            (0xd9, 0) => Decoded::new("fld", vec![st]),
+            (0xd9, 1) => Decoded::new("fxch", vec![st]),
+            (0xd9, 4) | (0xd9, 5) | (0xd9, 6) | (0xd9, 7) => {
+                let name = [
+                    "fchs", "fabs", "", "", "ftst", "fxam", "", "", "fld1", "fldl2t", "fldl2e",
+                    "fldpi", "fldlg2", "fldln2", "fldz", "", "f2xm1", "fyl2x", "fptan", "fpatan",
+                    "fxtract", "fprem1", "fdecstp", "fincstp", "fprem", "fyl2xp1", "fsqrt",
+                    "fsincos", "frndint", "fscale", "fsin", "fcos",
+                ][(ext as usize - 4) * 8 + index];
+                if name.is_empty() {
+                    return Err(());
+                }
+                Decoded::new(name, vec![])
+            }
+            (0xdf, 4) if index == 0 => Decoded::new("fnstsw", vec![Operand::Reg("ax")]),
+            (0xdd, 2) => Decoded::new("fst", vec![st]),
+            (0xdd, 3) => Decoded::new("fstp", vec![st]),
+            (0xd8, _) | (0xdc, _) if ext != 2 && ext != 3 => {
+                let name = ["fadd", "fmul", "", "", "fsub", "fsubr", "fdiv", "fdivr"];
+                // d8 stores into %st, dc into %st(i)
+                let operands = if op == 0xd8 {
+                    vec![st, top()]
+                } else {
+                    vec![top(), st]
+                };
+                Decoded::new(name[ext as usize], operands)
+            }
+            (0xd8, 2) => Decoded::new("fcom", vec![st]),
+            (0xd8, 3) => Decoded::new("fcomp", vec![st]),
+            (0xdb, 5) => Decoded::new("fucomi", vec![st, top()]),
+            (0xdb, 6) => Decoded::new("fcomi", vec![st, top()]),
+            (0xdf, 5) => Decoded::new("fucomip", vec![st, top()]),
+            (0xdf, 6) => Decoded::new("fcomip", vec![st, top()]),
+            (0xda, 5) if index == 1 => Decoded::new("fucompp", vec![]),
+            (0xde, _) => {
+                let name = [
+                    "faddp", "fmulp", "", "", "fsubp", "fsubrp", "fdivp", "fdivrp",
+                ][ext as usize];
+                if name.is_empty() {
+                    return Err(());
+                }
+                Decoded::new(name, vec![top(), st])
+            }
+            _ => return Err(()),
+        })
+    }
+
+    fn format_mem(&self, mem: &Mem, next_addr: usize) -> (String, Option<usize>) {
+        let segment = match self.segment {
+            Some(segment) if segment == "fs" || segment == "gs" => format!("%{}:", segment),
+            _ => String::new(),
+        };
+        if mem.rip {
+            let target = (next_addr as i64).wrapping_add(mem.disp) as usize;
+            return (
+                format!("{}{}(%rip)", segment, format_disp(mem.disp)),
+                Some(target),
+            );
+        }
+        let disp = if mem.has_disp || mem.base.is_none() {
+            format_disp(mem.disp)
+        } else {
+            String::new()
+        };
+        let text = match (mem.base, mem.index) {
+            (None, None) => disp,
+            (base, index) => {
+                let base = base.map_or(String::new(), |base| format!("%{}", REG64[base as usize]));
+                let index = index.map_or(String::new(), |(index, scale)| {
+                    format!(",%{},{}", REG64[index as usize], scale)
+                });
+                format!("{}({}{})", disp, base, index)
+            }
+        };
+        (format!("{}{}", segment, text), None)
+    }
+
+    fn format(
+        &self,
+        decoded: Decoded,
+        addr: usize,
+        symbolize: &dyn Fn(usize) -> Option<String>,
+    ) -> String {
+        let next_addr = addr + self.pos;
+        let label = |target: usize| match symbolize(target) {
+            Some(symbol) => format!("{:#x} <{}>", target, symbol),
+            None => format!("{:#x}", target),
+        };
+        let mut comment = None;
+        let operands: Vec<String> = decoded
+            .operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Reg(name) => format!("%{}", name),
+                Operand::Other(name) if name.starts_with('%') => name.to_string(),
+                Operand::Other(name) => format!("%{}", name),
+                Operand::Imm(value) => format!("${:#x}", value),
+                Operand::Target(rel) => label(next_addr.wrapping_add(*rel)),
+                Operand::Mem(mem) => {
+                    let (text, target) = self.format_mem(mem, next_addr);
+                    if target.is_some() {
+                        comment = target;
+                    }
+                    text
+                }
+            })
+            .collect();
+        let mut mnemonic = decoded.mnemonic;
+        if let Some(size) = decoded.suffix_size {
+            if !decoded
+                .operands
+                .iter()
+                .any(|operand| matches!(operand, Operand::Reg(_)))
+            {
+                mnemonic.push(suffix(size));
+            }
+        }
+        let mut prefixes = String::new();
+        if self.lock {
+            prefixes.push_str("lock ");
+        }
+        // a 66 prefix beyond the one setting the operand size does nothing, and REX.W overrides
+        // even that one
+        let unused_opsize = if self.rex_w() { 0 } else { 1 };
+        for _ in unused_opsize..self.opsize_prefixes {
+            prefixes.push_str("data16 ");
+        }
+        match self.segment {
+            // 3e on an indirect branch is the CET notrack prefix
+            Some("ds") if decoded.indirect => prefixes.push_str("notrack "),
+            Some(segment) if segment != "fs" && segment != "gs" => {
+                prefixes.push_str(segment);
+                prefixes.push(' ');
+            }
+            _ => {}
+        }
+        match self.rep {
+            Some(0xf3) => prefixes.push_str("repz "),
+            Some(0xf2) if decoded.indirect || mnemonic == "ret" || mnemonic == "jmp" => {
+                prefixes.push_str("bnd ")
+            }
+            Some(_) => prefixes.push_str("repnz "),
+            None => {}
+        }
+        let mut text = format!("{}{}", prefixes, mnemonic);
+        if !operands.is_empty() {
+            text = format!(
+                "{:<6} {}{}",
+                text,
+                if decoded.indirect { "*" } else { "" },
+                operands.join(",")
+            );
+        }
+        if let Some(target) = comment {
+            text.push_str(&format!("        # {}", label(target)));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names the two addresses the samples refer to, the way the debugger's symbolizer would.
+    fn symbolize(addr: usize) -> Option<String> {
+        match addr {
+            0x19 => Some("callee".to_string()),
+            0x120 => Some("counter".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Decodes each instruction, at the address it had in the listing, and checks it against
+    /// what objdump (binutils 2.40) printed for it, with symbols added.
+    fn check(cases: &[(usize, &[u8], &str)]) {
+        for (addr, bytes, text) in cases {
+            let instruction = decode(bytes, *addr, &symbolize);
+            assert_eq!(instruction.text, *text, "at {:#x}", addr);
+            assert_eq!(instruction.len, bytes.len(), "length of {}", text);
+        }
+    }
+
+    #[test]
+    fn general_purpose() {
+        check(&[
+            (0x0, &[0x55], "push   %rbp"),
+            (0x1, &[0x48, 0x89, 0xe5], "mov    %rsp,%rbp"),
+            (0x9, &[0x4c, 0x89, 0xc7], "mov    %r8,%rdi"),
+            (
+                0xc,
+                &[0x49, 0x8b, 0x44, 0x24, 0x08],
+                "mov    0x8(%r12),%rax",
+            ),
+            (0x11, &[0x4d, 0x8b, 0x3c, 0xde], "mov    (%r14,%rbx,8),%r15"),
+            (
+                0x15,
+                &[0x8b, 0x44, 0x8b, 0x10],
+                "mov    0x10(%rbx,%rcx,4),%eax",
+            ),
+            (0x61, &[0x48, 0x83, 0xec, 0x10], "sub    $0x10,%rsp"),
+            (
+                0x65,
+                &[0xc7, 0x45, 0xfc, 0x01, 0x00, 0x00, 0x00],
+                "movl   $0x1,-0x4(%rbp)",
+            ),
+            (
+                0x76,
+                &[0x64, 0x48, 0x8b, 0x04, 0x25, 0x28, 0x00, 0x00, 0x00],
+                "mov    %fs:0x28,%rax",
+            ),
+            (0x7f, &[0x0f, 0xb6, 0x45, 0xff], "movzbl -0x1(%rbp),%eax"),
+            (0x83, &[0x48, 0x63, 0xd0], "movslq %eax,%rdx"),
+            (0x86, &[0x40, 0x88, 0xf0], "mov    %sil,%al"),
+            (0x89, &[0x88, 0xe0], "mov    %ah,%al"),
+            (0x8b, &[0xf3, 0x48, 0xab], "rep stos %rax,%es:(%rdi)"),
+            (
+                0x8e,
+                &[0x0f, 0x1f, 0x44, 0x00, 0x00],
+                "nopl   0x0(%rax,%rax,1)",
+            ),
+            (
+                0x20,
+                &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11],
+                "movabs $0x1122334455667788,%rax",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn branches() {
+        check(&[
+            (0x4, &[0xe8, 0x10, 0x00, 0x00, 0x00], "call   0x19 <callee>"),
+            (0x60, &[0xc3], "ret"),
+            (0x6c, &[0x41, 0xff, 0xd4], "call   *%r12"),
+            (
+                0x6f,
+                &[0xff, 0x24, 0xc5, 0x00, 0x10, 0x40, 0x00],
+                "jmp    *0x401000(,%rax,8)",
+            ),
+            (0x93, &[0x74, 0xfe], "je     0x93"),
+        ]);
+    }
+
+    #[test]
+    fn rip_relative() {
+        check(&[
+            (
+                0x19,
+                &[0x48, 0x8b, 0x05, 0x00, 0x01, 0x00, 0x00],
+                "mov    0x100(%rip),%rax        # 0x120 <counter>",
+            ),
+            (
+                0x3b,
+                &[0xf2, 0x44, 0x0f, 0x59, 0x05, 0x10, 0x00, 0x00, 0x00],
+                "mulsd  0x10(%rip),%xmm8        # 0x54",
+            ),
+        ]);
+    }
+
+    #[test]
+    fn sse() {
+        check(&[
+            (0x2a, &[0x66, 0x0f, 0xef, 0xc0], "pxor   %xmm0,%xmm0"),
+            (0x2e, &[0xf2, 0x0f, 0x58, 0xc1], "addsd  %xmm1,%xmm0"),
+            (
+                0x32,
+                &[0xf3, 0x0f, 0x10, 0x45, 0xfc],
+                "movss  -0x4(%rbp),%xmm0",
+            ),
+            (0x37, &[0x66, 0x0f, 0x28, 0xca], "movapd %xmm2,%xmm1"),
+        ]);
+    }
+
+    #[test]
+    fn x87() {
+        // in the dc and de register forms AT&T swaps sub with subr and div with divr relative
+        // to Intel's manual (dc e1 is Intel's fsubr), and objdump and gdb follow AT&T
+        check(&[
+            (0x44, &[0xd8, 0xe1], "fsub   %st(1),%st"),
+            (0x46, &[0xd8, 0xe9], "fsubr  %st(1),%st"),
+            (0x48, &[0xdc, 0xe1], "fsub   %st,%st(1)"),
+            (0x4a, &[0xdc, 0xe9], "fsubr  %st,%st(1)"),
+            (0x4c, &[0xdc, 0xf1], "fdiv   %st,%st(1)"),
+            (0x4e, &[0xdc, 0xf9], "fdivr  %st,%st(1)"),
+            (0x50, &[0xde, 0xe9], "fsubrp %st,%st(1)"),
+            (0x52, &[0xde, 0xe1], "fsubp  %st,%st(1)"),
+            (0x54, &[0xde, 0xf9], "fdivrp %st,%st(1)"),
+            (0x56, &[0xde, 0xf1], "fdivp  %st,%st(1)"),
+            (0x58, &[0xdd, 0x45, 0xf8], "fldl   -0x8(%rbp)"),
+            (0x5b, &[0xd9, 0xee], "fldz"),
+            (0x5d, &[0xdb, 0x6d, 0x10], "fldt   0x10(%rbp)"),
+        ]);
+    }
+
+    #[test]
+    fn truncated_and_invalid() {
+        // a call cut off in the middle of its displacement
+        let instruction = decode(&[0xe8, 0x10, 0x00], 0, &symbolize);
+        assert_eq!((instruction.text.as_str(), instruction.len), ("(bad)", 1));
+        let instruction = decode(&[0x48], 0, &symbolize);
+        assert_eq!((instruction.text.as_str(), instruction.len), ("(bad)", 1));
+        let instruction = decode(&[], 0, &symbolize);
+        assert_eq!((instruction.text.as_str(), instruction.len), ("(bad)", 1));
+        // push %es doesn't exist in 64-bit mode
+        let instruction = decode(&[0x06, 0x90], 0, &symbolize);
+        assert_eq!((instruction.text.as_str(), instruction.len), ("(bad)", 1));
+    }
+}
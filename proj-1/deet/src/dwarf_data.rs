@@ -1,7 +1,10 @@
 use crate::gimli_wrapper;
+use crate::unwind::{CallFrameInfo, Frame, Unwound};
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection};
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::path::Path;
 use std::{fmt, fs};
 
 #[derive(Debug)]
@@ -12,7 +15,18 @@ pub enum Error {
 
 pub struct DwarfData {
     files: Vec<File>,
+    /// Every type in the debug info, keyed by its offset in .debug_info.
+    types: HashMap<usize, Type>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    cfi: CallFrameInfo,
+    /// Link-time entry point from the ELF header.
+    entry: usize,
+    /// Link-time address and contents of .text, so code can be disassembled before the program
+    /// runs.
+    text: (usize, Vec<u8>),
+    /// Where the executable got loaded, relative to its link-time addresses. Zero for non-PIE
+    /// binaries. Every address going in or out of the public methods is a runtime address.
+    load_base: usize,
 }
 
 impl fmt::Debug for DwarfData {
@@ -38,65 +52,116 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        let (files, types) = gimli_wrapper::load_file(&object, endian, path)?;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian, path)?,
+            files,
+            types,
             addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            cfi: CallFrameInfo::load(&object, endian),
+            entry: object.entry() as usize,
+            text: (
+                object
+                    .section_by_name(".text")
+                    .map_or(0, |section| section.address() as usize),
+                object
+                    .section_data_by_name(".text")
+                    .map_or(Vec::new(), |data| data.into_owned()),
+            ),
+            load_base: 0,
         })
     }
 
+    pub fn entry(&self) -> usize {
+        self.entry
+    }
+
+    pub fn load_base(&self) -> usize {
+        self.load_base
+    }
+
+    pub fn set_load_base(&mut self, load_base: usize) {
+        self.load_base = load_base;
+    }
+
+    /// Translates a link-time address from the debug info into the running process.
+    pub fn to_runtime(&self, addr: usize) -> usize {
+        addr.wrapping_add(self.load_base)
+    }
+
+    fn to_link(&self, addr: usize) -> usize {
+        addr.wrapping_sub(self.load_base)
+    }
+
     #[allow(dead_code)]
     fn get_target_file(&self, file: &str) -> Option<&File> {
+        // DWARF names files relative to the compilation directory, while the target may be an
+        // absolute path (e.g. when attached via /proc/<pid>/exe)
         self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+            f.name == file
+                || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+                || file.ends_with(&format!("/{}", f.name))
         })
     }
 
+    /// Returns where a breakpoint on `line_number` should go: the lowest statement address of
+    /// the first line at or after it that has code. A function's opening line resolves past the
+    /// prologue, where its arguments are in place.
     #[allow(dead_code)]
     pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
         let target_file = match file {
             Some(filename) => self.get_target_file(filename)?,
             None => self.files.get(0)?,
         };
-        Some(
-            target_file
-                .lines
-                .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+        let addr = first_statement_from(&target_file.lines, line_number)?;
+        match self.get_function_by_addr(self.to_runtime(addr)) {
+            Some(func) if func.address == addr => Some(self.get_prologue_end(func)),
+            _ => Some(self.to_runtime(addr)),
+        }
     }
 
+    /// Returns where a breakpoint on `func_name` should go: just past its prologue.
     #[allow(dead_code)]
     pub fn get_addr_for_function(&self, file: Option<&str>, func_name: &str) -> Option<usize> {
-        match file {
-            Some(filename) => Some(
-                self.get_target_file(filename)?
-                    .functions
-                    .iter()
-                    .find(|func| func.name == func_name)?
-                    .address,
-            ),
-            None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
-                        return Some(func.address);
-                    }
-                }
-                None
-            }
-        }
+        let func = match file {
+            Some(filename) => self
+                .get_target_file(filename)?
+                .functions
+                .iter()
+                .find(|func| func.name == func_name && func.text_length > 0)?,
+            None => self.get_function_by_name(func_name)?,
+        };
+        Some(self.get_prologue_end(func))
+    }
+
+    /// Returns the names of the files defining a function called `func_name`. Static functions
+    /// in different compilation units can share a name.
+    pub fn get_files_defining(&self, func_name: &str) -> Vec<&str> {
+        self.defined_functions()
+            .filter(|(_, func)| func.name == func_name)
+            .map(|(file, _)| file.name.as_str())
+            .collect()
+    }
+
+    /// Functions along with their files, leaving out the prototypes of functions defined
+    /// elsewhere, which have no code.
+    fn defined_functions(&self) -> impl Iterator<Item = (&File, &Function)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter().map(move |func| (file, func)))
+            .filter(|(_, func)| func.text_length > 0)
     }
 
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
         let location = self
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(self.to_link(curr_addr).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
             number: location.line?.try_into().unwrap(),
             address: curr_addr,
+            is_stmt: true,
         })
     }
 
@@ -104,13 +169,200 @@ impl DwarfData {
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(self.to_link(curr_addr).try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the function whose text contains `curr_addr`. Its fields hold link-time
+    /// addresses; see `to_runtime`.
+    pub fn get_function_by_addr(&self, curr_addr: usize) -> Option<&Function> {
+        let curr_addr = self.to_link(curr_addr);
+        self.files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.address <= curr_addr && curr_addr < func.address + func.text_length)
+    }
+
+    /// Looks up a function by name in any compilation unit.
+    pub fn get_function_by_name(&self, name: &str) -> Option<&Function> {
+        self.defined_functions()
+            .map(|(_, func)| func)
+            .find(|func| func.name == name)
+    }
+
+    /// Returns the path of the source file called `name`, as the compiler saw it.
+    pub fn get_source_path(&self, name: &str) -> Option<String> {
+        Some(self.get_target_file(name)?.source_path())
+    }
+
+    /// Returns the source path and line where the function called `name` is defined.
+    pub fn get_function_source(&self, name: &str) -> Option<(String, usize)> {
+        let (file, func) = self
+            .defined_functions()
+            .find(|(_, func)| func.name == name)?;
+        Some((file.source_path(), func.line_number))
+    }
+
+    /// Reads `len` bytes of code at `addr` from the executable's .text section.
+    pub fn read_text(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        let start = self.to_link(addr).checked_sub(self.text.0)?;
+        Some(self.text.1.get(start..start + len)?.to_vec())
+    }
+
+    /// Returns the line-table rows starting in `[start, end)`, ordered by address, with one row
+    /// per address.
+    pub fn get_lines_in_range(&self, start: usize, end: usize) -> Vec<Line> {
+        let (start, end) = (self.to_link(start), self.to_link(end));
+        let mut lines: Vec<Line> = self
+            .files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .filter(|line| start <= line.address && line.address < end)
+            .map(|line| Line {
+                address: self.to_runtime(line.address),
+                ..line.clone()
+            })
+            .collect();
+        // stable, so the last row at an address wins, which is the one addr2line reports
+        lines.sort_by_key(|line| line.address);
+        lines.reverse();
+        lines.dedup_by_key(|line| line.address);
+        lines.reverse();
+        lines
+    }
+
+    /// Names the function or global variable containing `addr`, like gdb's `<main+4>` labels.
+    pub fn get_symbol(&self, addr: usize) -> Option<String> {
+        let (name, start) = match self.get_function_by_addr(addr) {
+            Some(func) => (&func.name, func.address),
+            None => {
+                let link_addr = self.to_link(addr);
+                let var = self
+                    .files
+                    .iter()
+                    .flat_map(|file| file.global_variables.iter())
+                    .find(|var| match var.location {
+                        Location::Address(start) => {
+                            start <= link_addr && link_addr < start + var.entity_type.size.max(1)
+                        }
+                        Location::FramePointerOffset(_) => false,
+                    })?;
+                match var.location {
+                    Location::Address(start) => (&var.name, start),
+                    Location::FramePointerOffset(_) => return None,
+                }
+            }
+        };
+        let offset = self.to_link(addr) - start;
+        if offset == 0 {
+            Some(name.clone())
+        } else {
+            Some(format!("{}+{}", name, offset))
+        }
+    }
+
+    /// Returns the address range `[start, end)` of the line-table row containing `curr_addr`.
+    pub fn get_line_range(&self, curr_addr: usize) -> Option<(usize, usize)> {
+        let func = self.get_function_by_addr(curr_addr)?;
+        let curr_addr = self.to_link(curr_addr);
+        let mut start = None;
+        let mut end = func.address + func.text_length;
+        for line in self.files.iter().flat_map(|file| file.lines.iter()) {
+            if line.address < func.address {
+                continue;
+            }
+            if line.address <= curr_addr {
+                start = Some(start.map_or(line.address, |addr: usize| addr.max(line.address)));
+            } else if line.address < end {
+                end = line.address;
+            }
+        }
+        Some((self.to_runtime(start?), self.to_runtime(end)))
+    }
+
+    /// Returns true if some line-table row starts exactly at `curr_addr`.
+    pub fn is_line_start(&self, curr_addr: usize) -> bool {
+        let curr_addr = self.to_link(curr_addr);
+        self.files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .any(|line| line.address == curr_addr)
+    }
+
+    /// Returns the address of the first statement past the entry of `func`. At -O0 that is
+    /// where the prologue ends and the locals become addressable.
+    pub fn get_prologue_end(&self, func: &Function) -> usize {
+        let addr = self
+            .files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .filter(|line| line.is_stmt)
+            .map(|line| line.address)
+            .filter(|addr| *addr > func.address && *addr < func.address + func.text_length)
+            .min()
+            .unwrap_or(func.address);
+        self.to_runtime(addr)
+    }
+
+    /// Looks up a type by its offset in .debug_info, which is how struct members refer to theirs.
+    pub fn get_type(&self, offset: usize) -> Option<&Type> {
+        self.types.get(&offset)
+    }
+
+    /// Looks up a variable visible from `curr_addr`: locals and parameters of the enclosing
+    /// function shadow globals.
+    pub fn get_variable(&self, curr_addr: usize, name: &str) -> Option<&Variable> {
+        if let Some(func) = self.get_function_by_addr(curr_addr) {
+            if let Some(var) = func.variables.iter().find(|var| var.name == name) {
+                return Some(var);
+            }
+        }
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Returns the parameters and locals of the function containing `curr_addr`.
+    pub fn get_function_variables(&self, curr_addr: usize) -> Option<&Vec<Variable>> {
+        Some(&self.get_function_by_addr(curr_addr)?.variables)
+    }
+
+    /// Recovers the caller of `frame` from the executable's call frame information.
+    pub fn unwind_frame<F>(
+        &self,
+        frame: &Frame,
+        innermost: bool,
+        read_word: F,
+    ) -> Result<Unwound, String>
+    where
+        F: Fn(usize) -> Result<usize, String>,
+    {
+        let pc = frame.lookup_pc(innermost);
+        self.cfi.unwind(frame, self.to_link(pc), read_word)
+    }
+
+    /// Computes the CFA of `frame`, which is what DW_AT_frame_base (DW_OP_call_frame_cfa) refers
+    /// to. Without CFI we assume a standard frame, where it sits 16 bytes above rbp (saved rbp and
+    /// return address).
+    pub fn frame_cfa(&self, frame: &Frame, innermost: bool) -> usize {
+        let pc = frame.lookup_pc(innermost);
+        self.cfi
+            .cfa(frame, self.to_link(pc))
+            .unwrap_or(frame.rbp + 16)
+    }
+
+    /// Computes the runtime address of `var` given the CFA of the frame it lives in.
+    pub fn variable_address(&self, var: &Variable, cfa: usize) -> usize {
+        match var.location {
+            Location::Address(addr) => self.to_runtime(addr),
+            Location::FramePointerOffset(offset) => (cfa as isize + offset) as usize,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -148,19 +400,113 @@ impl DwarfData {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Type {
+    /// The type as C would spell it, e.g. `struct node *` or `int [4]`.
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Base(Encoding),
+    /// None for `void *`.
+    Pointer(Option<Box<Type>>),
+    /// Element type and count; the count is unknown for flexible array members.
+    Array(Box<Type>, Option<usize>),
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    Enum(Vec<(String, i64)>),
+    /// A typedef or a const/volatile-qualified type, which is laid out like its target.
+    Alias(Option<Box<Type>>),
+    Function,
+}
+
+/// How a base type's bytes are interpreted (DW_AT_encoding).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Signed,
+    Unsigned,
+    SignedChar,
+    UnsignedChar,
+    Float,
+    Bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    /// Offset of the member's type in .debug_info; see `DwarfData::get_type`. Members refer to
+    /// their types indirectly so that structs can point to themselves.
+    pub type_offset: usize,
+    /// Byte offset of the member within the struct.
+    pub offset: usize,
 }
 
 impl Type {
-    pub fn new(name: String, size: usize) -> Self {
+    pub fn new(name: String, size: usize, kind: TypeKind) -> Self {
         Type {
             name: name,
             size: size,
+            kind: kind,
         }
     }
+
+    pub fn pointer_to(target: Option<Type>) -> Self {
+        let name = match &target {
+            // `int (*)()` for function pointers
+            Some(target) if target.is_function() => target.name.replacen(" ()", " (*)()", 1),
+            // `int (*)[3]` for pointers to arrays
+            Some(target) if matches!(target.kind, TypeKind::Array(..)) => {
+                target.name.replacen(" [", " (*)[", 1)
+            }
+            Some(target) if target.name.ends_with('*') => format!("{}*", target.name),
+            Some(target) => format!("{} *", target.name),
+            None => "void *".to_string(),
+        };
+        Type::new(
+            name,
+            std::mem::size_of::<usize>(),
+            TypeKind::Pointer(target.map(Box::new)),
+        )
+    }
+
+    pub fn array_of(element: Type, count: Option<usize>) -> Self {
+        let dimension = match count {
+            Some(count) => format!("[{}]", count),
+            None => "[]".to_string(),
+        };
+        // the new dimension goes before any the element type already has: int [2][3]
+        let name = match element.name.find(" [") {
+            Some(index) => format!(
+                "{} {}{}",
+                &element.name[..index],
+                dimension,
+                &element.name[index + 1..]
+            ),
+            None if element.name.ends_with('*') => format!("{}{}", element.name, dimension),
+            None => format!("{} {}", element.name, dimension),
+        };
+        let size = element.size * count.unwrap_or(0);
+        Type::new(name, size, TypeKind::Array(Box::new(element), count))
+    }
+
+    pub fn base(name: &str, size: usize, encoding: Encoding) -> Self {
+        Type::new(name.to_string(), size, TypeKind::Base(encoding))
+    }
+
+    /// Looks through typedefs and qualifiers to the type that decides the layout.
+    pub fn strip_aliases(&self) -> &Type {
+        match &self.kind {
+            TypeKind::Alias(Some(target)) => target.strip_aliases(),
+            _ => self,
+        }
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self.strip_aliases().kind, TypeKind::Function)
+    }
 }
 
 #[derive(Clone)]
@@ -191,6 +537,7 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -200,21 +547,36 @@ pub struct Function {
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
     pub variables: Vec<Variable>,
+    pub return_type: Option<Type>,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct File {
     pub name: String,
+    /// Directory the compiler ran in, which relative source paths start from.
+    pub comp_dir: String,
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
 }
 
+impl File {
+    /// The file's name resolved against the compilation directory.
+    pub fn source_path(&self) -> String {
+        Path::new(&self.comp_dir)
+            .join(&self.name)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Line {
     pub file: String,
     pub number: usize,
     pub address: usize,
+    /// Whether the row is a recommended breakpoint location for its line.
+    pub is_stmt: bool,
 }
 
 impl fmt::Display for Line {
@@ -223,4 +585,52 @@ impl fmt::Display for Line {
     }
 }
 
+/// Finds the lowest statement address of the first line at or after `line_number` that has
+/// code, as an address in the debug info.
+fn first_statement_from(lines: &[Line], line_number: usize) -> Option<usize> {
+    let statements = || lines.iter().filter(|line| line.is_stmt);
+    let number = statements()
+        .map(|line| line.number)
+        .filter(|number| *number >= line_number)
+        .min()?;
+    statements()
+        .filter(|line| line.number == number)
+        .map(|line| line.address)
+        .min()
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(number: usize, address: usize, is_stmt: bool) -> Line {
+        Line {
+            file: "main.c".to_string(),
+            number,
+            address,
+            is_stmt,
+        }
+    }
+
+    #[test]
+    fn breakpoints_go_on_the_first_line_with_code() {
+        // rows come in address order, and a line's code can be split up
+        let lines = vec![
+            line(3, 0x10, true),
+            line(4, 0x18, false),
+            line(6, 0x20, true),
+            line(4, 0x28, true),
+            line(6, 0x30, true),
+            line(8, 0x38, false),
+        ];
+        assert_eq!(first_statement_from(&lines, 1), Some(0x10));
+        assert_eq!(first_statement_from(&lines, 3), Some(0x10));
+        // not the row that isn't a statement
+        assert_eq!(first_statement_from(&lines, 4), Some(0x28));
+        // line 5 has no code, so the breakpoint goes on 6, at its lowest address
+        assert_eq!(first_statement_from(&lines, 5), Some(0x20));
+        // nothing from line 7 on is a statement
+        assert_eq!(first_statement_from(&lines, 7), None);
+        assert_eq!(first_statement_from(&[], 1), None);
+    }
+}
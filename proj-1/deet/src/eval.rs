@@ -0,0 +1,1181 @@
+use crate::dwarf_data::{Encoding, Type, TypeKind};
+use crate::value::{read_unsigned, to_number, Number};
+
+/// What expressions are evaluated against: the variables in scope and the inferior's memory.
+pub trait Context {
+    /// Finds a variable visible from the selected frame, returning its type and address.
+    fn lookup_variable(&self, name: &str) -> Result<(Type, usize), String>;
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String>;
+    fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), String>;
+    /// Looks up a type by its offset in the debug info, for struct members.
+    fn get_type(&self, offset: usize) -> Option<Type>;
+    /// Names the symbol `addr` falls in, e.g. `main+4`, for printing code pointers.
+    fn symbol_at(&self, addr: usize) -> Option<String>;
+    /// Reads a register of the selected frame by name, e.g. `rax` for `$rax`.
+    fn read_register(&self, name: &str) -> Result<u64, String>;
+    fn write_register(&mut self, name: &str, value: u64) -> Result<(), String>;
+}
+
+/// The result of evaluating an expression. Values that live in the inferior remember where, so
+/// that `&`, `.`, `[]` and `=` can be applied to them.
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub entity_type: Type,
+    pub address: Option<usize>,
+    pub bytes: Vec<u8>,
+}
+
+impl Value {
+    /// Where the value points, for commands like `x` that take an address: the value of a
+    /// pointer or integer, or the location of an array.
+    pub fn to_address(&self) -> Result<usize, String> {
+        match number(self)? {
+            Number::Int(value) => Ok(value as usize),
+            Number::Float(_) => Err("Invalid floating value found in program.".to_string()),
+        }
+    }
+
+    /// Whether the value counts as true in C, i.e. is non-zero.
+    pub fn is_true(&self) -> Result<bool, String> {
+        Ok(match number(self)? {
+            Number::Int(value) => value != 0,
+            Number::Float(value) => value != 0.0,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Variable(String),
+    /// `$rax`
+    Register(String),
+    Number(i64),
+    Float(f64),
+    Char(u8),
+    Unary(UnaryOp, Box<Expr>),
+    Deref(Box<Expr>),
+    AddressOf(Box<Expr>),
+    /// `a.b`
+    Member(Box<Expr>, String),
+    /// `a->b`
+    PointerMember(Box<Expr>, String),
+    /// `a[i]`
+    Index(Box<Expr>, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Assign(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+/// Binary operators with their C precedence; higher binds tighter.
+const BINARY_OPS: [(&str, BinaryOp, u8); 18] = [
+    ("||", BinaryOp::Or, 1),
+    ("&&", BinaryOp::And, 2),
+    ("|", BinaryOp::BitOr, 3),
+    ("^", BinaryOp::BitXor, 4),
+    ("&", BinaryOp::BitAnd, 5),
+    ("==", BinaryOp::Eq, 6),
+    ("!=", BinaryOp::Ne, 6),
+    ("<", BinaryOp::Lt, 7),
+    ("<=", BinaryOp::Le, 7),
+    (">", BinaryOp::Gt, 7),
+    (">=", BinaryOp::Ge, 7),
+    ("<<", BinaryOp::Shl, 8),
+    (">>", BinaryOp::Shr, 8),
+    ("+", BinaryOp::Add, 9),
+    ("-", BinaryOp::Sub, 9),
+    ("*", BinaryOp::Mul, 10),
+    ("/", BinaryOp::Div, 10),
+    ("%", BinaryOp::Rem, 10),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Register(String),
+    Number(i64),
+    Float(f64),
+    Char(u8),
+    Punct(&'static str),
+}
+
+// longer punctuation comes first so `->` isn't read as `-`
+const PUNCTUATION: [&str; 27] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*", "&", ".", "[", "]", "(", ")", "+",
+    "-", "/", "%", "<", ">", "=", "!", "~", "|", "^",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '$' {
+            let start = i + 1;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Register(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let (end, float) = lex_number(&chars, i);
+            let literal: String = chars[i..end].iter().collect();
+            i = end;
+            if float {
+                let value = literal
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid number \"{}\".", literal))?;
+                tokens.push(Token::Float(value));
+            } else {
+                tokens.push(Token::Number(parse_number(&literal)?));
+            }
+        } else if c == '\'' {
+            let (byte, len) = parse_char(&chars[i + 1..])?;
+            tokens.push(Token::Char(byte));
+            i += len + 1;
+        } else {
+            let rest: String = chars[i..].iter().collect();
+            let punct = PUNCTUATION
+                .iter()
+                .find(|punct| rest.starts_with(*punct))
+                .ok_or_else(|| format!("Invalid character '{}' in expression.", c))?;
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Finds the end of the number starting at `chars[start]`, and whether it is floating point:
+/// one with a fraction or an exponent, like `2.5` or `1e5`. An exponent's sign is part of it.
+fn lex_number(chars: &[char], start: usize) -> (usize, bool) {
+    let digits = |mut i: usize| {
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+    let suffix = |mut i: usize| {
+        while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        i
+    };
+    let hex = chars[start] == '0' && matches!(chars.get(start + 1), Some('x') | Some('X'));
+    if hex {
+        return (suffix(start), false);
+    }
+    let mut i = digits(start);
+    let mut float = false;
+    if chars.get(i) == Some(&'.') {
+        i = digits(i + 1);
+        float = true;
+    }
+    if let Some('e') | Some('E') = chars.get(i) {
+        let sign = match chars.get(i + 1) {
+            Some('+') | Some('-') => 1,
+            _ => 0,
+        };
+        let exponent = i + 1 + sign;
+        if exponent < chars.len() && chars[exponent].is_ascii_digit() {
+            i = digits(exponent);
+            float = true;
+        }
+    }
+    (suffix(i), float)
+}
+
+fn parse_number(literal: &str) -> Result<i64, String> {
+    // C integer suffixes don't change anything we care about
+    let literal = literal.trim_end_matches(&['u', 'U', 'l', 'L'][..]);
+    let parsed = if literal.starts_with("0x") || literal.starts_with("0X") {
+        u64::from_str_radix(&literal[2..], 16).map(|value| value as i64)
+    } else if literal.len() > 1 && literal.starts_with('0') {
+        i64::from_str_radix(&literal[1..], 8)
+    } else {
+        literal.parse::<i64>()
+    };
+    parsed.map_err(|_| format!("Invalid number \"{}\".", literal))
+}
+
+/// Parses a character literal following the opening quote, returning the byte and how many
+/// characters it took up, including the closing quote.
+fn parse_char(chars: &[char]) -> Result<(u8, usize), String> {
+    let unmatched = || "Unmatched single quote.".to_string();
+    let (byte, len) = match chars.first() {
+        Some('\\') => {
+            let escaped = *chars.get(1).ok_or_else(unmatched)?;
+            match escaped {
+                'n' => (b'\n', 2),
+                't' => (b'\t', 2),
+                'r' => (b'\r', 2),
+                'a' => (7, 2),
+                'b' => (8, 2),
+                'f' => (12, 2),
+                'v' => (11, 2),
+                'e' => (27, 2),
+                'x' => {
+                    let digits: String = chars[2..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_hexdigit())
+                        .collect();
+                    let byte = u8::from_str_radix(&digits, 16)
+                        .map_err(|_| "\\x escape without a following hex digit".to_string())?;
+                    (byte, 2 + digits.len())
+                }
+                '0'..='7' => {
+                    let digits: String = chars[1..]
+                        .iter()
+                        .take(3)
+                        .take_while(|c| ('0'..='7').contains(c))
+                        .collect();
+                    let byte = u8::from_str_radix(&digits, 8)
+                        .map_err(|_| format!("Invalid escape \\{}.", digits))?;
+                    (byte, 1 + digits.len())
+                }
+                other if other.is_ascii() => (other as u8, 2),
+                _ => return Err("Only ASCII character literals are supported.".to_string()),
+            }
+        }
+        Some('\'') | None => return Err("Empty character constant.".to_string()),
+        Some(c) if c.is_ascii() => (*c as u8, 1),
+        Some(_) => return Err("Only ASCII character literals are supported.".to_string()),
+    };
+    if chars.get(len) != Some(&'\'') {
+        return Err(unmatched());
+    }
+    Ok((byte, len + 1))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Punct(p)) if *p == punct) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(format!(
+                "A syntax error in expression, expected '{}'.",
+                punct
+            ))
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let lhs = self.binary(1)?;
+        // assignment is right-associative: a = b = 1
+        if self.eat("=") {
+            return Ok(Expr::Assign(Box::new(lhs), Box::new(self.expression()?)));
+        }
+        Ok(lhs)
+    }
+
+    /// Parses a chain of binary operators binding at least as tightly as `min_precedence`.
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some((op, precedence)) = self.peek_binary_op() {
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn peek_binary_op(&self) -> Option<(BinaryOp, u8)> {
+        match self.peek() {
+            Some(Token::Punct(punct)) => BINARY_OPS
+                .iter()
+                .find(|(symbol, _, _)| symbol == punct)
+                .map(|(_, op, precedence)| (*op, *precedence)),
+            _ => None,
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("*") {
+            return Ok(Expr::Deref(Box::new(self.unary()?)));
+        }
+        if self.eat("&") {
+            return Ok(Expr::AddressOf(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)));
+        }
+        if self.eat("~") {
+            return Ok(Expr::Unary(UnaryOp::BitNot, Box::new(self.unary()?)));
+        }
+        if self.eat("+") {
+            return self.unary();
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat(".") {
+                expr = Expr::Member(Box::new(expr), self.member_name()?);
+            } else if self.eat("->") {
+                expr = Expr::PointerMember(Box::new(expr), self.member_name()?);
+            } else if self.eat("[") {
+                let index = self.expression()?;
+                self.expect("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn member_name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            _ => Err("A syntax error in expression, expected a member name.".to_string()),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Register(name)) => Ok(Expr::Register(name)),
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Float(value)) => Ok(Expr::Float(value)),
+            Some(Token::Char(byte)) => Ok(Expr::Char(byte)),
+            Some(Token::Punct("(")) => {
+                let expr = self.expression()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err("A syntax error in expression.".to_string()),
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+    if parser.peek().is_none() {
+        return Err("Argument required (expression to compute).".to_string());
+    }
+    let expr = parser.expression()?;
+    if parser.peek().is_some() {
+        return Err("A syntax error in expression.".to_string());
+    }
+    Ok(expr)
+}
+
+/// Reads a value of `entity_type` out of the inferior.
+fn load(context: &dyn Context, entity_type: Type, addr: usize) -> Result<Value, String> {
+    let bytes = context
+        .read_memory(addr, entity_type.size)
+        .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+    Ok(Value {
+        entity_type,
+        address: Some(addr),
+        bytes,
+    })
+}
+
+fn int_value(value: i64) -> Value {
+    let entity_type = if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
+        Type::base("int", 4, Encoding::Signed)
+    } else {
+        Type::base("long", 8, Encoding::Signed)
+    };
+    number_value(Number::Int(value), entity_type)
+}
+
+/// Builds a value of the given base type out of a number, truncating it to fit.
+fn number_value(number: Number, entity_type: Type) -> Value {
+    let bytes = match (number, entity_type.size) {
+        (number, 4) if is_float(&entity_type) => (number.as_f64() as f32).to_le_bytes().to_vec(),
+        (number, _) if is_float(&entity_type) => number.as_f64().to_le_bytes().to_vec(),
+        (Number::Int(value), size) => value.to_le_bytes()[..size.min(8)].to_vec(),
+        (Number::Float(value), size) => (value as i64).to_le_bytes()[..size.min(8)].to_vec(),
+    };
+    Value {
+        entity_type,
+        address: None,
+        bytes,
+    }
+}
+
+fn is_float(entity_type: &Type) -> bool {
+    matches!(
+        entity_type.strip_aliases().kind,
+        TypeKind::Base(Encoding::Float)
+    )
+}
+
+fn is_unsigned(entity_type: &Type) -> bool {
+    matches!(
+        entity_type.strip_aliases().kind,
+        TypeKind::Base(Encoding::Unsigned)
+            | TypeKind::Base(Encoding::UnsignedChar)
+            | TypeKind::Base(Encoding::Bool)
+            | TypeKind::Pointer(_)
+    )
+}
+
+/// Reads a scalar as a number. Arrays decay to the address of their first element.
+fn number(value: &Value) -> Result<Number, String> {
+    match (&value.entity_type.strip_aliases().kind, value.address) {
+        (TypeKind::Array(..), Some(addr)) => Ok(Number::Int(addr as i64)),
+        _ => to_number(&value.entity_type, &value.bytes),
+    }
+}
+
+/// Reads an integer (or pointer, or enum) value, for indexing and bitwise operations.
+fn integer(value: &Value) -> Result<i64, String> {
+    match number(value)? {
+        Number::Int(value) => Ok(value),
+        Number::Float(_) => Err("Integer only operation.".to_string()),
+    }
+}
+
+/// Finds what `value` points to: the target of a pointer, or the first element of an array.
+fn pointee(value: &Value) -> Result<(Type, usize), String> {
+    match &value.entity_type.strip_aliases().kind {
+        TypeKind::Pointer(Some(target)) => Ok((
+            (**target).clone(),
+            read_unsigned(&value.bytes, value.entity_type.size) as usize,
+        )),
+        TypeKind::Pointer(None) => {
+            Err("Attempt to take contents of a non-pointer value.".to_string())
+        }
+        TypeKind::Array(element, _) => match value.address {
+            Some(addr) => Ok(((**element).clone(), addr)),
+            None => Err("Attempt to take address of value not located in memory.".to_string()),
+        },
+        _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+    }
+}
+
+fn is_pointer_like(value: &Value) -> bool {
+    matches!(
+        value.entity_type.strip_aliases().kind,
+        TypeKind::Pointer(Some(_)) | TypeKind::Array(..)
+    )
+}
+
+fn member(context: &dyn Context, value: Value, name: &str) -> Result<Value, String> {
+    let members = match &value.entity_type.strip_aliases().kind {
+        TypeKind::Struct(members) | TypeKind::Union(members) => members,
+        _ => {
+            return Err(
+                "Attempt to extract a component of a value that is not a structure.".to_string(),
+            )
+        }
+    };
+    let member = members
+        .iter()
+        .find(|member| member.name == name)
+        .ok_or_else(|| format!("There is no member named {}.", name))?;
+    let entity_type = context
+        .get_type(member.type_offset)
+        .ok_or_else(|| format!("Unknown type of member {}.", name))?;
+    let end = member.offset + entity_type.size;
+    let bytes = value
+        .bytes
+        .get(member.offset..end)
+        .ok_or_else(|| format!("Member {} lies outside its structure.", name))?
+        .to_vec();
+    Ok(Value {
+        entity_type,
+        address: value.address.map(|addr| addr + member.offset),
+        bytes,
+    })
+}
+
+/// The type C's usual arithmetic conversions give two integer operands: at least int, and
+/// unsigned if the wider operand is.
+fn common_int_type(lhs: &Type, rhs: &Type) -> Type {
+    let size = if lhs.size.max(rhs.size) > 4 { 8 } else { 4 };
+    let unsigned = (is_unsigned(lhs) && lhs.size >= size) || (is_unsigned(rhs) && rhs.size >= size);
+    match (size, unsigned) {
+        (8, true) => Type::base("unsigned long", 8, Encoding::Unsigned),
+        (8, false) => Type::base("long", 8, Encoding::Signed),
+        (_, true) => Type::base("unsigned int", 4, Encoding::Unsigned),
+        _ => Type::base("int", 4, Encoding::Signed),
+    }
+}
+
+fn unary(op: UnaryOp, operand: Value) -> Result<Value, String> {
+    match op {
+        UnaryOp::Not => Ok(int_value(!operand.is_true()? as i64)),
+        UnaryOp::Neg => match number(&operand)? {
+            Number::Float(value) => Ok(number_value(
+                Number::Float(-value),
+                operand.entity_type.strip_aliases().clone(),
+            )),
+            Number::Int(value) => Ok(number_value(
+                Number::Int(value.wrapping_neg()),
+                common_int_type(&operand.entity_type, &operand.entity_type),
+            )),
+        },
+        UnaryOp::BitNot => Ok(number_value(
+            Number::Int(!integer(&operand)?),
+            common_int_type(&operand.entity_type, &operand.entity_type),
+        )),
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, String> {
+    // pointer arithmetic scales by the size of what is pointed to
+    match op {
+        BinaryOp::Add | BinaryOp::Sub if is_pointer_like(&lhs) && is_pointer_like(&rhs) => {
+            if op == BinaryOp::Add {
+                return Err("Cannot add two pointers.".to_string());
+            }
+            let (element, lhs_addr) = pointee(&lhs)?;
+            let (_, rhs_addr) = pointee(&rhs)?;
+            let difference = (lhs_addr as i64).wrapping_sub(rhs_addr as i64);
+            return Ok(number_value(
+                Number::Int(difference / element.size.max(1) as i64),
+                Type::base("long", 8, Encoding::Signed),
+            ));
+        }
+        BinaryOp::Add | BinaryOp::Sub if is_pointer_like(&lhs) => {
+            let (element, addr) = pointee(&lhs)?;
+            let offset = integer(&rhs)?.wrapping_mul(element.size as i64);
+            let offset = if op == BinaryOp::Sub {
+                offset.wrapping_neg()
+            } else {
+                offset
+            };
+            return Ok(number_value(
+                Number::Int((addr as i64).wrapping_add(offset)),
+                Type::pointer_to(Some(element)),
+            ));
+        }
+        BinaryOp::Add if is_pointer_like(&rhs) => return binary(op, rhs, lhs),
+        _ => {}
+    }
+
+    let lhs_number = number(&lhs)?;
+    let rhs_number = number(&rhs)?;
+    let bool_value = |value: bool| Ok(int_value(value as i64));
+    if let (Number::Int(_), Number::Int(_)) = (lhs_number, rhs_number) {
+        // pointers and arrays compare as unsigned addresses
+        let lhs_type = if is_pointer_like(&lhs) {
+            Type::base("unsigned long", 8, Encoding::Unsigned)
+        } else {
+            lhs.entity_type.clone()
+        };
+        let result_type = common_int_type(&lhs_type, &rhs.entity_type);
+        let unsigned = is_unsigned(&result_type);
+        let (lhs, rhs) = (integer(&lhs)?, integer(&rhs)?);
+        let ordering = if unsigned {
+            (lhs as u64).cmp(&(rhs as u64))
+        } else {
+            lhs.cmp(&rhs)
+        };
+        let value = match op {
+            BinaryOp::Lt => return bool_value(ordering == std::cmp::Ordering::Less),
+            BinaryOp::Le => return bool_value(ordering != std::cmp::Ordering::Greater),
+            BinaryOp::Gt => return bool_value(ordering == std::cmp::Ordering::Greater),
+            BinaryOp::Ge => return bool_value(ordering != std::cmp::Ordering::Less),
+            BinaryOp::Eq => return bool_value(lhs == rhs),
+            BinaryOp::Ne => return bool_value(lhs != rhs),
+            BinaryOp::And => return bool_value(lhs != 0 && rhs != 0),
+            BinaryOp::Or => return bool_value(lhs != 0 || rhs != 0),
+            BinaryOp::Div | BinaryOp::Rem if rhs == 0 => return Err("Division by zero".to_string()),
+            BinaryOp::Div if unsigned => ((lhs as u64) / (rhs as u64)) as i64,
+            BinaryOp::Rem if unsigned => ((lhs as u64) % (rhs as u64)) as i64,
+            BinaryOp::Div => lhs.wrapping_div(rhs),
+            BinaryOp::Rem => lhs.wrapping_rem(rhs),
+            BinaryOp::Mul => lhs.wrapping_mul(rhs),
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+            BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+            BinaryOp::Shr if unsigned => (lhs as u64).wrapping_shr(rhs as u32) as i64,
+            BinaryOp::Shr => lhs.wrapping_shr(rhs as u32),
+            BinaryOp::BitAnd => lhs & rhs,
+            BinaryOp::BitXor => lhs ^ rhs,
+            BinaryOp::BitOr => lhs | rhs,
+        };
+        return Ok(number_value(Number::Int(value), result_type));
+    }
+
+    let (lhs, rhs) = (lhs_number.as_f64(), rhs_number.as_f64());
+    let value = match op {
+        BinaryOp::Lt => return bool_value(lhs < rhs),
+        BinaryOp::Le => return bool_value(lhs <= rhs),
+        BinaryOp::Gt => return bool_value(lhs > rhs),
+        BinaryOp::Ge => return bool_value(lhs >= rhs),
+        BinaryOp::Eq => return bool_value(lhs == rhs),
+        BinaryOp::Ne => return bool_value(lhs != rhs),
+        BinaryOp::And => return bool_value(lhs != 0.0 && rhs != 0.0),
+        BinaryOp::Or => return bool_value(lhs != 0.0 || rhs != 0.0),
+        BinaryOp::Mul => lhs * rhs,
+        BinaryOp::Div => lhs / rhs,
+        BinaryOp::Add => lhs + rhs,
+        BinaryOp::Sub => lhs - rhs,
+        _ => return Err("Integer only operation.".to_string()),
+    };
+    Ok(number_value(
+        Number::Float(value),
+        Type::base("double", 8, Encoding::Float),
+    ))
+}
+
+/// Types registers the way gdb does: the stack and frame pointers point to data, the
+/// instruction pointer to code.
+fn register_type(name: &str) -> Type {
+    match name {
+        "rip" | "pc" => Type::pointer_to(Some(Type::new(
+            "void ()".to_string(),
+            1,
+            TypeKind::Function,
+        ))),
+        "rsp" | "rbp" | "sp" | "fp" => Type::pointer_to(None),
+        "eflags" => Type::base("int", 4, Encoding::Signed),
+        _ => Type::base("long", 8, Encoding::Signed),
+    }
+}
+
+/// Converts `value` to the representation of `entity_type`, for storing it.
+fn convert(value: &Value, entity_type: &Type) -> Result<Vec<u8>, String> {
+    match &entity_type.strip_aliases().kind {
+        TypeKind::Base(Encoding::Bool) => Ok(vec![value.is_true()? as u8]),
+        TypeKind::Base(_) | TypeKind::Pointer(_) | TypeKind::Enum(_) => {
+            Ok(number_value(number(value)?, entity_type.clone()).bytes)
+        }
+        _ if value.entity_type.strip_aliases().name == entity_type.strip_aliases().name
+            && value.bytes.len() == entity_type.size =>
+        {
+            Ok(value.bytes.clone())
+        }
+        _ => Err(format!(
+            "Invalid cast from {} to {}.",
+            value.entity_type.name, entity_type.name
+        )),
+    }
+}
+
+pub fn evaluate(context: &mut dyn Context, expr: &Expr) -> Result<Value, String> {
+    match expr {
+        Expr::Variable(name) => {
+            let (entity_type, addr) = context.lookup_variable(name)?;
+            load(context, entity_type, addr)
+        }
+        Expr::Register(name) => {
+            let value = context.read_register(name)?;
+            Ok(number_value(Number::Int(value as i64), register_type(name)))
+        }
+        Expr::Number(value) => Ok(int_value(*value)),
+        Expr::Float(value) => Ok(number_value(
+            Number::Float(*value),
+            Type::base("double", 8, Encoding::Float),
+        )),
+        Expr::Char(byte) => Ok(Value {
+            entity_type: Type::base("char", 1, Encoding::SignedChar),
+            address: None,
+            bytes: vec![*byte],
+        }),
+        Expr::Unary(op, operand) => unary(*op, evaluate(context, operand)?),
+        Expr::Deref(operand) => {
+            let (target, addr) = pointee(&evaluate(context, operand)?)?;
+            if target.is_function() {
+                return Err("Attempt to take contents of a function pointer.".to_string());
+            }
+            load(context, target, addr)
+        }
+        Expr::AddressOf(operand) => {
+            let value = evaluate(context, operand)?;
+            let addr = value
+                .address
+                .ok_or("Attempt to take address of value not located in memory.")?;
+            Ok(Value {
+                entity_type: Type::pointer_to(Some(value.entity_type)),
+                address: None,
+                bytes: addr.to_le_bytes().to_vec(),
+            })
+        }
+        Expr::Member(operand, name) => {
+            let value = evaluate(context, operand)?;
+            member(context, value, name)
+        }
+        Expr::PointerMember(operand, name) => {
+            let (target, addr) = pointee(&evaluate(context, operand)?)?;
+            let value = load(context, target, addr)?;
+            member(context, value, name)
+        }
+        Expr::Index(operand, index) => {
+            let (element, base) = pointee(&evaluate(context, operand)?)?;
+            let index = integer(&evaluate(context, index)?)?;
+            let addr = (base as i64).wrapping_add(index.wrapping_mul(element.size as i64));
+            load(context, element, addr as usize)
+        }
+        // && and || only evaluate their right side when they have to
+        Expr::Binary(BinaryOp::And, lhs, rhs) => {
+            let value = evaluate(context, lhs)?.is_true()? && evaluate(context, rhs)?.is_true()?;
+            Ok(int_value(value as i64))
+        }
+        Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+            let value = evaluate(context, lhs)?.is_true()? || evaluate(context, rhs)?.is_true()?;
+            Ok(int_value(value as i64))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = evaluate(context, lhs)?;
+            let rhs = evaluate(context, rhs)?;
+            binary(*op, lhs, rhs)
+        }
+        Expr::Assign(lhs, rhs) => {
+            if let Expr::Register(name) = &**lhs {
+                let value = evaluate(context, rhs)?;
+                let bytes = convert(&value, &register_type(name))?;
+                context.write_register(name, read_unsigned(&bytes, bytes.len()))?;
+                return evaluate(context, lhs);
+            }
+            let target = evaluate(context, lhs)?;
+            let addr = target
+                .address
+                .ok_or("Left operand of assignment is not an lvalue.")?;
+            let value = evaluate(context, rhs)?;
+            let bytes = convert(&value, &target.entity_type)?;
+            context
+                .write_memory(addr, &bytes)
+                .map_err(|_| format!("Cannot access memory at address {:#x}", addr))?;
+            Ok(Value {
+                entity_type: target.entity_type,
+                address: Some(addr),
+                bytes,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::dwarf_data::Member;
+    use crate::value::format_value;
+    use std::collections::HashMap;
+
+    pub(crate) const INT: usize = 1;
+    const NODE_POINTER: usize = 2;
+
+    /// A stopped program with a few variables:
+    ///
+    /// ```c
+    /// int a = 5;               // 0x100
+    /// unsigned int u = 1;      // 0x104
+    /// double d = 2.5;          // 0x108
+    /// char c = 'x';            // 0x110
+    /// int arr[4] = {10, 20, 30, 40};  // 0x1000
+    /// int *p = arr;            // 0x2000
+    /// struct node { int val; struct node *next; } n = {5, &m}, m = {6, 0};  // 0x3000, 0x3010
+    /// ```
+    ///
+    /// Memory elsewhere is unmapped until `map`ped, and member types are found by the offsets
+    /// `INT` and `NODE_POINTER`.
+    pub(crate) struct Program {
+        variables: HashMap<&'static str, (Type, usize)>,
+        memory: HashMap<usize, u8>,
+    }
+
+    pub(crate) fn int() -> Type {
+        Type::base("int", 4, Encoding::Signed)
+    }
+
+    fn node() -> Type {
+        let member = |name: &str, type_offset, offset| Member {
+            name: name.to_string(),
+            type_offset,
+            offset,
+        };
+        Type::new(
+            "struct node".to_string(),
+            16,
+            TypeKind::Struct(vec![member("val", INT, 0), member("next", NODE_POINTER, 8)]),
+        )
+    }
+
+    impl Program {
+        pub(crate) fn new() -> Program {
+            let mut program = Program {
+                variables: HashMap::new(),
+                memory: HashMap::new(),
+            };
+            program.define("a", int(), 0x100, &5i32.to_le_bytes());
+            let unsigned = Type::base("unsigned int", 4, Encoding::Unsigned);
+            program.define("u", unsigned, 0x104, &1u32.to_le_bytes());
+            let double = Type::base("double", 8, Encoding::Float);
+            program.define("d", double, 0x108, &2.5f64.to_le_bytes());
+            let char_type = Type::base("char", 1, Encoding::SignedChar);
+            program.define("c", char_type, 0x110, b"x");
+            let mut arr = Vec::new();
+            for value in &[10i32, 20, 30, 40] {
+                arr.extend_from_slice(&value.to_le_bytes());
+            }
+            let arr_type = Type::new(
+                "int [4]".to_string(),
+                16,
+                TypeKind::Array(Box::new(int()), Some(4)),
+            );
+            program.define("arr", arr_type, 0x1000, &arr);
+            let pointer = Type::pointer_to(Some(int()));
+            program.define("p", pointer, 0x2000, &0x1000usize.to_le_bytes());
+            let mut n = 5i64.to_le_bytes().to_vec();
+            n.extend_from_slice(&0x3010usize.to_le_bytes());
+            program.define("n", node(), 0x3000, &n);
+            let mut m = 6i64.to_le_bytes().to_vec();
+            m.extend_from_slice(&0usize.to_le_bytes());
+            program.define("m", node(), 0x3010, &m);
+            program
+        }
+
+        fn define(&mut self, name: &'static str, entity_type: Type, addr: usize, bytes: &[u8]) {
+            self.variables.insert(name, (entity_type, addr));
+            self.map(addr, bytes);
+        }
+
+        /// Makes `bytes` readable at `addr`.
+        pub(crate) fn map(&mut self, addr: usize, bytes: &[u8]) {
+            for (i, byte) in bytes.iter().enumerate() {
+                self.memory.insert(addr + i, *byte);
+            }
+        }
+
+        fn print(&mut self, text: &str) -> Result<String, String> {
+            let value = evaluate(self, &parse(text)?)?;
+            Ok(format_value(self, &value.entity_type, &value.bytes))
+        }
+
+        fn test(&mut self, text: &str) -> Result<bool, String> {
+            evaluate(self, &parse(text)?)?.is_true()
+        }
+    }
+
+    impl Context for Program {
+        fn lookup_variable(&self, name: &str) -> Result<(Type, usize), String> {
+            self.variables
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("No symbol \"{}\" in current context.", name))
+        }
+
+        fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, String> {
+            (0..len)
+                .map(|i| {
+                    addr.checked_add(i)
+                        .and_then(|addr| self.memory.get(&addr).copied())
+                })
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))
+        }
+
+        fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), String> {
+            self.map(addr, bytes);
+            Ok(())
+        }
+
+        fn get_type(&self, offset: usize) -> Option<Type> {
+            match offset {
+                INT => Some(int()),
+                NODE_POINTER => Some(Type::pointer_to(Some(node()))),
+                _ => None,
+            }
+        }
+
+        fn symbol_at(&self, _addr: usize) -> Option<String> {
+            None
+        }
+
+        fn read_register(&self, name: &str) -> Result<u64, String> {
+            Err(format!("Invalid register `{}'", name))
+        }
+
+        fn write_register(&mut self, name: &str, _value: u64) -> Result<(), String> {
+            Err(format!("Invalid register `{}'", name))
+        }
+    }
+
+    fn assert_prints(program: &mut Program, text: &str, expected: &str) {
+        assert_eq!(program.print(text), Ok(expected.to_string()), "{}", text);
+    }
+
+    #[test]
+    fn compares_variables_and_literals() {
+        let mut program = Program::new();
+        assert_eq!(program.test("a == 5"), Ok(true));
+        assert_eq!(program.test("a != 5"), Ok(false));
+        assert_eq!(program.test("a < 6"), Ok(true));
+        assert_eq!(program.test("a <= 4"), Ok(false));
+        assert_eq!(program.test("6 > a"), Ok(true));
+        assert_eq!(program.test("a >= 0x5"), Ok(true));
+        assert_eq!(program.test("a > -1"), Ok(true));
+        assert_eq!(program.test("u == a"), Ok(false));
+        assert_eq!(program.test("d > 2"), Ok(true));
+        assert_eq!(program.test("d == 2.5"), Ok(true));
+        assert_eq!(program.test("c == 'x'"), Ok(true));
+        assert_eq!(program.test("c != '\\n'"), Ok(true));
+    }
+
+    #[test]
+    fn lone_values_are_true_when_non_zero() {
+        let mut program = Program::new();
+        assert_eq!(program.test("a"), Ok(true));
+        assert_eq!(program.test("0"), Ok(false));
+        assert_eq!(program.test("0.0"), Ok(false));
+        assert_eq!(program.test("-d"), Ok(true));
+        assert_eq!(program.test("p"), Ok(true));
+        assert_eq!(program.test("m.next"), Ok(false));
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "1 + 2 * 3", "7");
+        assert_prints(&mut program, "(1 + 2) * 3", "9");
+        assert_prints(&mut program, "10 - 4 - 3", "3");
+        assert_prints(&mut program, "100 / 10 / 5", "2");
+        assert_prints(&mut program, "1 << 2 + 1", "8");
+        assert_prints(&mut program, "1 | 2 ^ 3 & 1", "3");
+        assert_prints(&mut program, "1 < 2 == 1", "1");
+        assert_prints(&mut program, "(a == 5) == (u == 1)", "1");
+        assert_prints(&mut program, "a - -1", "6");
+        assert_prints(&mut program, "-a * 2", "-10");
+        assert_prints(&mut program, "!a + ~0", "-1");
+        assert_prints(&mut program, "1 + 2.5", "3.5");
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "0 && nosuch", "0");
+        assert_prints(&mut program, "1 || nosuch", "1");
+        assert_prints(&mut program, "a && 2", "1");
+        assert_eq!(
+            program.print("1 && nosuch"),
+            Err("No symbol \"nosuch\" in current context.".to_string())
+        );
+    }
+
+    #[test]
+    fn pointers_members_and_indexing() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "arr", "{10, 20, 30, 40}");
+        assert_prints(&mut program, "arr[2]", "30");
+        assert_prints(&mut program, "p[3]", "40");
+        assert_prints(&mut program, "*p", "10");
+        assert_prints(&mut program, "*(p + 2)", "30");
+        assert_prints(&mut program, "*(2 + p)", "30");
+        assert_prints(&mut program, "p + 1", "0x1004");
+        assert_prints(&mut program, "&arr[3] - 1", "0x1008");
+        assert_prints(&mut program, "&arr[3] - &arr[1]", "2");
+        assert_prints(&mut program, "&a", "0x100");
+        assert_prints(&mut program, "*&a", "5");
+        assert_prints(&mut program, "n.val", "5");
+        assert_prints(&mut program, "n.next->val", "6");
+        assert_prints(&mut program, "(&n)->next", "0x3010");
+        assert_prints(&mut program, "n.next->next", "0x0");
+        assert_prints(&mut program, "m", "{val = 6, next = 0x0}");
+        assert_eq!(
+            program.print("n.nope"),
+            Err("There is no member named nope.".to_string())
+        );
+        assert_eq!(
+            program.print("a.val"),
+            Err("Attempt to extract a component of a value that is not a structure.".to_string())
+        );
+        assert_eq!(
+            program.print("*a"),
+            Err("Attempt to take contents of a non-pointer value.".to_string())
+        );
+        assert_eq!(
+            program.print("&5"),
+            Err("Attempt to take address of value not located in memory.".to_string())
+        );
+        assert_eq!(
+            program.print("m.next->val"),
+            Err("Cannot access memory at address 0x0".to_string())
+        );
+        assert_eq!(
+            program.print("p + p"),
+            Err("Cannot add two pointers.".to_string())
+        );
+    }
+
+    #[test]
+    fn floating_point_literals() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "1e5", "100000");
+        assert_prints(&mut program, "2.5e-3", "0.0025");
+        assert_prints(&mut program, "1E+2 - 1", "99");
+        assert_prints(&mut program, "d * 2e1", "50");
+        assert_prints(&mut program, "0x1e", "30");
+        assert_prints(&mut program, "a-1", "4");
+        assert_eq!(
+            program.print("1e"),
+            Err("Invalid number \"1e\".".to_string())
+        );
+    }
+
+    #[test]
+    fn char_octal_and_hex_literals() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "'a'", "97 'a'");
+        assert_prints(&mut program, "'\\n'", "10 '\\n'");
+        assert_prints(&mut program, "'\\101'", "65 'A'");
+        assert_prints(&mut program, "'\\x41'", "65 'A'");
+        assert_prints(&mut program, "'\\''", "39 '\\''");
+        assert_prints(&mut program, "'a' + 1", "98");
+        assert_prints(&mut program, "010", "8");
+        assert_prints(&mut program, "0", "0");
+        assert_prints(&mut program, "0x1f", "31");
+        assert_prints(&mut program, "0X10UL", "16");
+        assert_prints(&mut program, "0x100000000", "4294967296");
+        assert!(program.print("09").is_err());
+        assert!(program.print("''").is_err());
+        assert!(program.print("'ab'").is_err());
+    }
+
+    #[test]
+    fn signed_and_unsigned_comparison() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "-1 < 1", "1");
+        // -1 converts to a huge unsigned int when compared with one
+        assert_prints(&mut program, "-1 < u", "0");
+        assert_prints(&mut program, "-1 > u", "1");
+        assert_prints(&mut program, "u - 2", "4294967295");
+        assert_prints(&mut program, "-8 >> 1", "-4");
+        assert_prints(&mut program, "-1 == 0xffffffffffffffff", "1");
+    }
+
+    #[test]
+    fn assignment() {
+        let mut program = Program::new();
+        assert_prints(&mut program, "a = u = 7", "7");
+        assert_prints(&mut program, "a", "7");
+        assert_prints(&mut program, "u", "7");
+        assert_prints(&mut program, "arr[1] = 'b'", "98");
+        assert_prints(&mut program, "p[1]", "98");
+        assert_prints(&mut program, "d = 1", "1");
+        assert_eq!(
+            program.print("1 = 2"),
+            Err("Left operand of assignment is not an lvalue.".to_string())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut program = Program::new();
+        assert_eq!(program.print("1 / 0"), Err("Division by zero".to_string()));
+        assert_eq!(
+            program.print("a % (a - 5)"),
+            Err("Division by zero".to_string())
+        );
+        assert_eq!(
+            program.print("nosuch + 1"),
+            Err("No symbol \"nosuch\" in current context.".to_string())
+        );
+        assert_eq!(
+            program.print("1.5 % 2"),
+            Err("Integer only operation.".to_string())
+        );
+        assert_eq!(
+            program.print(""),
+            Err("Argument required (expression to compute).".to_string())
+        );
+        assert_eq!(
+            program.print("a =="),
+            Err("A syntax error in expression.".to_string())
+        );
+        assert_eq!(
+            program.print("(1 + 2"),
+            Err("A syntax error in expression, expected ')'.".to_string())
+        );
+        assert_eq!(
+            program.print("arr[1"),
+            Err("A syntax error in expression, expected ']'.".to_string())
+        );
+        assert_eq!(
+            program.print("n."),
+            Err("A syntax error in expression, expected a member name.".to_string())
+        );
+        assert_eq!(
+            program.print("1 2"),
+            Err("A syntax error in expression.".to_string())
+        );
+        assert_eq!(
+            program.print("09"),
+            Err("Invalid number \"09\".".to_string())
+        );
+        assert_eq!(
+            program.print("c == ''"),
+            Err("Empty character constant.".to_string())
+        );
+        assert_eq!(
+            program.print("a @ 2"),
+            Err("Invalid character '@' in expression.".to_string())
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use crate::value::{format_char, read_signed, read_unsigned};
+
+/// How `x` shows memory, as given by `x/<count><format><size>`. Format and size carry over from
+/// one `x` to the next, like in gdb.
+#[derive(Clone, Copy, Debug)]
+pub struct ExamineFormat {
+    pub count: usize,
+    /// One of x (hex), d (decimal), u (unsigned), o (octal), t (binary), c (char), a (address),
+    /// s (string) or i (instruction).
+    pub format: char,
+    /// Unit size in bytes: 1 (b), 2 (h), 4 (w) or 8 (g).
+    pub size: usize,
+}
+
+impl Default for ExamineFormat {
+    fn default() -> Self {
+        ExamineFormat {
+            count: 1,
+            format: 'x',
+            size: 4,
+        }
+    }
+}
+
+impl ExamineFormat {
+    /// Parses the part after `x/`, filling in what is left out from the previous format.
+    pub fn parse(spec: &str, last: ExamineFormat) -> Result<ExamineFormat, String> {
+        let digits: String = spec.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let count = if digits.is_empty() {
+            1
+        } else {
+            digits
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid number \"{}\".", digits))?
+        };
+        let mut format = None;
+        let mut size = None;
+        for letter in spec[digits.len()..].chars() {
+            match letter {
+                'x' | 'd' | 'u' | 'o' | 't' | 'c' | 'a' | 's' | 'i' => format = Some(letter),
+                'b' => size = Some(1),
+                'h' => size = Some(2),
+                'w' => size = Some(4),
+                'g' => size = Some(8),
+                _ => return Err(format!("Undefined output format \"{}\".", letter)),
+            }
+        }
+        let format = format.unwrap_or(last.format);
+        let size = match (format, size) {
+            ('a', _) => 8,
+            ('c', None) | ('s', None) => 1,
+            (_, Some(size)) => size,
+            // chars and strings shouldn't make later hex dumps bytewise
+            (_, None) if last.format == 'c' || last.format == 's' => 4,
+            (_, None) => last.size,
+        };
+        Ok(ExamineFormat {
+            count,
+            format,
+            size,
+        })
+    }
+
+    /// How many units gdb fits on a line.
+    pub fn per_line(&self) -> usize {
+        match (self.format, self.size) {
+            ('c', _) | (_, 1) | (_, 2) => 8,
+            (_, 4) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Formats one unit of memory. Addresses (`a`), strings and instructions need more than the
+    /// unit's bytes, so the debugger handles those itself.
+    pub fn format_unit(&self, bytes: &[u8]) -> String {
+        let unsigned = read_unsigned(bytes, self.size);
+        match self.format {
+            'd' => read_signed(bytes, self.size).to_string(),
+            'u' => unsigned.to_string(),
+            'o' if unsigned == 0 => "0".to_string(),
+            'o' => format!("0{:o}", unsigned),
+            't' => format!("{:0width$b}", unsigned, width = 8 * self.size),
+            'c' => format!("{} {}", bytes[0] as i8, format_char(bytes[0])),
+            _ => format!("{:#0width$x}", unsigned, width = 2 + 2 * self.size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(spec: &str, last: ExamineFormat) -> ExamineFormat {
+        ExamineFormat::parse(spec, last).unwrap()
+    }
+
+    #[test]
+    fn count_format_and_size() {
+        let format = parse("4xg", ExamineFormat::default());
+        assert_eq!((format.count, format.format, format.size), (4, 'x', 8));
+        assert_eq!(format.per_line(), 2);
+        assert_eq!(
+            format.format_unit(&0x1122334455667788u64.to_le_bytes()),
+            "0x1122334455667788"
+        );
+        assert_eq!(
+            format.format_unit(&[1, 0, 0, 0, 0, 0, 0, 0]),
+            "0x0000000000000001"
+        );
+    }
+
+    #[test]
+    fn format_and_size_carry_over() {
+        let first = parse("2dh", ExamineFormat::default());
+        let next = parse("", first);
+        assert_eq!((next.count, next.format, next.size), (1, 'd', 2));
+        let next = parse("3", first);
+        assert_eq!((next.count, next.format, next.size), (3, 'd', 2));
+        let next = parse("u", first);
+        assert_eq!((next.format, next.size), ('u', 2));
+        let next = parse("w", first);
+        assert_eq!((next.format, next.size), ('d', 4));
+        // addresses are always words
+        let next = parse("a", first);
+        assert_eq!((next.format, next.size), ('a', 8));
+    }
+
+    #[test]
+    fn strings_and_chars_do_not_leave_bytes_behind() {
+        let string = parse("s", ExamineFormat::default());
+        assert_eq!((string.format, string.size), ('s', 1));
+        let hex = parse("x", string);
+        assert_eq!((hex.format, hex.size), ('x', 4));
+        let chars = parse("3c", ExamineFormat::default());
+        assert_eq!((chars.count, chars.format, chars.size), (3, 'c', 1));
+        assert_eq!(chars.per_line(), 8);
+        assert_eq!(chars.format_unit(b"A"), "65 'A'");
+        assert_eq!(chars.format_unit(&[0xff]), "-1 '\\377'");
+        // a bare `x` goes on showing chars, but a new format gets words back
+        assert_eq!(parse("", chars).size, 1);
+        assert_eq!(parse("d", chars).size, 4);
+        // unless a size is given
+        assert_eq!(parse("xb", chars).size, 1);
+        assert_eq!(parse("2cw", ExamineFormat::default()).size, 4);
+    }
+
+    #[test]
+    fn formats_units() {
+        let unit =
+            |spec: &str, bytes: &[u8]| parse(spec, ExamineFormat::default()).format_unit(bytes);
+        assert_eq!(unit("ow", &[0, 0, 0, 0]), "0");
+        assert_eq!(unit("ow", &[8, 0, 0, 0]), "010");
+        assert_eq!(unit("dw", &(-2i32).to_le_bytes()), "-2");
+        assert_eq!(unit("uw", &(-2i32).to_le_bytes()), "4294967294");
+        assert_eq!(unit("tb", &[5]), "00000101");
+        assert_eq!(unit("xh", &[0xcd, 0xab]), "0xabcd");
+    }
+
+    #[test]
+    fn rejects_bad_letters() {
+        let last = ExamineFormat::default();
+        assert_eq!(
+            ExamineFormat::parse("4q", last).unwrap_err(),
+            "Undefined output format \"q\"."
+        );
+        assert_eq!(
+            ExamineFormat::parse("99999999999999999999x", last).unwrap_err(),
+            "Invalid number \"99999999999999999999\"."
+        );
+    }
+}
@@ -10,13 +10,15 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    Encoding, File, Function, Line, Location, Member, Type, TypeKind, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str) -> Result<Vec<File>, Error> {
+pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -49,6 +51,121 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str
     while let Some(header) = iter.next()? {
         let unit = dwarf.unit(header)?;
 
+        // Collect the unit's types first, since variables may refer to types declared after them
+        let mut raw_types: HashMap<usize, RawType> = HashMap::new();
+        // offsets of the struct/union/enum/array DIEs enclosing the current entry
+        let mut parents: Vec<(isize, usize)> = Vec::new();
+        let mut depth = 0;
+        let mut entries = unit.entries();
+        while let Some((delta_depth, entry)) = entries.next_dfs()? {
+            depth += delta_depth;
+            while parents
+                .last()
+                .map_or(false, |(parent_depth, _)| *parent_depth >= depth)
+            {
+                parents.pop();
+            }
+            let parent = parents.last().map(|(_, offset)| *offset);
+            let offset = section_offset(entry.offset(), &unit);
+            let name = match entry.attr(gimli::DW_AT_name) {
+                Ok(Some(attr)) => match get_attr_value(&attr, &unit, &dwarf) {
+                    Ok(DebugValue::Str(name)) => Some(name),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let target = match entry.attr(gimli::DW_AT_type) {
+                Ok(Some(attr)) => match get_attr_value(&attr, &unit, &dwarf) {
+                    Ok(DebugValue::Size(offset)) => Some(offset),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let udata = |name| match entry.attr(name) {
+                Ok(Some(attr)) => attr.udata_value(),
+                _ => None,
+            };
+            match entry.tag() {
+                gimli::DW_TAG_base_type
+                | gimli::DW_TAG_pointer_type
+                | gimli::DW_TAG_typedef
+                | gimli::DW_TAG_const_type
+                | gimli::DW_TAG_volatile_type
+                | gimli::DW_TAG_restrict_type
+                | gimli::DW_TAG_structure_type
+                | gimli::DW_TAG_union_type
+                | gimli::DW_TAG_enumeration_type
+                | gimli::DW_TAG_array_type
+                | gimli::DW_TAG_subroutine_type => {
+                    let encoding = match entry.attr_value(gimli::DW_AT_encoding) {
+                        Ok(Some(gimli::AttributeValue::Encoding(encoding))) => Some(encoding),
+                        _ => None,
+                    };
+                    raw_types.insert(
+                        offset,
+                        RawType {
+                            tag: entry.tag(),
+                            name,
+                            byte_size: udata(gimli::DW_AT_byte_size).map(|size| size as usize),
+                            target,
+                            encoding,
+                            members: Vec::new(),
+                            enumerators: Vec::new(),
+                            bounds: Vec::new(),
+                        },
+                    );
+                    if entry.has_children() {
+                        parents.push((depth, offset));
+                    }
+                }
+                gimli::DW_TAG_member => {
+                    if let (Some(parent), Some(type_offset)) =
+                        (parent.and_then(|parent| raw_types.get_mut(&parent)), target)
+                    {
+                        parent.members.push(Member {
+                            name: name.unwrap_or_default(),
+                            type_offset,
+                            // union members have no location; they all start at 0
+                            offset: udata(gimli::DW_AT_data_member_location).unwrap_or(0) as usize,
+                        });
+                    }
+                }
+                gimli::DW_TAG_enumerator => {
+                    let value = match entry.attr(gimli::DW_AT_const_value) {
+                        Ok(Some(attr)) => attr.sdata_value(),
+                        _ => None,
+                    };
+                    if let (Some(parent), Some(name), Some(value)) = (
+                        parent.and_then(|parent| raw_types.get_mut(&parent)),
+                        name,
+                        value,
+                    ) {
+                        parent.enumerators.push((name, value));
+                    }
+                }
+                gimli::DW_TAG_subrange_type => {
+                    // DW_AT_count, or DW_AT_upper_bound with C's lower bound of 0; neither for
+                    // flexible array members
+                    let count = udata(gimli::DW_AT_count)
+                        .or_else(|| match entry.attr(gimli::DW_AT_upper_bound) {
+                            Ok(Some(attr)) => attr.sdata_value().map(|bound| (bound + 1) as u64),
+                            _ => None,
+                        })
+                        .map(|count| count as usize);
+                    if let Some(parent) = parent.and_then(|parent| raw_types.get_mut(&parent)) {
+                        parent.bounds.push(count);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let offsets: Vec<usize> = raw_types.keys().cloned().collect();
+        for offset in offsets {
+            if let Some(dtype) = build_type(offset, &raw_types, &mut offset_to_type, 0) {
+                offset_to_type.insert(offset, dtype);
+            }
+        }
+
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
         let mut entries = unit.entries();
@@ -71,38 +188,14 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str
                     };
                     compilation_units.push(File {
                         name,
+                        comp_dir: unit
+                            .comp_dir
+                            .map_or(String::new(), |dir| dir.to_string_lossy().into_owned()),
                         global_variables: Vec::new(),
                         functions: Vec::new(),
                         lines: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
                     let mut attrs = entry.attrs();
@@ -131,6 +224,11 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str
                                     func.line_number = line_number.try_into().unwrap();
                                 }
                             }
+                            gimli::DW_AT_type => {
+                                if let Ok(DebugValue::Size(offset)) = val {
+                                    func.return_type = offset_to_type.get(&offset).cloned();
+                                }
+                            }
                             _ => {}
                         }
                     }
@@ -177,6 +275,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str
                             entity_type: entity_type.unwrap(),
                             location: location.unwrap(),
                             line_number: line_number.try_into().unwrap(),
+                            is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
                         };
                         if depth == 1 {
                             compilation_units
@@ -223,9 +322,11 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str
                     }
 
                     // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                    // sources in the compilation directory come back prefixed with it
+                    let file = compilation_units.iter_mut().find(|f| {
+                        f.name == path.as_os_str().to_str().unwrap()
+                            || path::Path::new(&f.comp_dir).join(&f.name) == path
+                    });
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -236,13 +337,140 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian, path: &str
                             file: file.name.clone(),
                             number: line.try_into().unwrap(),
                             address: row.address().try_into().unwrap(),
+                            is_stmt: row.is_stmt(),
                         });
                     }
                 }
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, offset_to_type))
+}
+
+fn section_offset<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+/// A type DIE as read from the unit, before the types it refers to are resolved.
+struct RawType {
+    tag: gimli::DwTag,
+    name: Option<String>,
+    byte_size: Option<usize>,
+    target: Option<usize>,
+    encoding: Option<gimli::DwAte>,
+    members: Vec<Member>,
+    enumerators: Vec<(String, i64)>,
+    /// Element counts of an array's dimensions, outermost first.
+    bounds: Vec<Option<usize>>,
+}
+
+/// Turns the raw type at `offset` into a `Type`, resolving the types it refers to on the way.
+/// Struct members stay as offsets, so self-referential structs don't recurse.
+fn build_type(
+    offset: usize,
+    raw_types: &HashMap<usize, RawType>,
+    types: &mut HashMap<usize, Type>,
+    depth: usize,
+) -> Option<Type> {
+    if let Some(dtype) = types.get(&offset) {
+        return Some(dtype.clone());
+    }
+    // only malformed DWARF nests types this deep
+    if depth > 64 {
+        return None;
+    }
+    let raw = raw_types.get(&offset)?;
+    // a target we can't resolve is treated like void
+    let target = raw
+        .target
+        .and_then(|target| build_type(target, raw_types, types, depth + 1));
+    let name = raw.name.clone();
+    let dtype = match raw.tag {
+        gimli::DW_TAG_base_type => {
+            let encoding = match raw.encoding {
+                Some(gimli::DW_ATE_float) => Encoding::Float,
+                Some(gimli::DW_ATE_boolean) => Encoding::Bool,
+                Some(gimli::DW_ATE_signed_char) => Encoding::SignedChar,
+                Some(gimli::DW_ATE_unsigned_char) => Encoding::UnsignedChar,
+                Some(gimli::DW_ATE_unsigned) => Encoding::Unsigned,
+                _ => Encoding::Signed,
+            };
+            Type::new(
+                name.unwrap_or_else(|| "<unknown>".to_string()),
+                raw.byte_size.unwrap_or(0),
+                TypeKind::Base(encoding),
+            )
+        }
+        gimli::DW_TAG_pointer_type => Type::pointer_to(target),
+        gimli::DW_TAG_typedef => {
+            let size = target.as_ref().map_or(0, |target| target.size);
+            Type::new(
+                name.unwrap_or_else(|| "<unknown>".to_string()),
+                size,
+                TypeKind::Alias(target.map(Box::new)),
+            )
+        }
+        gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type | gimli::DW_TAG_restrict_type => {
+            let qualifier = match raw.tag {
+                gimli::DW_TAG_const_type => "const",
+                gimli::DW_TAG_volatile_type => "volatile",
+                _ => "restrict",
+            };
+            let (name, size) = match &target {
+                // qualifiers of pointers go after the star
+                Some(target) if target.name.ends_with('*') => {
+                    (format!("{} {}", target.name, qualifier), target.size)
+                }
+                Some(target) => (format!("{} {}", qualifier, target.name), target.size),
+                None => (format!("{} void", qualifier), 0),
+            };
+            Type::new(name, size, TypeKind::Alias(target.map(Box::new)))
+        }
+        gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type => {
+            let (keyword, kind) = if raw.tag == gimli::DW_TAG_structure_type {
+                ("struct", TypeKind::Struct(raw.members.clone()))
+            } else {
+                ("union", TypeKind::Union(raw.members.clone()))
+            };
+            Type::new(
+                format!(
+                    "{} {}",
+                    keyword,
+                    name.unwrap_or_else(|| "{...}".to_string())
+                ),
+                raw.byte_size.unwrap_or(0),
+                kind,
+            )
+        }
+        gimli::DW_TAG_enumeration_type => Type::new(
+            format!("enum {}", name.unwrap_or_else(|| "{...}".to_string())),
+            raw.byte_size.unwrap_or(4),
+            TypeKind::Enum(raw.enumerators.clone()),
+        ),
+        gimli::DW_TAG_array_type => {
+            // int a[2][3] is an array of 2 arrays of 3 ints, so wrap from the innermost dimension
+            let mut dtype = target?;
+            let bounds = if raw.bounds.is_empty() {
+                vec![None]
+            } else {
+                raw.bounds.clone()
+            };
+            for count in bounds.into_iter().rev() {
+                dtype = Type::array_of(dtype, count);
+            }
+            dtype
+        }
+        gimli::DW_TAG_subroutine_type => {
+            let return_type = target.map_or("void".to_string(), |target| target.name);
+            Type::new(format!("{} ()", return_type), 1, TypeKind::Function)
+        }
+        _ => return None,
+    };
+    types.insert(offset, dtype.clone());
+    Some(dtype)
 }
 
 #[derive(Debug, Clone)]
@@ -1,15 +1,17 @@
+use crate::target::{auxv_entry, MemoryMap, Target};
+use crate::watchpoint::{DR_CONTROL, DR_STATUS};
 use nix::sys::ptrace;
 use nix::sys::signal;
+use nix::sys::uio::{process_vm_readv, IoVec, RemoteIoVec};
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
-use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
 use std::mem::size_of;
+use std::os::unix::fs::FileExt;
 use std::process::Child;
 use std::os::unix::process::CommandExt;
 use std::process::Command;
 
-use crate::debugger::Breakpoint;
-
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
     /// current instruction pointer that it is stopped at.
@@ -21,6 +23,23 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates the current thread forked. Contains the new process, stopped and traced, and
+    /// whether it was a vfork, which leaves it sharing our memory until it execs or exits.
+    Forked(Pid, bool),
+
+    /// Indicates a vfork child stopped sharing the inferior's memory.
+    VforkDone,
+
+    /// Indicates the inferior executed a new program. Only the main thread is left.
+    Execed,
+
+    /// Indicates the current thread stopped on its way into a system call, while syscalls were
+    /// being traced. Contains the current instruction pointer.
+    SyscallEntry(usize),
+
+    /// Indicates the current thread stopped on its way back from a system call.
+    SyscallExit(usize),
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
@@ -32,18 +51,148 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+/// Besides threads, we want to hear about forks and execs. TRACESYSGOOD tells syscall stops
+/// apart from real SIGTRAPs.
+fn trace_options() -> ptrace::Options {
+    ptrace::Options::PTRACE_O_TRACESYSGOOD
+        | ptrace::Options::PTRACE_O_TRACECLONE
+        | ptrace::Options::PTRACE_O_TRACEFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORK
+        | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+        | ptrace::Options::PTRACE_O_TRACEEXEC
+}
+
+/// Reads the x87/SSE registers of `tid` with PTRACE_GETFPREGS, which nix doesn't wrap.
+pub fn get_fp_registers(tid: Pid) -> Result<libc::user_fpregs_struct, nix::Error> {
+    let mut fp_registers: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETFPREGS,
+            tid.as_raw(),
+            std::ptr::null_mut::<libc::c_void>(),
+            &mut fp_registers as *mut libc::user_fpregs_struct as *mut libc::c_void,
+        )
+    };
+    if ret < 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(fp_registers)
+}
+
+/// The Status for `tid`'s stop on its way into the call, with `entering`, or back out of it.
+fn syscall_status(tid: Pid, entering: bool) -> Result<Status, nix::Error> {
+    let rip = ptrace::getregs(tid)?.rip as usize;
+    if entering {
+        Ok(Status::SyscallEntry(rip))
+    } else {
+        Ok(Status::SyscallExit(rip))
+    }
+}
+
+/// One thread of the inferior. Threads are numbered from 1 in the order we first see them, like
+/// gdb does, and the number stays put while the thread lives.
+pub struct Thread {
+    pub num: usize,
+    pub tid: Pid,
+    /// Whether we resumed it and haven't seen it stop since.
+    running: bool,
+    /// Whether a SIGSTOP is still on its way: the one every new thread starts with, or one we
+    /// sent that lost the race against another event.
+    stop_pending: bool,
+    /// An event it had while we were stopping everything for another thread's event, reported
+    /// on the next continue instead of resuming.
+    pending: Option<Status>,
+    /// A signal to deliver when it next resumes.
+    signal: Option<signal::Signal>,
+    /// Whether its last syscall stop was on the way into the call, so the next one is on the
+    /// way out. Syscall stops come in such pairs only while it is resumed with PTRACE_SYSCALL.
+    in_syscall: bool,
+}
+
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    /// The process we spawned, or None if we attached to it or it forked off one we debug.
+    child: Option<Child>,
+    /// Whether we attached to a process started elsewhere.
+    attached: bool,
+    /// Every live thread, the main one (tid == pid) included.
+    threads: Vec<Thread>,
+    next_thread_num: usize,
+    /// The thread that registers, stepping and memory accesses go to.
+    current: Pid,
+    /// Whether all threads were continued, as opposed to the current one being single-stepped.
+    running: bool,
+    /// A thread whose pending event `cont` chose to report rather than resuming.
+    reporting: Option<Pid>,
+    /// Threads and forked processes whose first stop arrived before the event announcing them.
+    unclaimed: Vec<Pid>,
+    /// The values last written to DR0-DR7, so threads created later can be given the same
+    /// watchpoints; the kernel doesn't copy them on clone.
+    debug_registers: [u64; 8],
+    /// The system calls continued threads stop at (PTRACE_SYSCALL): empty for any, or None to
+    /// not trace them. Stops at the others are resumed from right away.
+    syscalls: Option<Vec<u64>>,
+    /// /proc/<pid>/mem, kept open for writes. None if it couldn't be opened, in which case
+    /// writes go through ptrace.
+    mem: Option<File>,
+}
+
+/// Opens /proc/<pid>/mem for writing. It belongs to the process's current address space, so it
+/// has to be opened again after an exec.
+fn open_mem(pid: Pid) -> Option<File> {
+    OpenOptions::new()
+        .write(true)
+        .open(format!("/proc/{}/mem", pid))
+        .ok()
 }
 
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+/// Byte offset of `u_debugreg[index]` within `struct user`, as PEEKUSER/POKEUSER expect.
+fn debug_register_offset(index: usize) -> usize {
+    let user: libc::user = unsafe { std::mem::zeroed() };
+    let base = &user as *const libc::user as usize;
+    &user.u_debugreg[index] as *const libc::c_ulonglong as usize - base
+}
+
+/// Sends `sig` to one thread of the process, which plain kill() can't do.
+fn tgkill(pid: Pid, tid: Pid, sig: signal::Signal) -> Result<(), nix::Error> {
+    let ret = unsafe { libc::syscall(libc::SYS_tgkill, pid.as_raw(), tid.as_raw(), sig as i32) };
+    if ret < 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(())
+}
+
+/// Writes debug register `index` of thread `tid` with PTRACE_POKEUSER. The kernel validates DR7,
+/// so a bad encoding comes back as EINVAL.
+fn poke_debug_register(tid: Pid, index: usize, value: u64) -> Result<(), nix::Error> {
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_POKEUSER,
+            tid.as_raw(),
+            debug_register_offset(index) as *mut libc::c_void,
+            value as *mut libc::c_void,
+        )
+    };
+    if ret < 0 {
+        return Err(nix::Error::last());
+    }
+    Ok(())
+}
+
+/// Returns the path of the executable running as `pid`, read from /proc/<pid>/exe.
+pub fn exe_path(pid: Pid) -> Result<String, std::io::Error> {
+    let path = std::fs::read_link(format!("/proc/{}/exe", pid))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
     /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, break_pointers: &mut HashMap<usize, Breakpoint>) -> Option<Inferior> {
+    pub fn new(target: &str, args: &Vec<String>) -> Option<Inferior> {
         let mut command = Command::new(target);
         unsafe {
             command.pre_exec::<_>(child_traceme);
@@ -56,21 +205,18 @@ impl Inferior {
 
         match child {
             Some(c) => {
-                let mut infer = Inferior { child: c };
-                let wait = Inferior::wait(&infer, None).ok();
+                let pid = Pid::from_raw(c.id() as i32);
+                let mut infer = Inferior::with_main_thread(pid, Some(c));
+                infer.threads[0].running = true;
+                let wait = infer.wait().ok();
                 if wait.is_none() {
                     return None;
                 }
 
-                for break_pointer in break_pointers.values_mut() {
-                    match infer.write_byte(break_pointer.addr, 0xcc) {
-                        Ok(orig_byte) => break_pointer.orig_byte = orig_byte,
-                        Err(e) => println!("error happen: {}", e),
-                    }
-                }
-
                 if let Status::Stopped(sig, _) = wait.unwrap() {
-                    if sig == signal::Signal::SIGTRAP {
+                    if sig == signal::Signal::SIGTRAP
+                        && ptrace::setoptions(pid, trace_options()).is_ok()
+                    {
                         return Some(infer);
                     }
                 }
@@ -80,45 +226,691 @@ impl Inferior {
         }
     }
 
+    fn with_main_thread(pid: Pid, child: Option<Child>) -> Inferior {
+        Inferior {
+            pid,
+            child,
+            attached: false,
+            threads: vec![Thread {
+                num: 1,
+                tid: pid,
+                running: false,
+                stop_pending: false,
+                pending: None,
+                signal: None,
+                in_syscall: false,
+            }],
+            next_thread_num: 2,
+            current: pid,
+            running: false,
+            reporting: None,
+            unclaimed: Vec::new(),
+            debug_registers: [0; 8],
+            syscalls: None,
+            mem: open_mem(pid),
+        }
+    }
+
+    /// Wraps a process the inferior forked. It is already traced with our options and stopped,
+    /// and has a single thread.
+    pub fn from_fork(pid: Pid) -> Inferior {
+        Inferior::with_main_thread(pid, None)
+    }
+
+    /// Attaches to an already running process with PTRACE_ATTACH and waits for it to stop. Every
+    /// thread listed in /proc/<pid>/task is attached to as well.
+    pub fn attach(pid: Pid) -> Result<Inferior, nix::Error> {
+        Inferior::attach_thread(pid)?;
+        let mut infer = Inferior::with_main_thread(pid, None);
+        infer.attached = true;
+        // threads can keep spawning while we attach, so go over the list until it holds still
+        loop {
+            let tids = match std::fs::read_dir(format!("/proc/{}/task", pid)) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                    .map(Pid::from_raw)
+                    .filter(|tid| infer.thread(*tid).is_none())
+                    .collect::<Vec<Pid>>(),
+                Err(_) => Vec::new(),
+            };
+            if tids.is_empty() {
+                break;
+            }
+            for tid in tids {
+                // a thread that exited since the listing is simply not there to attach to
+                if Inferior::attach_thread(tid).is_ok() {
+                    infer.add_thread(tid, false);
+                }
+            }
+        }
+        Ok(infer)
+    }
+
+    /// Attaches to one thread and waits for its attach stop. That stop is a SIGSTOP; anything
+    /// else arriving first is left pending for later.
+    fn attach_thread(tid: Pid) -> Result<(), nix::Error> {
+        ptrace::attach(tid)?;
+        waitpid(tid, Some(WaitPidFlag::__WALL))?;
+        ptrace::setoptions(tid, trace_options())
+    }
+
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Returns the id of the current thread, which ptrace requests about registers or stepping
+    /// must name.
+    pub fn tid(&self) -> Pid {
+        self.current
+    }
+
+    /// Returns the live threads in the order they were numbered.
+    pub fn threads(&self) -> &[Thread] {
+        &self.threads
+    }
+
+    /// Returns the current thread, unless it has just exited.
+    pub fn current_thread(&self) -> Option<&Thread> {
+        self.thread(self.current)
+    }
+
+    fn thread(&self, tid: Pid) -> Option<&Thread> {
+        self.threads.iter().find(|thread| thread.tid == tid)
+    }
+
+    fn thread_mut(&mut self, tid: Pid) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|thread| thread.tid == tid)
+    }
+
+    /// Makes thread `num` the current one. Returns its tid, or None if there is no such thread.
+    pub fn select_thread(&mut self, num: usize) -> Option<Pid> {
+        let tid = self.threads.iter().find(|thread| thread.num == num)?.tid;
+        self.current = tid;
+        Some(tid)
+    }
+
+    /// Starts tracking a new thread. `starting` means it hasn't reported the SIGSTOP every new
+    /// thread begins with yet.
+    fn add_thread(&mut self, tid: Pid, starting: bool) {
+        if self.thread(tid).is_some() {
+            return;
+        }
+        println!("[New Thread {} (LWP {})]", self.next_thread_num, tid);
+        self.threads.push(Thread {
+            num: self.next_thread_num,
+            tid,
+            running: starting,
+            stop_pending: starting,
+            pending: None,
+            signal: None,
+            in_syscall: false,
+        });
+        self.next_thread_num += 1;
+    }
+
+    fn remove_thread(&mut self, tid: Pid) {
+        if let Some(index) = self.threads.iter().position(|thread| thread.tid == tid) {
+            let thread = self.threads.remove(index);
+            if tid != self.pid {
+                println!("[Thread {} (LWP {}) exited]", thread.num, tid);
+            }
+        }
+    }
+
+    /// Whether we attached to this process rather than spawning it.
+    pub fn is_attached(&self) -> bool {
+        self.attached
     }
 
     pub fn kill(&mut self) -> () {
         let pid = self.pid().to_string();
-        let ret = self.child.kill();
+        let ret = match self.child.as_mut() {
+            Some(child) => child.kill().map_err(|e| format!("{:?}", e)),
+            None => signal::kill(self.pid, signal::Signal::SIGKILL).map_err(|e| format!("{:?}", e)),
+        };
         match ret {
-            Ok(_) => println!("Killing running inferior (pid {})", pid),
-            Err(e) => println!("Kill fail: {:?}", e),
+            Ok(_) => {
+                // reap it so it doesn't linger as a zombie; the main thread's exit is only
+                // reported once the other threads have been reaped
+                for thread in self.threads.iter().filter(|thread| thread.tid != self.pid) {
+                    let _ = waitpid(thread.tid, Some(WaitPidFlag::__WALL));
+                }
+                let _ = waitpid(self.pid, Some(WaitPidFlag::__WALL));
+                println!("Killing running inferior (pid {})", pid)
+            }
+            Err(e) => println!("Kill fail: {}", e),
+        }
+    }
+
+    /// Stops tracing the inferior and lets it run freely. Breakpoints must already be removed.
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        // a SIGSTOP still on its way would stop the whole process once we are gone
+        for thread in self.threads.iter().filter(|thread| thread.stop_pending) {
+            if !thread.running {
+                ptrace::cont(thread.tid, None)?;
+            }
+            loop {
+                match waitpid(thread.tid, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, signal::Signal::SIGSTOP) => break,
+                    WaitStatus::Exited(..) | WaitStatus::Signaled(..) => break,
+                    _ => ptrace::cont(thread.tid, None)?,
+                }
+            }
+        }
+        for thread in self.threads.iter().filter(|thread| thread.tid != self.pid) {
+            ptrace::detach(thread.tid, None)?;
+        }
+        ptrace::detach(self.pid, None)
+    }
+
+    /// Resumes every thread. If one of them had an event of its own while we were stopping the
+    /// others, nothing is resumed and the next `wait` reports that event instead.
+    pub fn cont(&mut self) -> Result<(), nix::Error> {
+        if let Some(thread) = self.threads.iter().find(|thread| thread.pending.is_some()) {
+            self.reporting = Some(thread.tid);
+            return Ok(());
+        }
+        self.running = true;
+        let stopped: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|thread| !thread.running)
+            .map(|thread| thread.tid)
+            .collect();
+        for tid in stopped {
+            self.resume_thread(tid)?;
         }
+        Ok(())
     }
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+
+    /// Single-steps the current thread, leaving the others stopped.
+    pub fn step(&mut self) -> Result<(), nix::Error> {
+        self.running = false;
+        self.resume_thread(self.current)
+    }
+
+    /// Sets the signal the current thread gets when it next resumes, replacing any other. None
+    /// resumes it without one.
+    pub fn set_signal(&mut self, signal: Option<signal::Signal>) {
+        if let Some(thread) = self.thread_mut(self.current) {
+            thread.signal = signal;
+        }
+    }
+
+    /// Sets which system calls continuing stops at the entry and exit of, which is what syscall
+    /// catchpoints need: any with an empty list, none with None. Single-stepping never does.
+    pub fn trace_syscalls(&mut self, syscalls: Option<Vec<u64>>) {
+        self.syscalls = syscalls;
+    }
+
+    /// Whether `tid`'s syscall stop is at one of the calls being traced.
+    fn traces_syscall(&self, tid: Pid) -> Result<bool, nix::Error> {
+        let numbers = match &self.syscalls {
+            Some(numbers) => numbers,
+            None => return Ok(false),
+        };
+        // rax holds the return value on the way out, but the kernel keeps the number in orig_rax
+        let number = ptrace::getregs(tid)?.orig_rax;
+        Ok(numbers.is_empty() || numbers.contains(&number))
+    }
+
+    /// Lets one stopped thread go again, stepping it if it is the thread being single-stepped.
+    fn resume_thread(&mut self, tid: Pid) -> Result<(), nix::Error> {
+        let signal = self.thread_mut(tid).and_then(|thread| thread.signal.take());
+        let stepping = !self.running && tid == self.current;
+        let syscalls = !stepping && self.syscalls.is_some();
+        if stepping {
+            ptrace::step(tid, signal)?;
+        } else if syscalls {
+            ptrace::syscall(tid, signal)?;
+        } else {
+            ptrace::cont(tid, signal)?;
+        }
+        if let Some(thread) = self.thread_mut(tid) {
+            thread.running = true;
+            // resumed any other way, it leaves a call without a stop on the way out
+            if !syscalls {
+                thread.in_syscall = false;
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+        }
+        Ok(())
     }
 
-    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+    /// Waits for the next event of any thread and returns a Status to indicate the state of the
+    /// process. Thread creation and exit are dealt with along the way; once a thread stops, the
+    /// others are stopped too and it becomes the current thread.
+    pub fn wait(&mut self) -> Result<Status, nix::Error> {
+        if let Some(tid) = self.reporting.take() {
+            let status = self
+                .thread_mut(tid)
+                .and_then(|thread| thread.pending.take());
+            if let Some(status) = status {
+                self.current = tid;
+                return Ok(status);
+            }
+        }
+        loop {
+            match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::__WALL))? {
+                // the main thread's exit is held back until every other thread is gone
+                WaitStatus::Exited(tid, exit_code) if tid == self.pid => {
+                    self.threads.clear();
+                    return Ok(Status::Exited(exit_code));
+                }
+                WaitStatus::Signaled(tid, signal, _core_dumped) if tid == self.pid => {
+                    self.threads.clear();
+                    return Ok(Status::Signaled(signal));
+                }
+                WaitStatus::Exited(tid, _) | WaitStatus::Signaled(tid, _, _) => {
+                    self.remove_thread(tid)
+                }
+                WaitStatus::PtraceEvent(tid, _, event)
+                    if event == ptrace::Event::PTRACE_EVENT_EXEC as i32 =>
+                {
+                    // the other threads are gone, and the one that exec'ed now goes by the pid
+                    self.threads.retain(|thread| thread.tid == tid);
+                    for thread in self.threads.iter_mut() {
+                        thread.running = false;
+                        thread.pending = None;
+                        // still inside execve, whose way out is next when tracing syscalls
+                        thread.in_syscall = true;
+                    }
+                    self.current = tid;
+                    self.running = false;
+                    self.unclaimed.clear();
+                    // the kernel clears the debug registers of the new program
+                    self.debug_registers = [0; 8];
+                    self.mem = open_mem(self.pid);
+                    return Ok(Status::Execed);
+                }
+                WaitStatus::PtraceEvent(tid, _, event) => {
+                    if let Some(thread) = self.thread_mut(tid) {
+                        thread.running = false;
+                    }
+                    match self.event_status(tid, event)? {
+                        Some(status) => {
+                            self.current = tid;
+                            self.running = false;
+                            self.stop_others()?;
+                            return Ok(status);
+                        }
+                        None => self.resume_thread(tid)?,
+                    }
+                }
+                WaitStatus::Stopped(tid, signal) => {
+                    let thread = match self.thread_mut(tid) {
+                        Some(thread) => thread,
+                        None => {
+                            // a new thread or process whose first stop beat the event
+                            // announcing it
+                            self.unclaimed.push(tid);
+                            continue;
+                        }
+                    };
+                    thread.running = false;
+                    if signal == signal::Signal::SIGSTOP && thread.stop_pending {
+                        thread.stop_pending = false;
+                        self.set_debug_registers(tid)?;
+                        if self.running {
+                            self.resume_thread(tid)?;
+                        }
+                        continue;
+                    }
+                    self.current = tid;
+                    self.running = false;
+                    self.stop_others()?;
+                    let regs = ptrace::getregs(tid)?;
+                    return Ok(Status::Stopped(signal, regs.rip as usize));
+                }
+                WaitStatus::PtraceSyscall(tid) => {
+                    let entering = match self.thread_mut(tid) {
+                        Some(thread) => {
+                            thread.running = false;
+                            thread.in_syscall = !thread.in_syscall;
+                            thread.in_syscall
+                        }
+                        None => continue,
+                    };
+                    // calls no catchpoint is after don't hold up the other threads
+                    if !self.traces_syscall(tid)? {
+                        self.resume_thread(tid)?;
+                        continue;
+                    }
+                    self.current = tid;
+                    self.running = false;
+                    self.stop_others()?;
+                    return syscall_status(tid, entering);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Deals with a PTRACE_EVENT stop of `tid` other than exec. A new thread is just added to
+    /// the list; forks come back as a Status for the debugger to act on.
+    fn event_status(&mut self, tid: Pid, event: i32) -> Result<Option<Status>, nix::Error> {
+        if event == ptrace::Event::PTRACE_EVENT_CLONE as i32 {
+            let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+            if self.claim(new_tid) {
+                self.add_thread(new_tid, false);
+                self.set_debug_registers(new_tid)?;
+                if self.running {
+                    self.resume_thread(new_tid)?;
+                }
+            } else {
+                self.add_thread(new_tid, true);
+            }
+            return Ok(None);
+        }
+        let vfork = event == ptrace::Event::PTRACE_EVENT_VFORK as i32;
+        if vfork || event == ptrace::Event::PTRACE_EVENT_FORK as i32 {
+            let child = Pid::from_raw(ptrace::getevent(tid)? as i32);
+            // the child starts out with a SIGSTOP, like a new thread
+            while !self.claim(child) {
+                if let WaitStatus::Stopped(..) = waitpid(child, Some(WaitPidFlag::__WALL))? {
+                    break;
+                }
+            }
+            return Ok(Some(Status::Forked(child, vfork)));
+        }
+        if event == ptrace::Event::PTRACE_EVENT_VFORK_DONE as i32 {
+            return Ok(Some(Status::VforkDone));
+        }
+        Ok(None)
+    }
+
+    /// Takes `tid` off the unclaimed list, returning whether its first stop was seen already.
+    fn claim(&mut self, tid: Pid) -> bool {
+        let before = self.unclaimed.len();
+        self.unclaimed.retain(|unclaimed| *unclaimed != tid);
+        self.unclaimed.len() != before
+    }
+
+    /// Brings every thread that is still running to a stop with SIGSTOP. A thread that stops
+    /// with something else first keeps that event pending; a breakpoint trap is instead undone
+    /// by moving rip back onto the 0xcc, so it simply hits the breakpoint again once resumed.
+    fn stop_others(&mut self) -> Result<(), nix::Error> {
+        loop {
+            let running: Vec<Pid> = self
+                .threads
+                .iter()
+                .filter(|thread| thread.running)
+                .map(|thread| thread.tid)
+                .collect();
+            if running.is_empty() {
+                return Ok(());
+            }
+            for tid in &running {
+                let thread = self.thread_mut(*tid).unwrap();
+                if !thread.stop_pending && tgkill(self.pid, *tid, signal::Signal::SIGSTOP).is_ok() {
+                    self.thread_mut(*tid).unwrap().stop_pending = true;
+                }
+            }
+            for tid in running {
+                match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                    Ok(WaitStatus::Stopped(_, signal::Signal::SIGSTOP)) => {
+                        let thread = self.thread_mut(tid).unwrap();
+                        thread.running = false;
+                        thread.stop_pending = false;
+                        self.set_debug_registers(tid)?;
+                    }
+                    Ok(WaitStatus::Stopped(_, signal)) => {
+                        let pending = if signal == signal::Signal::SIGTRAP && self.undo_trap(tid)? {
+                            None
+                        } else {
+                            let regs = ptrace::getregs(tid)?;
+                            Some(Status::Stopped(signal, regs.rip as usize))
+                        };
+                        let thread = self.thread_mut(tid).unwrap();
+                        thread.running = false;
+                        thread.pending = pending;
+                    }
+                    Ok(WaitStatus::PtraceEvent(_, _, event)) => {
+                        self.thread_mut(tid).unwrap().running = false;
+                        let pending = self.event_status(tid, event)?;
+                        self.thread_mut(tid).unwrap().pending = pending;
+                    }
+                    Ok(WaitStatus::PtraceSyscall(_)) => {
+                        let thread = self.thread_mut(tid).unwrap();
+                        thread.running = false;
+                        thread.in_syscall = !thread.in_syscall;
+                        let entering = thread.in_syscall;
+                        // a call no catchpoint is after just leaves it stopped
+                        if self.traces_syscall(tid)? {
+                            let pending = syscall_status(tid, entering)?;
+                            self.thread_mut(tid).unwrap().pending = Some(pending);
+                        }
+                    }
+                    // it exited before it could stop
+                    _ => self.remove_thread(tid),
+                }
+            }
+        }
+    }
+
+    /// If `tid` trapped right after an int3, moves its rip back onto it and returns true.
+    fn undo_trap(&self, tid: Pid) -> Result<bool, nix::Error> {
+        let mut regs = ptrace::getregs(tid)?;
+        let addr = match (regs.rip as usize).checked_sub(1) {
+            Some(addr) => addr,
+            None => return Ok(false),
+        };
         let aligned_addr = align_addr_to_word(addr);
-        let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
-        ptrace::write(
-            self.pid(),
-            aligned_addr as ptrace::AddressType,
-            updated_word as *mut std::ffi::c_void,
-        )?;
-        Ok(orig_byte as u8)
+        let word = ptrace::read(tid, aligned_addr as ptrace::AddressType)? as u64;
+        if (word >> (8 * (addr - aligned_addr))) & 0xff != 0xcc {
+            return Ok(false);
+        }
+        regs.rip -= 1;
+        ptrace::setregs(tid, regs)?;
+        Ok(true)
+    }
+
+    /// Reads debug register `index` (DR0-DR7) out of the inferior's `struct user` with
+    /// PTRACE_PEEKUSER.
+    pub fn read_debug_register(&self, index: usize) -> Result<u64, nix::Error> {
+        let ret = unsafe {
+            nix::errno::Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                self.current.as_raw(),
+                debug_register_offset(index) as *mut libc::c_void,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        // PEEKUSER returns the value itself, so -1 is only an error if errno says so
+        if ret == -1 && nix::errno::errno() != 0 {
+            return Err(nix::Error::last());
+        }
+        Ok(ret as u64)
+    }
+
+    /// Writes debug register `index` (DR0-DR7). Watchpoints apply to the whole process, so
+    /// every thread gets the new value.
+    pub fn write_debug_register(&mut self, index: usize, value: u64) -> Result<(), nix::Error> {
+        if index == DR_STATUS {
+            // the status only matters to the thread that just stopped
+            return poke_debug_register(self.current, index, value);
+        }
+        for thread in self.threads.iter().filter(|thread| !thread.running) {
+            poke_debug_register(thread.tid, index, value)?;
+        }
+        self.debug_registers[index] = value;
+        Ok(())
+    }
+
+    /// Gives a thread we just saw stop for the first time the watchpoints the others have.
+    fn set_debug_registers(&self, tid: Pid) -> Result<(), nix::Error> {
+        if self.debug_registers[DR_CONTROL] == 0 {
+            return Ok(());
+        }
+        // addresses first, since the kernel checks DR7 against them
+        for index in (0..4).chain(std::iter::once(DR_CONTROL)) {
+            poke_debug_register(tid, index, self.debug_registers[index])?;
+        }
+        Ok(())
+    }
+
+    /// Writes one byte, returning the one it replaced, e.g. to plant a breakpoint's 0xcc.
+    pub fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
+        let orig_byte = self.read_memory(addr, 1)?[0];
+        self.write_memory(addr, &[val])?;
+        Ok(orig_byte)
+    }
+
+    /// Writes `bytes` to the inferior with a single pwrite to /proc/<pid>/mem, which can write
+    /// to read-only code just like ptrace. Should that fail, it falls back to ptrace.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let written = match &self.mem {
+            Some(mem) => mem.write_all_at(bytes, addr as u64).is_ok(),
+            None => false,
+        };
+        if written {
+            Ok(())
+        } else {
+            self.poke_bytes(addr, bytes)
+        }
+    }
+
+    /// Reads `len` bytes of the inferior's memory starting at `addr`, one word at a time.
+    fn peek_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut aligned_addr = align_addr_to_word(addr);
+        let mut byte_offset = addr - aligned_addr;
+        while bytes.len() < len {
+            let word = ptrace::read(self.current, aligned_addr as ptrace::AddressType)? as u64;
+            for i in byte_offset..size_of::<usize>() {
+                if bytes.len() == len {
+                    break;
+                }
+                bytes.push((word >> (8 * i)) as u8);
+            }
+            aligned_addr += size_of::<usize>();
+            byte_offset = 0;
+        }
+        Ok(bytes)
+    }
+
+    /// Writes `bytes` to the inferior a word at a time, preserving whatever surrounds them.
+    fn poke_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let word_size = size_of::<usize>();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let aligned_addr = align_addr_to_word(addr + offset);
+            let byte_offset = addr + offset - aligned_addr;
+            let count = (word_size - byte_offset).min(bytes.len() - offset);
+            let word = ptrace::read(self.current, aligned_addr as ptrace::AddressType)? as u64;
+            let mut word = word.to_le_bytes();
+            word[byte_offset..byte_offset + count].copy_from_slice(&bytes[offset..offset + count]);
+            ptrace::write(
+                self.current,
+                aligned_addr as ptrace::AddressType,
+                u64::from_le_bytes(word) as *mut std::ffi::c_void,
+            )?;
+            offset += count;
+        }
+        Ok(())
+    }
+}
+
+impl Target for Inferior {
+    /// Reads the whole range with one process_vm_readv. That refuses pages the inferior itself
+    /// can't read, which ptrace still can, so then it falls back to reading a word at a time.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = vec![0; len];
+        let read = process_vm_readv(
+            self.pid,
+            &[IoVec::from_mut_slice(&mut bytes)],
+            &[RemoteIoVec { base: addr, len }],
+        );
+        match read {
+            Ok(read) if read == len => Ok(bytes),
+            _ => self.peek_bytes(addr, len),
+        }
+    }
+
+    fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.current)
+    }
+
+    fn get_fp_registers(&self) -> Result<libc::user_fpregs_struct, nix::Error> {
+        get_fp_registers(self.current)
+    }
+
+    fn entry_point(&self) -> Result<usize, std::io::Error> {
+        auxv_entry(&std::fs::read(format!("/proc/{}/auxv", self.pid))?)
+    }
+
+    /// Reads the inferior's memory mappings from /proc/<pid>/maps.
+    fn memory_maps(&self) -> Result<Vec<MemoryMap>, std::io::Error> {
+        let maps = std::fs::read_to_string(format!("/proc/{}/maps", self.pid))?;
+        let mut result = Vec::new();
+        for line in maps.lines() {
+            // start-end perms offset dev inode path
+            let fields: Vec<&str> = line.splitn(6, ' ').collect();
+            if fields.len() < 5 {
+                continue;
+            }
+            let range: Vec<&str> = fields[0].split('-').collect();
+            let parse = |field: &str| usize::from_str_radix(field, 16).ok();
+            if let (Some(start), Some(end), Some(offset)) = (
+                range.first().and_then(|field| parse(field)),
+                range.get(1).and_then(|field| parse(field)),
+                parse(fields[2]),
+            ) {
+                result.push(MemoryMap {
+                    start,
+                    end,
+                    perms: fields[1].to_string(),
+                    offset,
+                    path: fields.get(5).map_or("", |path| path.trim()).to_string(),
+                });
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nums(infer: &Inferior) -> Vec<(usize, i32)> {
+        infer
+            .threads()
+            .iter()
+            .map(|thread| (thread.num, thread.tid.as_raw()))
+            .collect()
+    }
+
+    #[test]
+    fn threads_keep_their_numbers() {
+        let mut infer = Inferior::with_main_thread(Pid::from_raw(100), None);
+        infer.add_thread(Pid::from_raw(101), true);
+        infer.add_thread(Pid::from_raw(102), true);
+        // a thread seen again, e.g. through both its clone event and its first stop
+        infer.add_thread(Pid::from_raw(101), false);
+        assert_eq!(nums(&infer), vec![(1, 100), (2, 101), (3, 102)]);
+
+        infer.remove_thread(Pid::from_raw(101));
+        // numbers aren't reused
+        infer.add_thread(Pid::from_raw(103), true);
+        assert_eq!(nums(&infer), vec![(1, 100), (3, 102), (4, 103)]);
+    }
+
+    #[test]
+    fn selects_threads_by_number() {
+        let mut infer = Inferior::with_main_thread(Pid::from_raw(100), None);
+        infer.add_thread(Pid::from_raw(101), true);
+        assert_eq!(infer.tid(), Pid::from_raw(100));
+        assert_eq!(infer.select_thread(2), Some(Pid::from_raw(101)));
+        assert_eq!(infer.tid(), Pid::from_raw(101));
+        assert_eq!(infer.current_thread().map(|thread| thread.num), Some(2));
+        assert_eq!(infer.select_thread(3), None);
+        assert_eq!(infer.tid(), Pid::from_raw(101));
+
+        // the current thread exiting leaves none selected until the next stop
+        infer.remove_thread(Pid::from_raw(101));
+        assert!(infer.current_thread().is_none());
+        assert_eq!(infer.pid(), Pid::from_raw(100));
     }
 }
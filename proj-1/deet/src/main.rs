@@ -1,25 +1,105 @@
+mod condition;
+mod core_file;
 mod debugger;
 mod debugger_command;
+mod disasm;
 mod inferior;
 mod dwarf_data;
+mod eval;
+mod examine;
 mod gimli_wrapper;
+mod registers;
+mod signals;
+mod source;
+mod syscall;
+mod target;
+mod unwind;
+mod value;
+mod watchpoint;
 
 use crate::debugger::Debugger;
+use crate::inferior::exe_path;
 use nix::sys::signal::{signal, SigHandler, Signal};
+use nix::unistd::Pid;
 use std::env;
 
+fn usage(program: &str) -> ! {
+    println!("Usage: {} [-x <script>]... <target program>", program);
+    println!("       {} [-x <script>]... --pid <pid>", program);
+    println!(
+        "       {} [-x <script>]... <target program> --core <core file>",
+        program
+    );
+    println!("With -x, run the commands in each script, then exit with the program's status.");
+    std::process::exit(1);
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
+    let mut args: Vec<String> = env::args().collect();
+    // -x <script> runs a script's commands in batch mode, in place of the prompt
+    let mut scripts = Vec::new();
+    while let Some(index) = args.iter().position(|arg| arg == "-x") {
+        if index + 1 == args.len() {
+            usage(&args[0]);
+        }
+        scripts.push(args.remove(index + 1));
+        args.remove(index);
     }
-    let target = &args[1];
+    // deet --pid <pid> attaches to a running process instead of starting one, and
+    // deet <target program> --core <core file> examines a dump of one that died
+    let (attach_pid, core) = if args.len() == 3 && args[1] == "--pid" {
+        (Some(args[2].clone()), None)
+    } else if args.len() == 4 && args[2] == "--core" {
+        (None, Some(args[3].clone()))
+    } else if args.len() == 2 {
+        (None, None)
+    } else {
+        usage(&args[0]);
+    };
+    let target = match &attach_pid {
+        Some(pid) => {
+            let pid = match pid.parse::<i32>() {
+                Ok(pid) => Pid::from_raw(pid),
+                Err(_) => {
+                    println!("Invalid process id \"{}\"", pid);
+                    std::process::exit(1);
+                }
+            };
+            match exe_path(pid) {
+                Ok(target) => target,
+                Err(err) => {
+                    println!("Cannot read executable of process {}: {}", pid, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => args[1].clone(),
+    };
     // let target = "samples/sleepy_print";
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(&target, !scripts.is_empty());
+    if !debugger.source_init_files() {
+        return;
+    }
+    if attach_pid.is_some() {
+        debugger.attach_command(attach_pid);
+    }
+    if let Some(core) = core {
+        debugger.core_command(&core);
+    }
+    if scripts.is_empty() {
+        debugger.run();
+        return;
+    }
+    for script in scripts {
+        if !debugger.source_command(&script) {
+            break;
+        }
+    }
+    debugger.quit();
+    std::process::exit(debugger.exit_status());
 }
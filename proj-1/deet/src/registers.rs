@@ -0,0 +1,75 @@
+use libc::user_regs_struct;
+
+/// The general purpose registers in the order `info registers` shows them.
+pub const REGISTER_NAMES: [&str; 27] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+    "orig_rax",
+];
+
+// bit positions of the flags gdb shows for eflags
+const EFLAGS: [(u32, &str); 14] = [
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+    (14, "NT"),
+    (16, "RF"),
+    (17, "VM"),
+    (18, "AC"),
+    (21, "ID"),
+];
+
+/// Returns the field of `regs` holding the register called `name`, e.g. `rax` or `eflags`.
+pub fn register_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    Some(match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" | "fp" => &mut regs.rbp,
+        "rsp" | "sp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" | "pc" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        "orig_rax" => &mut regs.orig_rax,
+        _ => return None,
+    })
+}
+
+pub fn register(regs: &user_regs_struct, name: &str) -> Option<u64> {
+    let mut regs = *regs;
+    register_mut(&mut regs, name).map(|value| *value)
+}
+
+/// Spells out the flags set in eflags, like gdb: `[ PF ZF IF ]`.
+pub fn format_eflags(eflags: u64) -> String {
+    let set: Vec<&str> = EFLAGS
+        .iter()
+        .filter(|(bit, _)| eflags & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", set.join(" "))
+}
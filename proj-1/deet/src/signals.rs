@@ -0,0 +1,179 @@
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::str::FromStr;
+
+/// What to do when the inferior receives a signal, as set with `handle`.
+#[derive(Clone, Copy)]
+pub struct SignalPolicy {
+    /// Stop and give control back to the user.
+    pub stop: bool,
+    /// Say that the signal arrived, even when not stopping.
+    pub print: bool,
+    /// Deliver the signal when the inferior resumes, rather than discarding it.
+    pub pass: bool,
+}
+
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl Default for SignalTable {
+    /// gdb's defaults: everything stops, except signals programs commonly expect to get quietly.
+    /// SIGTRAP and SIGINT are the debugger's own, so they aren't passed on.
+    fn default() -> SignalTable {
+        let mut policies = HashMap::new();
+        for signal in Signal::iterator() {
+            let policy = match signal {
+                Signal::SIGALRM
+                | Signal::SIGURG
+                | Signal::SIGCHLD
+                | Signal::SIGWINCH
+                | Signal::SIGIO
+                | Signal::SIGVTALRM
+                | Signal::SIGPROF => SignalPolicy {
+                    stop: false,
+                    print: false,
+                    pass: true,
+                },
+                Signal::SIGTRAP | Signal::SIGINT => SignalPolicy {
+                    stop: true,
+                    print: true,
+                    pass: false,
+                },
+                _ => SignalPolicy {
+                    stop: true,
+                    print: true,
+                    pass: true,
+                },
+            };
+            policies.insert(signal, policy);
+        }
+        SignalTable { policies }
+    }
+}
+
+impl SignalTable {
+    pub fn get(&self, signal: Signal) -> SignalPolicy {
+        self.policies[&signal]
+    }
+
+    /// Applies one `handle` keyword, which must be one of ACTIONS, to `signal`. Like gdb,
+    /// stopping implies printing and not printing implies not stopping.
+    pub fn apply(&mut self, signal: Signal, action: &str) {
+        let policy = self.policies.get_mut(&signal).unwrap();
+        match action {
+            "stop" => {
+                policy.stop = true;
+                policy.print = true;
+            }
+            "nostop" => policy.stop = false,
+            "print" => policy.print = true,
+            "noprint" => {
+                policy.print = false;
+                policy.stop = false;
+            }
+            "pass" | "noignore" => policy.pass = true,
+            "nopass" | "ignore" => policy.pass = false,
+            _ => unreachable!("not a handle action: {}", action),
+        }
+    }
+
+    /// Formats the `info signals` row for `signal`.
+    pub fn format_row(&self, signal: Signal) -> String {
+        let policy = self.get(signal);
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+        format!(
+            "{:<14}{}\t{}\t{}\t\t{}",
+            signal.as_str(),
+            yes_no(policy.stop),
+            yes_no(policy.print),
+            yes_no(policy.pass),
+            description(signal)
+        )
+    }
+}
+
+/// The keywords `handle` takes after the signals.
+pub const ACTIONS: [&str; 8] = [
+    "stop", "nostop", "print", "noprint", "pass", "nopass", "ignore", "noignore",
+];
+
+/// The header `info signals` and `handle` print above their rows.
+pub const TABLE_HEADER: &str = "Signal        Stop\tPrint\tPass to program\tDescription";
+
+/// Parses a signal as gdb accepts it: `SIGUSR1` or a number. Bare names like `STOP` aren't
+/// taken, since `handle` would mistake its keywords for signals.
+pub fn parse_signal(name: &str) -> Option<Signal> {
+    match name.parse::<i32>() {
+        Ok(number) => Signal::try_from(number).ok(),
+        Err(_) => Signal::from_str(&name.to_uppercase()).ok(),
+    }
+}
+
+/// The C library's description of `signal`, e.g. "User defined signal 1".
+pub fn description(signal: Signal) -> String {
+    unsafe { CStr::from_ptr(libc::strsignal(signal as i32)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(table: &SignalTable, signal: Signal) -> (bool, bool, bool) {
+        let policy = table.get(signal);
+        (policy.stop, policy.print, policy.pass)
+    }
+
+    #[test]
+    fn defaults_follow_gdb() {
+        let table = SignalTable::default();
+        assert_eq!(flags(&table, Signal::SIGUSR1), (true, true, true));
+        assert_eq!(flags(&table, Signal::SIGCHLD), (false, false, true));
+        assert_eq!(flags(&table, Signal::SIGTRAP), (true, true, false));
+    }
+
+    #[test]
+    fn stop_and_print_imply_each_other() {
+        let mut table = SignalTable::default();
+        table.apply(Signal::SIGUSR1, "noprint");
+        assert_eq!(flags(&table, Signal::SIGUSR1), (false, false, true));
+        table.apply(Signal::SIGUSR1, "print");
+        assert_eq!(flags(&table, Signal::SIGUSR1), (false, true, true));
+        table.apply(Signal::SIGUSR1, "stop");
+        assert_eq!(flags(&table, Signal::SIGUSR1), (true, true, true));
+        table.apply(Signal::SIGUSR1, "nostop");
+        assert_eq!(flags(&table, Signal::SIGUSR1), (false, true, true));
+
+        table.apply(Signal::SIGCHLD, "stop");
+        assert_eq!(flags(&table, Signal::SIGCHLD), (true, true, true));
+    }
+
+    #[test]
+    fn ignore_is_nopass() {
+        let mut table = SignalTable::default();
+        table.apply(Signal::SIGUSR2, "ignore");
+        assert_eq!(flags(&table, Signal::SIGUSR2), (true, true, false));
+        table.apply(Signal::SIGUSR2, "noignore");
+        assert_eq!(flags(&table, Signal::SIGUSR2), (true, true, true));
+        table.apply(Signal::SIGUSR2, "nopass");
+        assert_eq!(flags(&table, Signal::SIGUSR2), (true, true, false));
+        table.apply(Signal::SIGUSR2, "pass");
+        assert_eq!(flags(&table, Signal::SIGUSR2), (true, true, true));
+        // other signals are left alone
+        assert_eq!(flags(&table, Signal::SIGUSR1), (true, true, true));
+    }
+
+    #[test]
+    fn parses_names_and_numbers() {
+        assert_eq!(parse_signal("SIGUSR1"), Some(Signal::SIGUSR1));
+        assert_eq!(parse_signal("sigint"), Some(Signal::SIGINT));
+        assert_eq!(parse_signal("10"), Some(Signal::SIGUSR1));
+        assert_eq!(parse_signal("STOP"), None);
+        assert_eq!(parse_signal("0"), None);
+        assert_eq!(parse_signal("nostop"), None);
+    }
+}
@@ -0,0 +1,176 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// How many lines `list` shows at a time.
+pub const LIST_LINES: usize = 10;
+
+/// The first and last line `list` shows around `center`, counting from 1.
+pub fn window_around(center: usize) -> (usize, usize) {
+    let first = center.saturating_sub(LIST_LINES / 2).max(1);
+    (first, first + LIST_LINES - 1)
+}
+
+/// The lines a bare `list` shows after `last` was listed.
+pub fn window_after(last: usize) -> (usize, usize) {
+    (last + 1, last + LIST_LINES)
+}
+
+/// The lines `list -` shows before `first` was listed, or None at the start of the file.
+pub fn window_before(first: usize) -> Option<(usize, usize)> {
+    if first <= 1 {
+        return None;
+    }
+    Some((first.saturating_sub(LIST_LINES).max(1), first - 1))
+}
+
+/// Source files for `list` and for the line shown whenever the inferior stops. Files are read on
+/// first use and kept, since the same few get shown over and over.
+#[derive(Default)]
+pub struct SourceCache {
+    /// Directories added with `directory`, searched first and most recent first, for programs
+    /// built somewhere else.
+    directories: Vec<String>,
+    /// Lines of every file asked for so far, or None if it couldn't be found.
+    files: RefCell<HashMap<String, Option<Rc<Vec<String>>>>>,
+}
+
+impl SourceCache {
+    pub fn directories(&self) -> &[String] {
+        &self.directories
+    }
+
+    pub fn add_directory(&mut self, dir: &str) {
+        self.directories.retain(|existing| existing != dir);
+        self.directories.insert(0, dir.to_string());
+        // files that were missing may turn up now
+        self.files.borrow_mut().clear();
+    }
+
+    pub fn reset_directories(&mut self) {
+        self.directories.clear();
+        self.files.borrow_mut().clear();
+    }
+
+    /// Returns the lines of the file the debug info calls `path`.
+    pub fn get_lines(&self, path: &str) -> Option<Rc<Vec<String>>> {
+        if let Some(lines) = self.files.borrow().get(path) {
+            return lines.clone();
+        }
+        let lines = self.find(path).and_then(|found| {
+            let contents = fs::read(found).ok()?;
+            Some(Rc::new(
+                String::from_utf8_lossy(&contents)
+                    .lines()
+                    .map(|line| line.to_string())
+                    .collect(),
+            ))
+        });
+        self.files
+            .borrow_mut()
+            .insert(path.to_string(), lines.clone());
+        lines
+    }
+
+    /// Looks for `path` like gdb does: in the added directories, then where the compiler saw it,
+    /// then in the current directory.
+    fn find(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        let name = path.file_name()?;
+        let mut candidates = Vec::new();
+        for dir in &self.directories {
+            if path.is_relative() {
+                candidates.push(Path::new(dir).join(path));
+            }
+            candidates.push(Path::new(dir).join(name));
+        }
+        candidates.push(path.to_path_buf());
+        candidates.push(PathBuf::from(name));
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_stay_within_the_file_start() {
+        assert_eq!(window_around(20), (15, 24));
+        assert_eq!(window_around(3), (1, 10));
+        assert_eq!(window_around(0), (1, 10));
+        assert_eq!(window_after(10), (11, 20));
+        assert_eq!(window_before(21), Some((11, 20)));
+        assert_eq!(window_before(5), Some((1, 4)));
+        assert_eq!(window_before(1), None);
+    }
+
+    /// A scratch directory with `sub/main.c` and `lib.c` in it, removed when dropped.
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str) -> Tree {
+            let root = std::env::temp_dir().join(format!("deet-{}-{}", name, std::process::id()));
+            fs::create_dir_all(root.join("sub")).unwrap();
+            fs::write(root.join("sub").join("main.c"), "int main() {\n}\n").unwrap();
+            fs::write(root.join("lib.c"), "int lib;\n").unwrap();
+            Tree(root)
+        }
+
+        fn path(&self, relative: &str) -> String {
+            self.0.join(relative).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn finds_files_where_the_compiler_saw_them() {
+        let tree = Tree::new("find-absolute");
+        let sources = SourceCache::default();
+        let main = tree.path("sub/main.c");
+        assert_eq!(sources.find(&main), Some(PathBuf::from(&main)));
+        assert_eq!(sources.find(&tree.path("sub/nosuch.c")), None);
+    }
+
+    #[test]
+    fn searches_added_directories() {
+        let tree = Tree::new("find-directories");
+        let mut sources = SourceCache::default();
+        assert_eq!(sources.find("sub/main.c"), None);
+        sources.add_directory(&tree.path(""));
+        // relative paths are looked up whole, then by file name
+        assert_eq!(
+            sources.find("sub/main.c"),
+            Some(PathBuf::from(tree.path("sub/main.c")))
+        );
+        assert_eq!(
+            sources.find("/moved/away/lib.c"),
+            Some(PathBuf::from(tree.path("lib.c")))
+        );
+        // the most recently added directory wins
+        sources.add_directory(&tree.path("sub"));
+        assert_eq!(
+            sources.find("other/main.c"),
+            Some(PathBuf::from(tree.path("sub/main.c")))
+        );
+        sources.reset_directories();
+        assert_eq!(sources.find("other/main.c"), None);
+    }
+
+    #[test]
+    fn missing_files_turn_up_after_adding_a_directory() {
+        let tree = Tree::new("get-lines");
+        let mut sources = SourceCache::default();
+        assert!(sources.get_lines("lib.c").is_none());
+        sources.add_directory(&tree.path(""));
+        let lines = sources.get_lines("lib.c").unwrap();
+        assert_eq!(*lines, vec!["int lib;".to_string()]);
+    }
+}
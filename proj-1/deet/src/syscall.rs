@@ -0,0 +1,649 @@
+use crate::eval::Context;
+use crate::value::{format_bytes, read_string, read_unsigned};
+use nix::errno::Errno;
+
+/// x86-64 syscall names, indexed by number, from asm/unistd_64.h.
+const NAMES: [&str; 335] = [
+    "read",
+    "write",
+    "open",
+    "close",
+    "stat",
+    "fstat",
+    "lstat",
+    "poll",
+    "lseek",
+    "mmap",
+    "mprotect",
+    "munmap",
+    "brk",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "ioctl",
+    "pread64",
+    "pwrite64",
+    "readv",
+    "writev",
+    "access",
+    "pipe",
+    "select",
+    "sched_yield",
+    "mremap",
+    "msync",
+    "mincore",
+    "madvise",
+    "shmget",
+    "shmat",
+    "shmctl",
+    "dup",
+    "dup2",
+    "pause",
+    "nanosleep",
+    "getitimer",
+    "alarm",
+    "setitimer",
+    "getpid",
+    "sendfile",
+    "socket",
+    "connect",
+    "accept",
+    "sendto",
+    "recvfrom",
+    "sendmsg",
+    "recvmsg",
+    "shutdown",
+    "bind",
+    "listen",
+    "getsockname",
+    "getpeername",
+    "socketpair",
+    "setsockopt",
+    "getsockopt",
+    "clone",
+    "fork",
+    "vfork",
+    "execve",
+    "exit",
+    "wait4",
+    "kill",
+    "uname",
+    "semget",
+    "semop",
+    "semctl",
+    "shmdt",
+    "msgget",
+    "msgsnd",
+    "msgrcv",
+    "msgctl",
+    "fcntl",
+    "flock",
+    "fsync",
+    "fdatasync",
+    "truncate",
+    "ftruncate",
+    "getdents",
+    "getcwd",
+    "chdir",
+    "fchdir",
+    "rename",
+    "mkdir",
+    "rmdir",
+    "creat",
+    "link",
+    "unlink",
+    "symlink",
+    "readlink",
+    "chmod",
+    "fchmod",
+    "chown",
+    "fchown",
+    "lchown",
+    "umask",
+    "gettimeofday",
+    "getrlimit",
+    "getrusage",
+    "sysinfo",
+    "times",
+    "ptrace",
+    "getuid",
+    "syslog",
+    "getgid",
+    "setuid",
+    "setgid",
+    "geteuid",
+    "getegid",
+    "setpgid",
+    "getppid",
+    "getpgrp",
+    "setsid",
+    "setreuid",
+    "setregid",
+    "getgroups",
+    "setgroups",
+    "setresuid",
+    "getresuid",
+    "setresgid",
+    "getresgid",
+    "getpgid",
+    "setfsuid",
+    "setfsgid",
+    "getsid",
+    "capget",
+    "capset",
+    "rt_sigpending",
+    "rt_sigtimedwait",
+    "rt_sigqueueinfo",
+    "rt_sigsuspend",
+    "sigaltstack",
+    "utime",
+    "mknod",
+    "uselib",
+    "personality",
+    "ustat",
+    "statfs",
+    "fstatfs",
+    "sysfs",
+    "getpriority",
+    "setpriority",
+    "sched_setparam",
+    "sched_getparam",
+    "sched_setscheduler",
+    "sched_getscheduler",
+    "sched_get_priority_max",
+    "sched_get_priority_min",
+    "sched_rr_get_interval",
+    "mlock",
+    "munlock",
+    "mlockall",
+    "munlockall",
+    "vhangup",
+    "modify_ldt",
+    "pivot_root",
+    "_sysctl",
+    "prctl",
+    "arch_prctl",
+    "adjtimex",
+    "setrlimit",
+    "chroot",
+    "sync",
+    "acct",
+    "settimeofday",
+    "mount",
+    "umount2",
+    "swapon",
+    "swapoff",
+    "reboot",
+    "sethostname",
+    "setdomainname",
+    "iopl",
+    "ioperm",
+    "create_module",
+    "init_module",
+    "delete_module",
+    "get_kernel_syms",
+    "query_module",
+    "quotactl",
+    "nfsservctl",
+    "getpmsg",
+    "putpmsg",
+    "afs_syscall",
+    "tuxcall",
+    "security",
+    "gettid",
+    "readahead",
+    "setxattr",
+    "lsetxattr",
+    "fsetxattr",
+    "getxattr",
+    "lgetxattr",
+    "fgetxattr",
+    "listxattr",
+    "llistxattr",
+    "flistxattr",
+    "removexattr",
+    "lremovexattr",
+    "fremovexattr",
+    "tkill",
+    "time",
+    "futex",
+    "sched_setaffinity",
+    "sched_getaffinity",
+    "set_thread_area",
+    "io_setup",
+    "io_destroy",
+    "io_getevents",
+    "io_submit",
+    "io_cancel",
+    "get_thread_area",
+    "lookup_dcookie",
+    "epoll_create",
+    "epoll_ctl_old",
+    "epoll_wait_old",
+    "remap_file_pages",
+    "getdents64",
+    "set_tid_address",
+    "restart_syscall",
+    "semtimedop",
+    "fadvise64",
+    "timer_create",
+    "timer_settime",
+    "timer_gettime",
+    "timer_getoverrun",
+    "timer_delete",
+    "clock_settime",
+    "clock_gettime",
+    "clock_getres",
+    "clock_nanosleep",
+    "exit_group",
+    "epoll_wait",
+    "epoll_ctl",
+    "tgkill",
+    "utimes",
+    "vserver",
+    "mbind",
+    "set_mempolicy",
+    "get_mempolicy",
+    "mq_open",
+    "mq_unlink",
+    "mq_timedsend",
+    "mq_timedreceive",
+    "mq_notify",
+    "mq_getsetattr",
+    "kexec_load",
+    "waitid",
+    "add_key",
+    "request_key",
+    "keyctl",
+    "ioprio_set",
+    "ioprio_get",
+    "inotify_init",
+    "inotify_add_watch",
+    "inotify_rm_watch",
+    "migrate_pages",
+    "openat",
+    "mkdirat",
+    "mknodat",
+    "fchownat",
+    "futimesat",
+    "newfstatat",
+    "unlinkat",
+    "renameat",
+    "linkat",
+    "symlinkat",
+    "readlinkat",
+    "fchmodat",
+    "faccessat",
+    "pselect6",
+    "ppoll",
+    "unshare",
+    "set_robust_list",
+    "get_robust_list",
+    "splice",
+    "tee",
+    "sync_file_range",
+    "vmsplice",
+    "move_pages",
+    "utimensat",
+    "epoll_pwait",
+    "signalfd",
+    "timerfd_create",
+    "eventfd",
+    "fallocate",
+    "timerfd_settime",
+    "timerfd_gettime",
+    "accept4",
+    "signalfd4",
+    "eventfd2",
+    "epoll_create1",
+    "dup3",
+    "pipe2",
+    "inotify_init1",
+    "preadv",
+    "pwritev",
+    "rt_tgsigqueueinfo",
+    "perf_event_open",
+    "recvmmsg",
+    "fanotify_init",
+    "fanotify_mark",
+    "prlimit64",
+    "name_to_handle_at",
+    "open_by_handle_at",
+    "clock_adjtime",
+    "syncfs",
+    "sendmmsg",
+    "setns",
+    "getcpu",
+    "process_vm_readv",
+    "process_vm_writev",
+    "kcmp",
+    "finit_module",
+    "sched_setattr",
+    "sched_getattr",
+    "renameat2",
+    "seccomp",
+    "getrandom",
+    "memfd_create",
+    "kexec_file_load",
+    "bpf",
+    "execveat",
+    "userfaultfd",
+    "membarrier",
+    "mlock2",
+    "copy_file_range",
+    "preadv2",
+    "pwritev2",
+    "pkey_mprotect",
+    "pkey_alloc",
+    "pkey_free",
+    "statx",
+    "io_pgetevents",
+    "rseq",
+];
+
+/// The syscalls numbered past the gap after 334.
+const HIGH_NAMES: [(u64, &str); 27] = [
+    (424, "pidfd_send_signal"),
+    (425, "io_uring_setup"),
+    (426, "io_uring_enter"),
+    (427, "io_uring_register"),
+    (428, "open_tree"),
+    (429, "move_mount"),
+    (430, "fsopen"),
+    (431, "fsconfig"),
+    (432, "fsmount"),
+    (433, "fspick"),
+    (434, "pidfd_open"),
+    (435, "clone3"),
+    (436, "close_range"),
+    (437, "openat2"),
+    (438, "pidfd_getfd"),
+    (439, "faccessat2"),
+    (440, "process_madvise"),
+    (441, "epoll_pwait2"),
+    (442, "mount_setattr"),
+    (443, "quotactl_fd"),
+    (444, "landlock_create_ruleset"),
+    (445, "landlock_add_rule"),
+    (446, "landlock_restrict_self"),
+    (447, "memfd_secret"),
+    (448, "process_mrelease"),
+    (449, "futex_waitv"),
+    (450, "set_mempolicy_home_node"),
+];
+
+/// How many bytes of a read or write buffer to show.
+const MAX_BUFFER: usize = 32;
+/// How many execve arguments to show.
+const MAX_ARGS: usize = 16;
+const AT_FDCWD: i32 = -100;
+
+/// Names syscall `number`, or None if x86-64 has no such call.
+pub fn name(number: u64) -> Option<&'static str> {
+    NAMES.get(number as usize).copied().or_else(|| {
+        HIGH_NAMES
+            .iter()
+            .find(|(high, _)| *high == number)
+            .map(|(_, name)| *name)
+    })
+}
+
+/// Looks up a syscall by name, e.g. `write`, or takes its number if x86-64 has such a call.
+pub fn number(syscall: &str) -> Option<u64> {
+    if let Ok(number) = syscall.parse::<u64>() {
+        return name(number).map(|_| number);
+    }
+    NAMES
+        .iter()
+        .position(|known| *known == syscall)
+        .map(|number| number as u64)
+        .or_else(|| {
+            HIGH_NAMES
+                .iter()
+                .find(|(_, known)| *known == syscall)
+                .map(|(number, _)| *number)
+        })
+}
+
+/// Names syscall `number` for messages, falling back to the number itself.
+pub fn display_name(number: u64) -> String {
+    match name(number) {
+        Some(name) => name.to_string(),
+        None => number.to_string(),
+    }
+}
+
+/// A `catch syscall` catchpoint. It shares its numbering with breakpoints and watchpoints.
+pub struct Catchpoint {
+    pub num: usize,
+    /// The syscall numbers to stop at; empty to stop at any.
+    pub syscalls: Vec<u64>,
+    pub enabled: bool,
+    pub hit_count: usize,
+}
+
+impl Catchpoint {
+    pub fn catches(&self, number: u64) -> bool {
+        self.enabled && (self.syscalls.is_empty() || self.syscalls.contains(&number))
+    }
+
+    /// Describes what it catches the way gdb does, e.g. `syscalls 'read' [0] 'write' [1]`.
+    pub fn describe(&self) -> String {
+        let calls: Vec<String> = self
+            .syscalls
+            .iter()
+            .map(|number| format!("'{}' [{}]", display_name(*number), number))
+            .collect();
+        match calls.len() {
+            0 => "syscall".to_string(),
+            1 => format!("syscall {}", calls[0]),
+            _ => format!("syscalls {}", calls.join(" ")),
+        }
+    }
+
+    /// The `What` column of `info breakpoints`.
+    pub fn what(&self) -> String {
+        let names: Vec<String> = self
+            .syscalls
+            .iter()
+            .map(|number| display_name(*number))
+            .collect();
+        match names.len() {
+            0 => "syscall \"<any syscall>\"".to_string(),
+            1 => format!("syscall \"{}\"", names[0]),
+            _ => format!("syscalls \"{}\"", names.join(", ")),
+        }
+    }
+}
+
+/// The syscall numbers the enabled catchpoints want stops at, in the form
+/// `Inferior::trace_syscalls` takes: empty if any will do, or None if no catchpoint is enabled.
+pub fn caught_syscalls(catch_points: &[Catchpoint]) -> Option<Vec<u64>> {
+    let enabled: Vec<&Catchpoint> = catch_points.iter().filter(|cp| cp.enabled).collect();
+    if enabled.is_empty() {
+        None
+    } else if enabled.iter().any(|cp| cp.syscalls.is_empty()) {
+        Some(Vec::new())
+    } else {
+        Some(enabled.iter().flat_map(|cp| cp.syscalls.clone()).collect())
+    }
+}
+
+/// Formats a call strace-style, e.g. `write(1, "hello\n", 6)`, decoding the arguments of the
+/// common calls; others are just named. `args` are the six argument registers in order.
+pub fn format_call(context: &dyn Context, number: u64, args: [u64; 6]) -> String {
+    let name = display_name(number);
+    let decoded = match name.as_str() {
+        "open" => format!(
+            "{}, {:#x}, 0{:o}",
+            read_string(context, args[0] as usize),
+            args[1],
+            args[2]
+        ),
+        "openat" => format!(
+            "{}, {}, {:#x}, 0{:o}",
+            format_dirfd(args[0] as i32),
+            read_string(context, args[1] as usize),
+            args[2],
+            args[3]
+        ),
+        "read" => format!("{}, {:#x}, {}", args[0] as i32, args[1], args[2]),
+        "write" => format!(
+            "{}, {}, {}",
+            args[0] as i32,
+            format_buffer(context, args[1] as usize, args[2] as usize),
+            args[2]
+        ),
+        "close" => format!("{}", args[0] as i32),
+        "mmap" => format!(
+            "{:#x}, {}, {:#x}, {:#x}, {}, {:#x}",
+            args[0], args[1], args[2], args[3], args[4] as i32, args[5]
+        ),
+        "fork" | "vfork" => String::new(),
+        "clone" => format!("{:#x}, {:#x}", args[0], args[1]),
+        "execve" => format!(
+            "{}, {}, {:#x}",
+            read_string(context, args[0] as usize),
+            format_argv(context, args[1] as usize),
+            args[2]
+        ),
+        "exit" | "exit_group" => format!("{}", args[0] as i32),
+        _ => return name,
+    };
+    format!("{}({})", name, decoded)
+}
+
+/// Formats what syscall `number` returned: a number, an address for mmap, or -1 and the
+/// errno for failures.
+pub fn format_return(number: u64, ret: u64) -> String {
+    let ret = ret as i64;
+    if (-4095..0).contains(&ret) {
+        let errno = Errno::from_i32(-ret as i32);
+        return format!("-1 {:?} ({})", errno, errno.desc());
+    }
+    match name(number) {
+        Some("mmap") | Some("brk") => format!("{:#x}", ret),
+        _ => ret.to_string(),
+    }
+}
+
+fn format_dirfd(fd: i32) -> String {
+    if fd == AT_FDCWD {
+        "AT_FDCWD".to_string()
+    } else {
+        fd.to_string()
+    }
+}
+
+/// Quotes the start of a `len` byte buffer at `addr`. It may well hold binary data, so a NUL
+/// doesn't end it.
+fn format_buffer(context: &dyn Context, addr: usize, len: usize) -> String {
+    match context.read_memory(addr, len.min(MAX_BUFFER)) {
+        Ok(bytes) => format_bytes(&bytes, len > MAX_BUFFER),
+        Err(_) => format!("{:#x}", addr),
+    }
+}
+
+/// Formats a NULL-terminated array of strings, like execve's argv.
+fn format_argv(context: &dyn Context, addr: usize) -> String {
+    let mut args = Vec::new();
+    for i in 0..=MAX_ARGS {
+        let entry = addr
+            .checked_add(8 * i)
+            .and_then(|entry| context.read_memory(entry, 8).ok());
+        let pointer = match entry {
+            Some(bytes) => read_unsigned(&bytes, 8) as usize,
+            None => return format!("{:#x}", addr),
+        };
+        if pointer == 0 {
+            return format!("[{}]", args.join(", "));
+        }
+        if i == MAX_ARGS {
+            break;
+        }
+        args.push(read_string(context, pointer));
+    }
+    format!("[{}, ...]", args.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_numbers() {
+        assert_eq!(name(0), Some("read"));
+        assert_eq!(name(59), Some("execve"));
+        assert_eq!(name(231), Some("exit_group"));
+        assert_eq!(name(435), Some("clone3"));
+        assert_eq!(name(400), None);
+        assert_eq!(name(99999), None);
+        assert_eq!(number("write"), Some(1));
+        assert_eq!(number("clone3"), Some(435));
+        assert_eq!(number("nosuchcall"), None);
+        assert_eq!(number("60"), Some(60));
+        assert_eq!(number("400"), None);
+        assert_eq!(number("99999"), None);
+        assert_eq!(display_name(1), "write");
+        assert_eq!(display_name(99999), "99999");
+    }
+
+    #[test]
+    fn return_values() {
+        assert_eq!(format_return(1, 5), "5");
+        assert_eq!(format_return(0, 0), "0");
+        assert_eq!(
+            format_return(257, -2i64 as u64),
+            "-1 ENOENT (No such file or directory)"
+        );
+        assert_eq!(format_return(0, -9i64 as u64), "-1 EBADF (Bad file number)");
+        assert_eq!(format_return(9, 0x7f12_3456_7000), "0x7f1234567000");
+        assert_eq!(format_return(12, 0x4c_d000), "0x4cd000");
+        // mmap's failures are still errnos
+        assert_eq!(format_return(9, -12i64 as u64), "-1 ENOMEM (Out of memory)");
+        // past -4095 it is a value, not an error
+        assert_eq!(format_return(8, -4096i64 as u64), "-4096");
+    }
+
+    #[test]
+    fn catchpoints() {
+        let catch_point = Catchpoint {
+            num: 3,
+            syscalls: vec![0, 1],
+            enabled: true,
+            hit_count: 0,
+        };
+        assert!(catch_point.catches(1));
+        assert!(!catch_point.catches(2));
+        assert_eq!(catch_point.describe(), "syscalls 'read' [0] 'write' [1]");
+        let any = Catchpoint {
+            syscalls: Vec::new(),
+            ..catch_point
+        };
+        assert!(any.catches(2));
+    }
+
+    #[test]
+    fn caught_syscalls_merge_enabled_catchpoints() {
+        let catch_point = |syscalls: Vec<u64>, enabled| Catchpoint {
+            num: 1,
+            syscalls,
+            enabled,
+            hit_count: 0,
+        };
+        assert_eq!(caught_syscalls(&[]), None);
+        assert_eq!(caught_syscalls(&[catch_point(vec![0], false)]), None);
+        assert_eq!(
+            caught_syscalls(&[
+                catch_point(vec![0], true),
+                catch_point(vec![1, 2], true),
+                catch_point(vec![3], false),
+            ]),
+            Some(vec![0, 1, 2])
+        );
+        assert_eq!(
+            caught_syscalls(&[catch_point(vec![0], true), catch_point(vec![], true)]),
+            Some(vec![])
+        );
+    }
+}
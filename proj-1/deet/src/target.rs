@@ -0,0 +1,54 @@
+use libc::{user_fpregs_struct, user_regs_struct};
+use std::convert::TryInto;
+use std::mem::size_of;
+
+/// One line of /proc/<pid>/maps.
+#[derive(Clone)]
+pub struct MemoryMap {
+    pub start: usize,
+    pub end: usize,
+    /// Permissions like `r-xp`. Empty for the mappings a core file lists, which don't say.
+    pub perms: String,
+    pub offset: usize,
+    /// Backing file, or a pseudo-path like `[stack]`; empty for anonymous mappings.
+    pub path: String,
+}
+
+/// The memory and registers of the program being examined, either a live process or a core
+/// file. Commands that only look, like `bt`, `print` and `x`, work on either.
+pub trait Target {
+    /// Reads `len` bytes of memory starting at `addr`.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error>;
+
+    /// The general purpose registers of the current thread.
+    fn get_registers(&self) -> Result<user_regs_struct, nix::Error>;
+
+    /// The x87/SSE registers of the current thread.
+    fn get_fp_registers(&self) -> Result<user_fpregs_struct, nix::Error>;
+
+    /// Returns the runtime entry point (AT_ENTRY) from the auxiliary vector. Comparing it with
+    /// the ELF header's entry gives the load base of a position-independent executable.
+    fn entry_point(&self) -> Result<usize, std::io::Error>;
+
+    /// The memory mappings, for finding the shared library an address falls in.
+    fn memory_maps(&self) -> Result<Vec<MemoryMap>, std::io::Error>;
+}
+
+/// Looks up AT_ENTRY in a raw auxiliary vector.
+pub fn auxv_entry(auxv: &[u8]) -> Result<usize, std::io::Error> {
+    // auxv is a list of (type, value) pairs of native words, terminated by AT_NULL
+    for pair in auxv.chunks_exact(2 * size_of::<usize>()) {
+        let (key, value) = pair.split_at(size_of::<usize>());
+        let key = usize::from_ne_bytes(key.try_into().unwrap());
+        if key == libc::AT_ENTRY as usize {
+            return Ok(usize::from_ne_bytes(value.try_into().unwrap()));
+        }
+        if key == libc::AT_NULL as usize {
+            break;
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "no AT_ENTRY in auxv",
+    ))
+}
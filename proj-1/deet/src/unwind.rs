@@ -0,0 +1,229 @@
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, Register, RegisterRule,
+    UninitializedUnwindContext, UnwindSection, UnwindTableRow,
+};
+use object::{Object, ObjectSection};
+use std::fs;
+use std::rc::Rc;
+
+type R = gimli::EndianRcSlice<gimli::RunTimeEndian>;
+
+// DWARF register numbers on x86-64
+const RBP: Register = Register(6);
+const RSP: Register = Register(7);
+const RETURN_ADDRESS: Register = Register(16);
+
+/// The registers needed to find a frame's variables and its caller.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub pc: usize,
+    pub rsp: usize,
+    pub rbp: usize,
+}
+
+impl Frame {
+    /// The pc to look the frame up by. An outer frame's pc is a return address, which can be
+    /// just past the end of a function that ends in a call, so it goes by the call instead.
+    pub fn lookup_pc(&self, innermost: bool) -> usize {
+        if innermost {
+            self.pc
+        } else {
+            // a garbage return address of 0 mustn't wrap around
+            self.pc.saturating_sub(1)
+        }
+    }
+}
+
+/// The result of unwinding one frame.
+pub enum Unwound {
+    Caller(Frame),
+    /// The CFI marks this as the outermost frame (e.g. `_start`).
+    Outermost,
+    /// There is no CFI covering the pc.
+    NoInfo,
+}
+
+/// Call frame information from `.eh_frame` and `.debug_frame`, which describes for every
+/// instruction how to find the CFA and where the caller's registers were saved.
+pub struct CallFrameInfo {
+    eh_frame: Option<(EhFrame<R>, BaseAddresses)>,
+    debug_frame: Option<DebugFrame<R>>,
+}
+
+fn section_data(object: &object::File, name: &str, endian: gimli::RunTimeEndian) -> Option<R> {
+    let data = object.section_data_by_name(name)?;
+    if data.is_empty() {
+        return None;
+    }
+    Some(gimli::EndianRcSlice::new(Rc::from(data.as_ref()), endian))
+}
+
+fn section_address(object: &object::File, name: &str) -> u64 {
+    object
+        .section_by_name(name)
+        .map_or(0, |section| section.address())
+}
+
+impl CallFrameInfo {
+    /// Loads the call frame information of another object file, such as a shared library the
+    /// inferior has mapped.
+    pub fn from_file(path: &str) -> Option<CallFrameInfo> {
+        let file = fs::File::open(path).ok()?;
+        let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&*mmap).ok()?;
+        let endian = if object.is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        Some(CallFrameInfo::load(&object, endian))
+    }
+
+    pub fn load(object: &object::File, endian: gimli::RunTimeEndian) -> CallFrameInfo {
+        let eh_frame = section_data(object, ".eh_frame", endian).map(|data| {
+            // pointers in .eh_frame are usually encoded relative to the section itself
+            let bases = BaseAddresses::default()
+                .set_eh_frame(section_address(object, ".eh_frame"))
+                .set_text(section_address(object, ".text"));
+            (EhFrame::from(data), bases)
+        });
+        let debug_frame = section_data(object, ".debug_frame", endian).map(|data| {
+            let mut debug_frame = DebugFrame::from(data);
+            debug_frame.set_address_size(8);
+            debug_frame
+        });
+        CallFrameInfo {
+            eh_frame,
+            debug_frame,
+        }
+    }
+
+    /// Finds the unwind row covering the link-time address `pc`.
+    fn row_for(&self, pc: usize) -> Option<UnwindTableRow<R>> {
+        let mut ctx = UninitializedUnwindContext::new();
+        if let Some((eh_frame, bases)) = &self.eh_frame {
+            let row = eh_frame.unwind_info_for_address(
+                bases,
+                &mut ctx,
+                pc as u64,
+                |section, bases, offset| section.cie_from_offset(bases, offset),
+            );
+            if let Ok(row) = row {
+                return Some(row);
+            }
+        }
+        if let Some(debug_frame) = &self.debug_frame {
+            let row = debug_frame.unwind_info_for_address(
+                &BaseAddresses::default(),
+                &mut ctx,
+                pc as u64,
+                |section, bases, offset| section.cie_from_offset(bases, offset),
+            );
+            if let Ok(row) = row {
+                return Some(row);
+            }
+        }
+        None
+    }
+
+    /// Computes the canonical frame address of `frame`, i.e. the caller's rsp before the call,
+    /// if there is CFI for `lookup_pc`.
+    pub fn cfa(&self, frame: &Frame, lookup_pc: usize) -> Option<usize> {
+        compute_cfa(&self.row_for(lookup_pc)?, frame).ok()
+    }
+
+    /// Recovers the frame that called `frame`. `lookup_pc` is the link-time address to look up,
+    /// which for every frame but the innermost should point into the call instruction rather
+    /// than at the return address.
+    pub fn unwind<F>(
+        &self,
+        frame: &Frame,
+        lookup_pc: usize,
+        read_word: F,
+    ) -> Result<Unwound, String>
+    where
+        F: Fn(usize) -> Result<usize, String>,
+    {
+        let row = match self.row_for(lookup_pc) {
+            Some(row) => row,
+            None => return Ok(Unwound::NoInfo),
+        };
+
+        let cfa = compute_cfa(&row, frame)?;
+        let recover = |rule: RegisterRule<R>, value: usize| -> Result<Option<usize>, String> {
+            match rule {
+                RegisterRule::Undefined => Ok(None),
+                RegisterRule::SameValue => Ok(Some(value)),
+                RegisterRule::Offset(offset) => read_word((cfa as i64 + offset) as usize).map(Some),
+                RegisterRule::ValOffset(offset) => Ok(Some((cfa as i64 + offset) as usize)),
+                RegisterRule::Register(RSP) => Ok(Some(frame.rsp)),
+                RegisterRule::Register(RBP) => Ok(Some(frame.rbp)),
+                _ => Err("unsupported register rule".to_string()),
+            }
+        };
+        // the return address column has no value of its own in the callee, so an undefined or
+        // same-value rule there means there is no caller
+        let pc = match row.register(RETURN_ADDRESS) {
+            RegisterRule::SameValue => return Ok(Unwound::Outermost),
+            rule => match recover(rule, 0)? {
+                Some(pc) => pc,
+                None => return Ok(Unwound::Outermost),
+            },
+        };
+        let rbp = match row.register(RBP) {
+            // a register without a rule keeps its value across the call
+            RegisterRule::Undefined => frame.rbp,
+            rule => recover(rule, frame.rbp)?.unwrap_or(frame.rbp),
+        };
+        Ok(Unwound::Caller(Frame { pc, rsp: cfa, rbp }))
+    }
+}
+
+fn compute_cfa(row: &UnwindTableRow<R>, frame: &Frame) -> Result<usize, String> {
+    match row.cfa() {
+        CfaRule::RegisterAndOffset { register, offset } => {
+            let base = match *register {
+                RSP => frame.rsp,
+                RBP => frame.rbp,
+                Register(other) => {
+                    return Err(format!("CFA based on unsupported register {}", other))
+                }
+            };
+            Ok((base as i64 + offset) as usize)
+        }
+        CfaRule::Expression(_) => Err("CFA expressions are not supported".to_string()),
+    }
+}
+
+/// Call frame information of a shared library mapped into the inferior.
+pub struct LibraryCfi {
+    /// Address range covered by the library's mappings.
+    pub start: usize,
+    pub end: usize,
+    /// Where the library's link-time address zero got mapped.
+    pub base: usize,
+    /// None if the file couldn't be read or has no CFI.
+    pub cfi: Option<CallFrameInfo>,
+}
+
+impl LibraryCfi {
+    pub fn contains(&self, pc: usize) -> bool {
+        self.start <= pc && pc < self.end
+    }
+}
+
+/// Unwinds `frame` assuming the standard prologue (`push rbp; mov rbp, rsp`), for code we have
+/// no call frame information for.
+pub fn unwind_frame_pointer<F>(frame: &Frame, read_word: F) -> Result<Unwound, String>
+where
+    F: Fn(usize) -> Result<usize, String>,
+{
+    if frame.rbp == 0 {
+        return Ok(Unwound::Outermost);
+    }
+    Ok(Unwound::Caller(Frame {
+        pc: read_word(frame.rbp + 8)?,
+        rsp: frame.rbp + 16,
+        rbp: read_word(frame.rbp)?,
+    }))
+}
@@ -0,0 +1,485 @@
+use crate::dwarf_data::{Encoding, Type, TypeKind};
+use crate::eval::Context;
+use std::convert::TryInto;
+
+/// gdb's default `print elements` limit, for arrays and strings.
+const MAX_ELEMENTS: usize = 200;
+/// Runs of at least this many equal array elements are collapsed into `<repeats N times>`.
+const REPEAT_THRESHOLD: usize = 10;
+const PAGE_SIZE: usize = 4096;
+
+/// Formats the raw bytes of a value according to its type, the way gdb would show them:
+/// pointers in hex, structs as `{a = 1, b = 0x0}`, arrays as `{1, 2, 3}` and char arrays as
+/// strings. `context` is used to look up member types and to follow `char *` strings.
+pub fn format_value(context: &dyn Context, entity_type: &Type, bytes: &[u8]) -> String {
+    if bytes.len() < entity_type.size {
+        return "<unreadable>".to_string();
+    }
+    let entity_type = entity_type.strip_aliases();
+    match &entity_type.kind {
+        TypeKind::Base(encoding) => format_base(*encoding, entity_type.size, bytes),
+        TypeKind::Pointer(target) => {
+            let addr = read_unsigned(bytes, entity_type.size) as usize;
+            let target = target.as_ref().map(|target| target.strip_aliases());
+            match target.map(|target| &target.kind) {
+                Some(TypeKind::Base(Encoding::SignedChar))
+                | Some(TypeKind::Base(Encoding::UnsignedChar))
+                    if addr != 0 =>
+                {
+                    format!("{:#x} {}", addr, read_string(context, addr))
+                }
+                Some(TypeKind::Function) => match context.symbol_at(addr) {
+                    Some(name) => format!("{:#x} <{}>", addr, name),
+                    None => format!("{:#x}", addr),
+                },
+                _ => format!("{:#x}", addr),
+            }
+        }
+        TypeKind::Array(element, count) => {
+            let count = count.unwrap_or(0);
+            if is_char(element) {
+                return format_string(&bytes[..count.min(bytes.len())], count > MAX_ELEMENTS);
+            }
+            let elements: Vec<String> = (0..count.min(MAX_ELEMENTS))
+                .map(|i| {
+                    let start = i * element.size;
+                    format_value(context, element, &bytes[start..start + element.size])
+                })
+                .collect();
+            let mut text = collapse_repeats(elements).join(", ");
+            if count > MAX_ELEMENTS {
+                text.push_str("...");
+            }
+            format!("{{{}}}", text)
+        }
+        TypeKind::Struct(members) | TypeKind::Union(members) => {
+            let fields: Vec<String> = members
+                .iter()
+                .map(|member| {
+                    let value = match context.get_type(member.type_offset) {
+                        Some(member_type) => match bytes.get(member.offset..) {
+                            Some(member_bytes) => format_value(context, &member_type, member_bytes),
+                            None => "<unreadable>".to_string(),
+                        },
+                        None => "<unknown type>".to_string(),
+                    };
+                    format!("{} = {}", member.name, value)
+                })
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        TypeKind::Enum(enumerators) => {
+            let value = read_signed(bytes, entity_type.size);
+            match enumerators
+                .iter()
+                .find(|(_, enumerator)| *enumerator == value)
+            {
+                Some((name, _)) => name.clone(),
+                None => value.to_string(),
+            }
+        }
+        TypeKind::Alias(None) | TypeKind::Function => "<no value>".to_string(),
+        TypeKind::Alias(Some(_)) => unreachable!("aliases are stripped above"),
+    }
+}
+
+fn format_base(encoding: Encoding, size: usize, bytes: &[u8]) -> String {
+    match encoding {
+        Encoding::Bool => match bytes.first() {
+            Some(byte) => (*byte != 0).to_string(),
+            None => "<unreadable>".to_string(),
+        },
+        Encoding::Float if size == 4 => {
+            f32::from_le_bytes(bytes[..4].try_into().unwrap()).to_string()
+        }
+        Encoding::Float if size == 8 => {
+            f64::from_le_bytes(bytes[..8].try_into().unwrap()).to_string()
+        }
+        // x87 80-bit extended precision has no Rust counterpart
+        Encoding::Float => "<long double>".to_string(),
+        Encoding::Unsigned => read_unsigned(bytes, size).to_string(),
+        Encoding::Signed => read_signed(bytes, size).to_string(),
+        Encoding::UnsignedChar => match bytes.first() {
+            Some(byte) => format!("{} {}", byte, format_char(*byte)),
+            None => "<unreadable>".to_string(),
+        },
+        Encoding::SignedChar => match bytes.first() {
+            Some(byte) => format!("{} {}", *byte as i8, format_char(*byte)),
+            None => "<unreadable>".to_string(),
+        },
+    }
+}
+
+fn is_char(entity_type: &Type) -> bool {
+    matches!(
+        entity_type.strip_aliases().kind,
+        TypeKind::Base(Encoding::SignedChar) | TypeKind::Base(Encoding::UnsignedChar)
+    )
+}
+
+/// Reads the NUL-terminated string at `addr`, up to the print limit.
+pub fn read_string(context: &dyn Context, addr: usize) -> String {
+    match read_c_string(context, addr, MAX_ELEMENTS) {
+        Ok(bytes) => format_string(&bytes, bytes.len() == MAX_ELEMENTS),
+        Err(_) => format!("<error: Cannot access memory at address {:#x}>", addr),
+    }
+}
+
+/// Reads the bytes of the NUL-terminated string at `addr`, stopping after `max` of them. It is
+/// fetched a page at a time, since a string may end right before an unmapped page.
+pub fn read_c_string(context: &dyn Context, addr: usize, max: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < max {
+        // a garbage pointer can run off the top of the address space
+        let next = addr
+            .checked_add(bytes.len())
+            .ok_or_else(|| format!("Cannot access memory at address {:#x}", addr))?;
+        let len = (PAGE_SIZE - next % PAGE_SIZE).min(max - bytes.len());
+        let chunk = context.read_memory(next, len)?;
+        match chunk.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&chunk[..end]);
+                return Ok(bytes);
+            }
+            None => bytes.extend(chunk),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Quotes `bytes` as a C string, stopping at the first NUL like gdb does for char arrays.
+pub fn format_string(bytes: &[u8], truncated: bool) -> String {
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    format_bytes(
+        &bytes[..end.min(MAX_ELEMENTS)],
+        truncated || end > MAX_ELEMENTS,
+    )
+}
+
+/// Quotes every one of `bytes` as a C string, NULs included, followed by `...` if `truncated`.
+pub fn format_bytes(bytes: &[u8], truncated: bool) -> String {
+    let mut text = String::from("\"");
+    for byte in bytes {
+        match *byte {
+            b'"' => text.push_str("\\\""),
+            b'\'' => text.push('\''),
+            _ => {
+                let quoted = format_char(*byte);
+                text.push_str(&quoted[1..quoted.len() - 1]);
+            }
+        }
+    }
+    text.push('"');
+    if truncated {
+        text.push_str("...");
+    }
+    text
+}
+
+fn collapse_repeats(elements: Vec<String>) -> Vec<String> {
+    let mut collapsed = Vec::new();
+    let mut i = 0;
+    while i < elements.len() {
+        let run = elements[i..]
+            .iter()
+            .take_while(|element| **element == elements[i])
+            .count();
+        if run >= REPEAT_THRESHOLD {
+            collapsed.push(format!("{} <repeats {} times>", elements[i], run));
+            i += run;
+        } else {
+            collapsed.push(elements[i].clone());
+            i += 1;
+        }
+    }
+    collapsed
+}
+
+/// Renders a byte as a quoted C character literal.
+pub fn format_char(byte: u8) -> String {
+    match byte {
+        0 => "'\\0'".to_string(),
+        b'\n' => "'\\n'".to_string(),
+        b'\t' => "'\\t'".to_string(),
+        b'\r' => "'\\r'".to_string(),
+        b'\'' => "'\\''".to_string(),
+        b'\\' => "'\\\\'".to_string(),
+        0x20..=0x7e => format!("'{}'", byte as char),
+        _ => format!("'\\{:03o}'", byte),
+    }
+}
+
+pub fn read_unsigned(bytes: &[u8], size: usize) -> u64 {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().take(size.min(8)).enumerate() {
+        value |= (*byte as u64) << (8 * i);
+    }
+    value
+}
+
+pub fn read_signed(bytes: &[u8], size: usize) -> i64 {
+    let size = size.min(8);
+    let value = read_unsigned(bytes, size);
+    if size == 0 || size == 8 {
+        return value as i64;
+    }
+    // sign-extend from the top bit of the value
+    let shift = 64 - 8 * size;
+    ((value << shift) as i64) >> shift
+}
+
+/// A scalar read out of the inferior, used when evaluating expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+}
+
+/// Interprets the raw bytes of a base type, pointer or enum as a number.
+pub fn to_number(entity_type: &Type, bytes: &[u8]) -> Result<Number, String> {
+    let size = entity_type.size;
+    match &entity_type.strip_aliases().kind {
+        TypeKind::Base(Encoding::Float) if size == 4 && bytes.len() >= 4 => Ok(Number::Float(
+            f32::from_le_bytes(bytes[..4].try_into().unwrap()) as f64,
+        )),
+        TypeKind::Base(Encoding::Float) if size == 8 && bytes.len() >= 8 => Ok(Number::Float(
+            f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        )),
+        TypeKind::Base(Encoding::Float) => Err("long double is not supported".to_string()),
+        TypeKind::Base(Encoding::Signed)
+        | TypeKind::Base(Encoding::SignedChar)
+        | TypeKind::Enum(_) => Ok(Number::Int(read_signed(bytes, size))),
+        TypeKind::Base(_) | TypeKind::Pointer(_) => {
+            Ok(Number::Int(read_unsigned(bytes, size) as i64))
+        }
+        _ => Err(format!("{} is not a number", entity_type.name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dwarf_data::Member;
+    use crate::eval::tests::{int, Program, INT};
+
+    /// Maps `bytes` at `addr` and zeros after them to the end of the page, since strings are
+    /// read a page at a time.
+    fn map_to_page_end(context: &mut Program, addr: usize, bytes: &[u8]) {
+        let mut page = bytes.to_vec();
+        page.resize(PAGE_SIZE - addr % PAGE_SIZE, 0);
+        context.map(addr, &page);
+    }
+
+    fn array(element: Type, count: usize) -> Type {
+        Type::new(
+            format!("{} [{}]", element.name, count),
+            element.size * count,
+            TypeKind::Array(Box::new(element), Some(count)),
+        )
+    }
+
+    #[test]
+    fn formats_base_types() {
+        let context = Program::new();
+        let int = int();
+        assert_eq!(format_value(&context, &int, &(-5i32).to_le_bytes()), "-5");
+        let unsigned = Type::base("unsigned int", 4, Encoding::Unsigned);
+        assert_eq!(
+            format_value(&context, &unsigned, &(-5i32).to_le_bytes()),
+            "4294967291"
+        );
+        let double = Type::base("double", 8, Encoding::Float);
+        assert_eq!(
+            format_value(&context, &double, &1.5f64.to_le_bytes()),
+            "1.5"
+        );
+        let float = Type::base("float", 4, Encoding::Float);
+        assert_eq!(
+            format_value(&context, &float, &0.25f32.to_le_bytes()),
+            "0.25"
+        );
+        let char_type = Type::base("char", 1, Encoding::SignedChar);
+        assert_eq!(format_value(&context, &char_type, b"A"), "65 'A'");
+        assert_eq!(format_value(&context, &char_type, &[0xff]), "-1 '\\377'");
+        let unsigned_char = Type::base("unsigned char", 1, Encoding::UnsignedChar);
+        assert_eq!(
+            format_value(&context, &unsigned_char, &[0xff]),
+            "255 '\\377'"
+        );
+        let bool_type = Type::base("_Bool", 1, Encoding::Bool);
+        assert_eq!(format_value(&context, &bool_type, &[1]), "true");
+        let pointer = Type::pointer_to(Some(int.clone()));
+        assert_eq!(
+            format_value(&context, &pointer, &0x1000usize.to_le_bytes()),
+            "0x1000"
+        );
+        assert_eq!(format_value(&context, &int, &[1, 2]), "<unreadable>");
+    }
+
+    #[test]
+    fn zero_size_bool_and_char_are_unreadable() {
+        let context = Program::new();
+        for encoding in &[Encoding::Bool, Encoding::SignedChar, Encoding::UnsignedChar] {
+            let empty = Type::base("empty", 0, *encoding);
+            assert_eq!(format_value(&context, &empty, &[]), "<unreadable>");
+        }
+    }
+
+    #[test]
+    fn formats_arrays_structs_and_enums() {
+        let context = Program::new();
+        let mut bytes = Vec::new();
+        for value in &[1i32, 2, 3] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(
+            format_value(&context, &array(int(), 3), &bytes),
+            "{1, 2, 3}"
+        );
+        let zeros = vec![0; 4 * 12];
+        assert_eq!(
+            format_value(&context, &array(int(), 12), &zeros),
+            "{0 <repeats 12 times>}"
+        );
+        let char_type = Type::base("char", 1, Encoding::SignedChar);
+        assert_eq!(
+            format_value(&context, &array(char_type, 7), b"hi\0junk"),
+            "\"hi\""
+        );
+
+        let member = |name: &str, offset| Member {
+            name: name.to_string(),
+            type_offset: INT,
+            offset,
+        };
+        let point = Type::new(
+            "struct point".to_string(),
+            8,
+            TypeKind::Struct(vec![member("x", 0), member("y", 4)]),
+        );
+        let mut bytes = 7i32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(format_value(&context, &point, &bytes), "{x = 7, y = -1}");
+
+        let color = Type::new(
+            "enum color".to_string(),
+            4,
+            TypeKind::Enum(vec![("RED".to_string(), 0), ("BLUE".to_string(), 2)]),
+        );
+        assert_eq!(format_value(&context, &color, &2i32.to_le_bytes()), "BLUE");
+        assert_eq!(format_value(&context, &color, &5i32.to_le_bytes()), "5");
+    }
+
+    #[test]
+    fn follows_char_pointers() {
+        let mut context = Program::new();
+        map_to_page_end(&mut context, 0x5000, b"hello\0");
+        let char_pointer = Type::pointer_to(Some(Type::base("char", 1, Encoding::SignedChar)));
+        assert_eq!(
+            format_value(&context, &char_pointer, &0x5000usize.to_le_bytes()),
+            "0x5000 \"hello\""
+        );
+        assert_eq!(
+            format_value(&context, &char_pointer, &0x6000usize.to_le_bytes()),
+            "0x6000 <error: Cannot access memory at address 0x6000>"
+        );
+        assert_eq!(
+            format_value(&context, &char_pointer, &0usize.to_le_bytes()),
+            "0x0"
+        );
+    }
+
+    #[test]
+    fn reads_strings_up_to_an_unmapped_page() {
+        // the string runs right up to the end of the mapping, without a NUL
+        let end = 0x9000;
+        let mut context = Program::new();
+        context.map(end - 3, b"abc");
+        assert_eq!(
+            read_c_string(&context, end - 3, 10),
+            Err(format!("Cannot access memory at address {:#x}", end))
+        );
+        context.map(end - 1, b"\0");
+        assert_eq!(read_c_string(&context, end - 3, 10), Ok(b"ab".to_vec()));
+        assert_eq!(read_c_string(&context, end - 3, 1), Ok(b"a".to_vec()));
+    }
+
+    #[test]
+    fn strings_stop_at_the_top_of_memory() {
+        let top = usize::MAX - 1;
+        let mut context = Program::new();
+        context.map(top, b"ab");
+        assert_eq!(
+            read_c_string(&context, top, 10),
+            Err(format!("Cannot access memory at address {:#x}", top))
+        );
+    }
+
+    #[test]
+    fn collapses_long_runs_only() {
+        let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let mut elements = strings(&["1"; 9]);
+        elements.push("2".to_string());
+        assert_eq!(collapse_repeats(elements.clone()), elements);
+        let mut elements = strings(&["1"]);
+        elements.extend(strings(&["0"; 10]));
+        elements.push("1".to_string());
+        assert_eq!(
+            collapse_repeats(elements),
+            strings(&["1", "0 <repeats 10 times>", "1"])
+        );
+        assert!(collapse_repeats(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn quotes_strings() {
+        assert_eq!(format_string(b"a\"b'c\n", false), "\"a\\\"b'c\\n\"");
+        assert_eq!(format_string(b"ab\0cd", false), "\"ab\"");
+        assert_eq!(format_string(b"ab", true), "\"ab\"...");
+        let long = vec![b'x'; MAX_ELEMENTS + 1];
+        let quoted = format_string(&long, false);
+        assert!(quoted.ends_with("\"..."));
+        assert_eq!(quoted.len(), MAX_ELEMENTS + 5);
+    }
+
+    #[test]
+    fn quotes_binary_bytes() {
+        assert_eq!(format_bytes(b"a\0b\xff", false), "\"a\\0b\\377\"");
+        assert_eq!(format_bytes(b"", true), "\"\"...");
+    }
+
+    #[test]
+    fn quotes_chars() {
+        assert_eq!(format_char(b'a'), "'a'");
+        assert_eq!(format_char(0), "'\\0'");
+        assert_eq!(format_char(b'\n'), "'\\n'");
+        assert_eq!(format_char(b'\''), "'\\''");
+        assert_eq!(format_char(b'\\'), "'\\\\'");
+        assert_eq!(format_char(0x7f), "'\\177'");
+        assert_eq!(format_char(0xff), "'\\377'");
+    }
+
+    #[test]
+    fn sign_extends() {
+        assert_eq!(read_signed(&[0xff], 1), -1);
+        assert_eq!(read_signed(&[0x7f], 1), 127);
+        assert_eq!(read_signed(&[0x00, 0x80], 2), -32768);
+        assert_eq!(read_signed(&[0xfe, 0xff, 0xff, 0xff], 4), -2);
+        assert_eq!(read_signed(&[0xff; 8], 8), -1);
+        // only `size` bytes count
+        assert_eq!(read_signed(&[0x01, 0xff], 1), 1);
+        assert_eq!(read_signed(&[], 0), 0);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::dwarf_data::Type;
+
+/// x86-64 has four debug address registers (DR0-DR3), so at most four watchpoints can be armed.
+pub const MAX_HW_WATCH_POINTS: usize = 4;
+
+/// DR6, the debug status register. Bits 0-3 tell which of DR0-DR3 fired.
+pub const DR_STATUS: usize = 6;
+/// DR7, the debug control register.
+pub const DR_CONTROL: usize = 7;
+
+#[derive(Clone)]
+pub struct Watchpoint {
+    pub num: usize,
+    /// What the user typed, e.g. `counter` or `*0x404028`.
+    pub expr: String,
+    pub addr: usize,
+    pub entity_type: Type,
+    /// Stop on reads as well as writes (`awatch`), rather than on writes only (`watch`).
+    pub access: bool,
+    pub old_value: Vec<u8>,
+    /// Set when watching a local: the watchpoint goes away with the frame that owns it.
+    pub scope: Option<WatchScope>,
+    pub enabled: bool,
+    pub hit_count: usize,
+}
+
+#[derive(Clone)]
+pub struct WatchScope {
+    /// CFA (rbp + 16) of the frame the local lives in.
+    pub cfa: usize,
+    pub function: String,
+}
+
+impl Watchpoint {
+    pub fn size(&self) -> usize {
+        self.entity_type.size
+    }
+
+    pub fn kind(&self) -> &'static str {
+        if self.access {
+            "acc watchpoint"
+        } else {
+            "hw watchpoint"
+        }
+    }
+}
+
+/// Checks that the hardware can watch `len` bytes at `addr`: DR7 only encodes lengths of 1, 2,
+/// 4 and 8, and the address must be aligned to the length.
+pub fn check_watchable(addr: usize, len: usize) -> Result<(), String> {
+    match len {
+        1 | 2 | 4 | 8 if addr % len == 0 => Ok(()),
+        1 | 2 | 4 | 8 => Err(format!(
+            "{:#x} is not aligned to {} bytes, which hardware watchpoints require",
+            addr, len
+        )),
+        _ => Err(format!(
+            "hardware watchpoints can only watch 1, 2, 4 or 8 bytes, not {}",
+            len
+        )),
+    }
+}
+
+/// Returns the DR7 bits arming debug register `slot` for `len` bytes: the local enable bit, the
+/// R/W field (01 = break on writes, 11 = break on reads or writes) and the LEN field.
+pub fn dr7_bits(slot: usize, len: usize, access: bool) -> u64 {
+    let rw: u64 = if access { 0b11 } else { 0b01 };
+    let len_bits: u64 = match len {
+        1 => 0b00,
+        2 => 0b01,
+        8 => 0b10,
+        _ => 0b11,
+    };
+    (1 << (2 * slot)) | (rw << (16 + 4 * slot)) | (len_bits << (18 + 4 * slot))
+}
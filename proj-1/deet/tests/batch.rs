@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// A scratch directory for this test run, which also serves as HOME and the working directory,
+/// so no `.deetinit` gets sourced.
+fn scratch() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("deet-tests-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Builds `samples/<name>.c` the way the Makefile does, asking for DWARF 4 since newer
+/// compilers default to 5.
+fn build(name: &str) -> PathBuf {
+    let source = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("samples")
+        .join(format!("{}.c", name));
+    let program = scratch().join(name);
+    let status = Command::new("cc")
+        .args("-O0 -gdwarf-4 -no-pie -fno-omit-frame-pointer -o".split(' '))
+        .arg(&program)
+        .arg(&source)
+        .status()
+        .unwrap();
+    assert!(status.success(), "can't build {}", source.display());
+    program
+}
+
+/// Runs `deet -x <script> <program>` on a freshly built sample.
+fn deet(script: &str, sample: &str) -> Output {
+    let program = build(sample);
+    let path = scratch().join(format!("{}.deet", sample));
+    std::fs::write(&path, script).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_deet"))
+        .arg("-x")
+        .arg(&path)
+        .arg(&program)
+        .current_dir(scratch())
+        .env("HOME", scratch())
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn runs_a_script_to_the_end() {
+    let output = deet("break 6\nrun\nc\n", "count");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("count.c:6\n6\t    printf(\"3\\n\");\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("1\n2\n3\n4\n5\n"), "{}", stdout);
+    assert!(stdout.ends_with("Child Exited (Status 0)\n"), "{}", stdout);
+    // the debug info dump is only for the prompt
+    assert!(!stdout.contains("Line numbers:"), "{}", stdout);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn exits_with_the_status_of_the_program() {
+    let output = deet("break func2\nrun\nprint a\nc\nc\n", "segfault");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("a (int) = 2\n"), "{}", stdout);
+    assert!(
+        stdout.contains("Child Stopped (status SIGSEGV)\n"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.ends_with("Child Signal (Status SIGSEGV)\n"),
+        "{}",
+        stdout
+    );
+    // like a shell, 128 plus the signal that killed it
+    assert_eq!(output.status.code(), Some(128 + 11));
+}
+
+#[test]
+fn stops_at_a_bad_command() {
+    let output = deet("frobnicate\nrun\n", "exit");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Unrecognized command \"frobnicate\"."),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("Inferior run!"), "{}", stdout);
+}
+
+#[test]
+fn skips_a_nested_command_list() {
+    let script =
+        "break func2\ncommands\nprint a\ncommands\nprint b\nend\nprint global\nend\nrun\nc\n";
+    let output = deet(script, "function_calls");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Can't use \"commands\" inside a command list."),
+        "{}",
+        stdout
+    );
+    // the nested `end` doesn't end the outer list early
+    assert!(stdout.contains("a (int) = 42\n"), "{}", stdout);
+    assert!(stdout.contains("global (int) = 5\n"), "{}", stdout);
+    assert!(!stdout.contains("b (int)"), "{}", stdout);
+    assert!(stdout.ends_with("Child Exited (Status 0)\n"), "{}", stdout);
+}